@@ -3,7 +3,8 @@ use std::{future::Future, ops::DerefMut};
 use cyberpixie_network::{
     core::proto::types::{Hertz, ImageId, PeerInfo},
     tokio::{TokioSocket, TokioStack},
-    Client, NetworkSocket, NetworkStack, SocketAddr,
+    Client, NetworkSocket, NetworkStack, SocketAddr, DEFAULT_DEVICE_IP_ADDRESS,
+    DEFAULT_DEVICE_PORT,
 };
 use image::{
     imageops::{self, FilterType},
@@ -132,7 +133,7 @@ impl DeviceHandle {
             let mut stack = TokioStack;
             let mut socket = stack.socket();
             let inner = DeviceHandleInner {
-                address: SocketAddr::new([192, 168, 1, 1].into(), 1800),
+                address: SocketAddr::new(DEFAULT_DEVICE_IP_ADDRESS, DEFAULT_DEVICE_PORT),
                 socket: &mut socket,
             };
 
@@ -150,7 +151,7 @@ struct DeviceHandleInner<'a, S: NetworkSocket + 'a> {
 // impl Default for DeviceHandleInner {
 //     fn default() -> Self {
 //         Self {
-//             address: SocketAddr::new([192, 168, 71, 1].into(), 1800),
+//             address: SocketAddr::new(DEFAULT_DEVICE_IP_ADDRESS, DEFAULT_DEVICE_PORT),
 //         }
 //     }
 // }
@@ -15,14 +15,25 @@
 )]
 
 use cyberpixie_app::{
-    core::proto::types::{FirmwareInfo, ImageId},
-    network::{NetworkSocket, NetworkStack, SocketAddr},
+    core::{
+        io::AsyncRead,
+        proto::types::{
+            FirmwareInfo, Hertz, ImageId, Playback, BOARD_NAME_LEN, BUILD_ID_LEN,
+            FIRMWARE_VERSION_LEN,
+        },
+        MAX_STRIP_LEN,
+    },
+    network::{NetworkSocket, NetworkStack, PayloadReader, SocketAddr, UdpSocket},
     Board, Configuration, CyberpixieError, CyberpixieResult,
 };
 use cyberpixie_embedded_storage::MemoryLayout;
-use cyberpixie_network::FromSocketAddress;
-use embassy_net::{tcp::TcpSocket, IpListenEndpoint, Stack};
-use embassy_time::Duration;
+use cyberpixie_network::{FromSocketAddress, IntoSocketAddress};
+use embassy_net::{
+    tcp::TcpSocket,
+    udp::{self, PacketMetadata},
+    IpListenEndpoint, Stack,
+};
+use embassy_time::{Duration, Instant, Timer};
 #[cfg(feature = "esp32c3")]
 use esp32c3_hal as hal;
 #[cfg(feature = "esp32s3")]
@@ -42,6 +53,26 @@ pub const DEFAULT_MEMORY_LAYOUT: MemoryLayout = MemoryLayout {
     size: 0x0019_9000,
 };
 
+/// Max supported frame rate.
+pub const MAX_FRAME_RATE: Hertz = Hertz(500);
+
+/// Name of the board the firmware is built for.
+#[cfg(feature = "esp32c3")]
+const BOARD_NAME: &str = "esp32c3";
+#[cfg(feature = "esp32s3")]
+const BOARD_NAME: &str = "esp32s3";
+/// Firmware build identifier.
+const BUILD_ID: &str = match option_env!("CYBERPIXIE_BUILD_ID") {
+    Some(build_id) => build_id,
+    None => "unknown",
+};
+
+// Make sure that the firmware information fits into the protocol strings, otherwise
+// the conversion panics at runtime.
+const _: () = assert!(env!("CARGO_PKG_VERSION").len() <= FIRMWARE_VERSION_LEN);
+const _: () = assert!(BOARD_NAME.len() <= BOARD_NAME_LEN);
+const _: () = assert!(BUILD_ID.len() <= BUILD_ID_LEN);
+
 #[derive(Clone, Copy)]
 pub struct NetworkStackImpl {
     stack: &'static Stack<WifiDevice<'static>>,
@@ -50,12 +81,36 @@ pub struct NetworkStackImpl {
 pub struct NetworkSocketImpl {
     rx: [u8; 1024],
     tx: [u8; 1024],
+    rx_meta: [PacketMetadata; 4],
+    tx_meta: [PacketMetadata; 4],
     stack: &'static Stack<WifiDevice<'static>>,
 }
 
+pub struct UdpSocketImpl<'a>(udp::UdpSocket<'a>);
+
+impl UdpSocket for UdpSocketImpl<'_> {
+    async fn receive_from(&mut self, buf: &mut [u8]) -> CyberpixieResult<(usize, SocketAddr)> {
+        let (len, endpoint) = self
+            .0
+            .recv_from(buf)
+            .await
+            .map_err(CyberpixieError::network)?;
+        Ok((len, endpoint.into_socket_address()))
+    }
+
+    async fn send_to(&mut self, buf: &[u8], address: SocketAddr) -> CyberpixieResult<()> {
+        let (addr, port) = FromSocketAddress::from_socket_address(address);
+        self.0
+            .send_to(buf, (addr, port))
+            .await
+            .map_err(CyberpixieError::network)
+    }
+}
+
 impl NetworkSocket for NetworkSocketImpl {
     type ConnectionError = embassy_net::tcp::Error;
     type Connection<'a> = TcpSocket<'a>;
+    type Udp<'a> = UdpSocketImpl<'a> where Self: 'a;
 
     async fn accept(&mut self, port: u16) -> CyberpixieResult<Self::Connection<'_>> {
         let mut socket = TcpSocket::new(self.stack, &mut self.rx, &mut self.tx);
@@ -79,6 +134,19 @@ impl NetworkSocket for NetworkSocketImpl {
             .map_err(CyberpixieError::network)?;
         Ok(socket)
     }
+
+    async fn bind(&mut self, port: u16) -> CyberpixieResult<Self::Udp<'_>> {
+        let mut socket = udp::UdpSocket::new(
+            self.stack,
+            &mut self.rx_meta,
+            &mut self.rx,
+            &mut self.tx_meta,
+            &mut self.tx,
+        );
+
+        socket.bind(port).map_err(CyberpixieError::network)?;
+        Ok(UdpSocketImpl(socket))
+    }
 }
 
 impl NetworkStackImpl {
@@ -95,6 +163,8 @@ impl NetworkStack for NetworkStackImpl {
         NetworkSocketImpl {
             rx: [0_u8; 1024],
             tx: [0_u8; 1024],
+            rx_meta: [PacketMetadata::EMPTY; 4],
+            tx_meta: [PacketMetadata::EMPTY; 4],
             stack: self.stack,
         }
     }
@@ -105,14 +175,17 @@ pub struct BoardImpl {
     network: Option<NetworkStackImpl>,
     storage: Option<StorageImpl>,
     rendering_handle: RenderingHandle,
+    random_seed: u64,
 }
 
 impl BoardImpl {
+    /// Creates a new board with the random seed taken from the hardware random number generator.
     pub fn new(
         stack: &'static Stack<WifiDevice<'static>>,
         rendering_handle: RenderingHandle,
+        random_seed: u64,
     ) -> Self {
-        let storage = StorageImpl::init(
+        let storage = StorageImpl::open_or_init(
             Configuration::default(),
             FlashStorage::new(),
             DEFAULT_MEMORY_LAYOUT,
@@ -124,6 +197,7 @@ impl BoardImpl {
             network: Some(NetworkStackImpl::new(stack)),
             storage: Some(storage),
             rendering_handle,
+            random_seed,
         }
     }
 }
@@ -143,8 +217,11 @@ impl Board for BoardImpl {
         &mut self,
         storage: Self::Storage,
         image_id: ImageId,
+        playback: Playback,
     ) -> cyberpixie_app::CyberpixieResult<Self::RenderTask> {
-        self.rendering_handle.start(storage, image_id).await;
+        self.rendering_handle
+            .start(storage, image_id, playback)
+            .await;
         Ok(self.rendering_handle.clone())
     }
 
@@ -156,7 +233,41 @@ impl Board for BoardImpl {
     }
 
     fn firmware_info(&self) -> FirmwareInfo {
-        FirmwareInfo
+        FirmwareInfo {
+            version: env!("CARGO_PKG_VERSION").into(),
+            board_name: BOARD_NAME.into(),
+            build_id: BUILD_ID.into(),
+            max_strip_len: MAX_STRIP_LEN as u16,
+            max_refresh_rate: MAX_FRAME_RATE,
+            storage_capacity: DEFAULT_MEMORY_LAYOUT.size,
+        }
+    }
+
+    fn now(&self) -> core::time::Duration {
+        core::time::Duration::from_micros(Instant::now().as_micros())
+    }
+
+    async fn sleep_until(&self, time: core::time::Duration) {
+        Timer::at(Instant::from_micros(time.as_micros() as u64)).await;
+    }
+
+    fn random_seed(&self) -> u64 {
+        self.random_seed
+    }
+
+    async fn stream_lines<R: AsyncRead>(
+        &mut self,
+        config: Configuration,
+        refresh_rate: Hertz,
+        lines: PayloadReader<R>,
+    ) -> cyberpixie_app::CyberpixieResult<()> {
+        self.rendering_handle
+            .stream_lines(config, refresh_rate, lines)
+            .await
+    }
+
+    async fn clear_strip(&mut self) {
+        self.rendering_handle.clear().await;
     }
 }
 
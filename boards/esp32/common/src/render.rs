@@ -4,12 +4,14 @@
 
 use cyberpixie_app::{
     core::{
-        io::image_reader::ImageLines,
-        proto::types::{Hertz, ImageId},
-        MAX_STRIP_LEN,
+        color::{ColorCorrection, RGBW8},
+        io::{image_reader::AsyncImageLines, AsyncRead, ExactSizeRead},
+        proto::types::{Hertz, ImageId, PixelFormat, Playback},
+        BYTES_PER_PIXEL, MAX_BYTES_PER_PIXEL, MAX_STRIP_LEN,
     },
-    Storage,
+    Configuration, CyberpixieError, CyberpixieResult, Storage,
 };
+use cyberpixie_network::PayloadReader;
 use embassy_executor::Spawner;
 use embassy_sync::{
     blocking_mutex::raw::CriticalSectionRawMutex,
@@ -23,64 +25,138 @@ use crate::{singleton, StorageImpl};
 
 /// Pending frames queue length.
 pub const QUEUE_LEN: usize = 8;
+/// The maximum number of RGB pixels sent to the strip.
+///
+/// RGBW pixels are sent as a byte stream packed into RGB pixels, so they take more room.
+const WIRE_PIXELS: usize = MAX_STRIP_LEN * MAX_BYTES_PER_PIXEL / BYTES_PER_PIXEL;
 /// ws2812-async DMA buffer size.
-const LED_BUF_LEN: usize = 12 * MAX_STRIP_LEN;
+const LED_BUF_LEN: usize = 12 * WIRE_PIXELS;
 
 pub type RGB8Line = heapless::Vec<RGB8, MAX_STRIP_LEN>;
+pub type RGBW8Line = heapless::Vec<RGBW8, MAX_STRIP_LEN>;
 pub type StaticSender<T, const N: usize> = Sender<'static, CriticalSectionRawMutex, T, N>;
 pub type StaticReceiver<T, const N: usize> = Receiver<'static, CriticalSectionRawMutex, T, N>;
 
 enum Command {
-    Start { storage: StorageImpl, id: ImageId },
+    Start {
+        storage: StorageImpl,
+        id: ImageId,
+        playback: Playback,
+    },
     Stop,
 }
 
+/// Strip line pixels.
+pub enum Line {
+    /// Line of the RGB strip.
+    Rgb(RGB8Line),
+    /// Line of the RGBW strip.
+    Rgbw(RGBW8Line),
+}
+
 /// Next frame
 pub enum Frame {
     /// Change refresh frame rate.
     UpdateRate(Hertz),
     /// Next line.
-    Line(RGB8Line),
+    Line(Line),
     /// Cleanup the strip.
     Clear,
 }
 
+/// Reads the image lines and sends them to the rendering task until the `Stop` command is received.
+async fn render_image(
+    storage: &mut StorageImpl,
+    id: ImageId,
+    playback: Playback,
+    commands: &StaticReceiver<Command, 1>,
+    framebuffer: &StaticSender<Frame, QUEUE_LEN>,
+) -> CyberpixieResult<()> {
+    let config = storage.config()?;
+    let strip_len = config.strip_len;
+    // Color channels reordering and brightness and gamma correction are applied
+    // to the each line before sending.
+    let color_order = config.color_order;
+    let correction = ColorCorrection::from(&config);
+
+    // Image bytes are read asynchronously, so the flash reads don't stall the
+    // network tasks.
+    let mut reader = AsyncImageLines::new(
+        storage.read_image_async(id).await?,
+        strip_len,
+        [0_u8; MAX_STRIP_LEN * MAX_BYTES_PER_PIXEL],
+    )?
+    .with_playback(playback)?;
+    let rate = reader.refresh_rate();
+    log::info!(
+        "Starting a new picture rendering task id: {}, rate: {}Hz",
+        id,
+        rate
+    );
+    // Start an endless loop of reading and sending frames to the rendering task,
+    // which can be stopped by the `Stop` command.
+    framebuffer.send(Frame::UpdateRate(rate)).await;
+    loop {
+        // Keep the strip blank after the last image repetition.
+        if reader.is_finished() {
+            log::info!("Picture playback finished");
+            framebuffer.send(Frame::Clear).await;
+            wait_for_stop(commands).await;
+            return Ok(());
+        }
+
+        let line = match config.pixel_format {
+            PixelFormat::Rgb => Line::Rgb(
+                reader
+                    .next_line()
+                    .await
+                    .map_err(CyberpixieError::storage_read)?
+                    .map(|pixel| correction.pixel(color_order.reorder(pixel)))
+                    .collect(),
+            ),
+            PixelFormat::Rgbw => Line::Rgbw(
+                reader
+                    .next_rgbw_line()
+                    .await
+                    .map_err(CyberpixieError::storage_read)?
+                    .map(|pixel| correction.rgbw_pixel(color_order.reorder_rgbw(pixel)))
+                    .collect(),
+            ),
+        };
+        // Send line to the rendering thread.
+        framebuffer.send(Frame::Line(line)).await;
+        // Check if a stop command has been sent.
+        if let Ok(Command::Stop) = commands.try_receive() {
+            // Stop this rendering task
+            log::info!("Stopping a rendering task");
+            return Ok(());
+        }
+    }
+}
+
+/// Waits until the application stops the current rendering task.
+async fn wait_for_stop(commands: &StaticReceiver<Command, 1>) {
+    while !matches!(commands.receive().await, Command::Stop) {}
+}
+
 #[embassy_executor::task]
 async fn storage_reading_task(
     commands: StaticReceiver<Command, 1>,
     responses: StaticSender<StorageImpl, 1>,
     framebuffer: StaticSender<Frame, QUEUE_LEN>,
 ) {
-    let mut pending: Option<(StorageImpl, ImageId)> = None;
+    let mut pending: Option<(StorageImpl, ImageId, Playback)> = None;
     loop {
-        if let Some((mut storage, id)) = pending.take() {
+        if let Some((mut storage, id, playback)) = pending.take() {
             // There is a received picture rendering task.
-            let strip_len = storage.config().unwrap().strip_len;
-
-            let mut reader = ImageLines::new(
-                storage.read_image(id).unwrap(),
-                strip_len,
-                [0_u8; MAX_STRIP_LEN * 3],
-            );
-            let rate = reader.refresh_rate();
-            log::info!(
-                "Starting a new picture rendering task id: {}, rate: {}Hz",
-                id,
-                rate
-            );
-            // Start an endless loop of reading and sending frames to the rendering task,
-            // which can be stopped by the `Stop` command.
-            framebuffer.send(Frame::UpdateRate(rate)).await;
-            loop {
-                let line: RGB8Line = reader.next_line().unwrap().collect();
-                // Send line to the rendering thread.
-                framebuffer.send(Frame::Line(line)).await;
-                // Check if a stop command has been sent.
-                if let Ok(Command::Stop) = commands.try_receive() {
-                    // Stop this rendering task
-                    log::info!("Stopping a rendering task");
-                    break;
-                }
+            if let Err(err) =
+                render_image(&mut storage, id, playback, &commands, &framebuffer).await
+            {
+                // A broken image should not crash the whole firmware, so we just leave
+                // the strip cleared until the application stops this task.
+                log::error!("Unable to render a picture id: {id}, error: {err}");
+                framebuffer.send(Frame::Clear).await;
+                wait_for_stop(&commands).await;
             }
             // Cleanup strip.
             framebuffer.send(Frame::Clear).await;
@@ -90,25 +166,74 @@ async fn storage_reading_task(
             log::info!("Rendering task stopped");
         } else {
             // Waiting for a new rendering task.
-            let Command::Start { storage, id } = commands.receive().await else {
+            let Command::Start {
+                storage,
+                id,
+                playback,
+            } = commands.receive().await
+            else {
                 continue;
             };
-            pending.replace((storage, id));
+            pending.replace((storage, id, playback));
             log::info!("Received a new picture rendering task");
         }
     }
 }
 
+/// Reads the streamed lines and sends them to the rendering task as soon as they are received.
+async fn send_streamed_lines<R: AsyncRead>(
+    config: Configuration,
+    lines: &mut PayloadReader<R>,
+    framebuffer: &StaticSender<Frame, QUEUE_LEN>,
+) -> CyberpixieResult<()> {
+    let color_order = config.color_order;
+    let correction = ColorCorrection::from(&config);
+
+    let mut buf = [0_u8; MAX_STRIP_LEN * MAX_BYTES_PER_PIXEL];
+    let buf = &mut buf[0..usize::from(config.strip_len) * config.pixel_format.bytes_per_pixel()];
+    while lines.bytes_remaining() != 0 {
+        lines
+            .read_exact(buf)
+            .await
+            .map_err(CyberpixieError::network)?;
+
+        let line = match config.pixel_format {
+            PixelFormat::Rgb => Line::Rgb(
+                buf.chunks_exact(BYTES_PER_PIXEL)
+                    .map(|p| correction.pixel(color_order.reorder(RGB8::new(p[0], p[1], p[2]))))
+                    .collect(),
+            ),
+            PixelFormat::Rgbw => Line::Rgbw(
+                buf.chunks_exact(PixelFormat::Rgbw.bytes_per_pixel())
+                    .map(|p| {
+                        let pixel = RGBW8::new(p[0], p[1], p[2], p[3]);
+                        correction.rgbw_pixel(color_order.reorder_rgbw(pixel))
+                    })
+                    .collect(),
+            ),
+        };
+        framebuffer.send(Frame::Line(line)).await;
+    }
+    Ok(())
+}
+
 /// Pictures rendering handle to control the rendering process.
 #[derive(Clone, Copy)]
 pub struct RenderingHandle {
     commands: StaticSender<Command, 1>,
     responses: StaticReceiver<StorageImpl, 1>,
+    framebuffer: StaticSender<Frame, QUEUE_LEN>,
 }
 
 impl RenderingHandle {
-    pub async fn start(&self, storage: StorageImpl, id: ImageId) {
-        self.commands.send(Command::Start { storage, id }).await;
+    pub async fn start(&self, storage: StorageImpl, id: ImageId, playback: Playback) {
+        self.commands
+            .send(Command::Start {
+                storage,
+                id,
+                playback,
+            })
+            .await;
     }
 
     pub async fn stop(&self) -> StorageImpl {
@@ -117,6 +242,26 @@ impl RenderingHandle {
         // Wait for the response with the storage.
         self.responses.receive().await
     }
+
+    /// Shows the streamed lines, the picture rendering task should be stopped before.
+    pub async fn stream_lines<R: AsyncRead>(
+        &self,
+        config: Configuration,
+        rate: Hertz,
+        mut lines: PayloadReader<R>,
+    ) -> CyberpixieResult<()> {
+        log::info!("Starting a lines streaming with rate: {rate}Hz");
+        self.framebuffer.send(Frame::UpdateRate(rate)).await;
+        let result = send_streamed_lines(config, &mut lines, &self.framebuffer).await;
+        // Cleanup strip after the last line.
+        self.framebuffer.send(Frame::Clear).await;
+        result
+    }
+
+    /// Clears the strip, the picture rendering task should be stopped before.
+    pub async fn clear(&self) {
+        self.framebuffer.send(Frame::Clear).await;
+    }
 }
 
 /// Creates a pictures render tasks set.
@@ -136,17 +281,37 @@ pub fn must_spawn(spawner: Spawner) -> (StaticReceiver<Frame, QUEUE_LEN>, Render
         RenderingHandle {
             commands: commands.sender(),
             responses: responses.receiver(),
+            framebuffer: framebuffer.sender(),
         },
     )
 }
 
+/// Packs RGBW pixels into the RGB pixels sent by the ws2812 driver.
+///
+/// The driver sends the RGB pixel bytes in the GRB order, so the color channels are packed
+/// in the same order as the RGB pixels, and the white channel follows them. Thus the strip
+/// receives the color channels in the configured order, e.g. the `Grb` order of the SK6812
+/// RGBW strips.
+fn rgbw_wire_pixels(line: &[RGBW8]) -> impl Iterator<Item = RGB8> + '_ {
+    let mut bytes = line
+        .iter()
+        .flat_map(|pixel| [pixel.g, pixel.r, pixel.b, pixel.w]);
+    core::iter::from_fn(move || {
+        let g = bytes.next()?;
+        // The last incomplete pixel is padded by zeros.
+        let r = bytes.next().unwrap_or_default();
+        let b = bytes.next().unwrap_or_default();
+        Some(RGB8 { r, g, b })
+    })
+}
+
 /// Generic ws2812 async render based on the SPI DMA.
 pub async fn ws2812_async_render<S: SpiBus>(
     mut ws: ws2812_async::Ws2812<S, LED_BUF_LEN>,
     receiver: StaticReceiver<Frame, QUEUE_LEN>,
 ) {
     // Initialize and cleanup a LEN strip.
-    ws.write(core::iter::repeat(RGB8::default()).take(MAX_STRIP_LEN))
+    ws.write(core::iter::repeat(RGB8::default()).take(WIRE_PIXELS))
         .await
         .unwrap();
 
@@ -170,7 +335,10 @@ pub async fn ws2812_async_render<S: SpiBus>(
             }
 
             Frame::Line(line) => {
-                ws.write(line.into_iter()).await.unwrap();
+                match line {
+                    Line::Rgb(line) => ws.write(line.into_iter()).await.unwrap(),
+                    Line::Rgbw(line) => ws.write(rgbw_wire_pixels(&line)).await.unwrap(),
+                }
                 let elapsed = now.elapsed();
 
                 total_render_time += elapsed.as_micros();
@@ -185,7 +353,7 @@ pub async fn ws2812_async_render<S: SpiBus>(
             }
 
             Frame::Clear => {
-                ws.write(core::iter::repeat(RGB8::default()).take(MAX_STRIP_LEN))
+                ws.write(core::iter::repeat(RGB8::default()).take(WIRE_PIXELS))
                     .await
                     .unwrap();
                 // Reset rendering stats.
@@ -1,5 +1,6 @@
 //! Wifi network tasks set.
 
+use cyberpixie_app::core::proto::types::MAX_SECONDARIES;
 use embassy_executor::Spawner;
 use embassy_net::{IpAddress, Ipv4Cidr, Stack};
 use embassy_time::{Duration, Timer};
@@ -12,6 +13,13 @@ use esp_wifi::{
 
 use crate::{hal::peripheral::Peripheral, singleton};
 
+/// Number of the sockets reserved for the secondary devices connections.
+const SECONDARY_SOCKETS: usize = if cfg!(feature = "secondaries") {
+    MAX_SECONDARIES
+} else {
+    0
+};
+
 /// Supported Wifi configuration modes.
 #[derive(PartialEq, Eq, Clone)]
 pub enum Mode {
@@ -110,7 +118,8 @@ impl WifiManager {
         let stack = singleton!(Stack::new(
             device,
             mode.network_config(),
-            singleton!(embassy_net::StackResources::<3>::new()),
+            // Sockets of the client, secondary devices, DMX input and discovery requests.
+            singleton!(embassy_net::StackResources::<{ 4 + SECONDARY_SOCKETS }>::new()),
             seed
         ));
 
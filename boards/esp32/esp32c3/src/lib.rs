@@ -4,14 +4,14 @@
 #![no_std]
 #![feature(async_fn_in_trait, type_alias_impl_trait)]
 
-use cyberpixie_app::{core::proto::types::Hertz, App};
+use cyberpixie_app::{network::discovery::DEFAULT_DISCOVERY_PORT, App};
 use cyberpixie_esp_common::{
     render::{Frame, RenderingHandle, StaticReceiver, QUEUE_LEN},
     singleton,
     wifi::WifiDevice,
 };
 pub use cyberpixie_esp_common::{
-    BoardImpl, NetworkSocketImpl, NetworkStackImpl, DEFAULT_MEMORY_LAYOUT,
+    BoardImpl, NetworkSocketImpl, NetworkStackImpl, DEFAULT_MEMORY_LAYOUT, MAX_FRAME_RATE,
 };
 use embassy_net::Stack;
 use embassy_time::{Duration, Timer};
@@ -27,9 +27,6 @@ use hal::{
 };
 use ws2812_async::Ws2812;
 
-/// Max supported frame rate.
-pub const MAX_FRAME_RATE: Hertz = Hertz(500);
-
 /// Initializes SPI for the ws2812 async driver on the pin 7.
 pub fn ws2812_spi(
     spi: SPI2,
@@ -91,6 +88,7 @@ pub async fn render_task(
 pub async fn app_task(
     stack: &'static Stack<WifiDevice<'static>>,
     rendering_handle: RenderingHandle,
+    random_seed: u64,
 ) {
     loop {
         if stack.is_link_up() {
@@ -101,7 +99,15 @@ pub async fn app_task(
 
     log::info!("Network config is {:?}", stack.config_v4());
 
-    let board = BoardImpl::new(stack, rendering_handle);
-    let app = App::new(board).expect("Unable to create a cyberpixie application");
+    let board = BoardImpl::new(stack, rendering_handle, random_seed);
+    // The application connects to the main device from the stored configuration if it is set,
+    // so the same firmware runs both the main and the secondary devices.
+    let app = App::new(board)
+        .expect("Unable to create a cyberpixie application")
+        .with_dmx_input(NetworkStackImpl::new(stack))
+        .with_discovery(NetworkStackImpl::new(stack), DEFAULT_DISCOVERY_PORT);
+    // Sockets for the secondary devices are reserved only if the feature is enabled.
+    #[cfg(feature = "secondaries")]
+    let app = app.with_secondary_port(cyberpixie_app::DEFAULT_SECONDARY_PORT);
     app.run().await.expect("Application execution failed");
 }
@@ -48,11 +48,14 @@ fn main() -> ! {
     let timer = timer_group1.timer0;
     let (wifi, _bluetooth) = peripherals.RADIO.split();
 
+    // Seed of the authentication challenges, which makes them differ after every restart.
+    let mut rng = Rng::new(peripherals.RNG);
+    let random_seed = u64::from(rng.random()) << 32 | u64::from(rng.random());
     let wifi_manager = WifiManager::new(
         cyberpixie_esp_common::wifi::Mode::default(),
         wifi,
         timer,
-        Rng::new(peripherals.RNG),
+        rng,
         system.radio_clock_control,
         &clocks,
     );
@@ -76,7 +79,7 @@ fn main() -> ! {
         let (framebuffer, rendering_handle) = cyberpixie_esp_common::render::must_spawn(spawner);
         let stack = wifi_manager.must_spawn(spawner);
 
-        spawner.must_spawn(app_task(stack, rendering_handle));
+        spawner.must_spawn(app_task(stack, rendering_handle, random_seed));
         spawner.must_spawn(cyberpixie_esp32s3::render_task(spi, framebuffer));
     })
 }
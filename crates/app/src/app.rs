@@ -1,21 +1,63 @@
 //! Cybeprixie application business-logic implementation
 
+use core::{
+    future::{poll_fn, Future},
+    pin::{pin, Pin},
+    task::Poll,
+    time::Duration,
+};
+
 use cyberpixie_core::{
-    io::{AsyncRead, ExactSizeRead},
+    io::{
+        image_reader::check_playback, rle, AsyncRead, AsyncWrite, BlockingRead, ErrorType,
+        ExactSizeRead,
+    },
     proto::{
-        types::{DeviceInfo, DeviceRole, ImageInfo, PeerInfo},
-        RequestHeader, ResponseHeader,
+        packet::{EncodeLE, PackedSize},
+        types::{
+            AuthChallenge, AuthKey, Capabilities, DeviceInfo, DeviceRole, DeviceTime,
+            DiscoveryReply, EntryLength, Hertz, ImageEncoding, ImageId, ImageInfo, ImageMetadata,
+            ImageName, MainDevice, PeerInfo, PixelFormat, Playback, PlaybackDirection, Playlist,
+            PlaylistId, PlaylistPosition, Playlists, SecondaryResult, SecondaryResults,
+            MAX_SECONDARIES,
+        },
+        RequestHeader, ResponseHeader, PROTOCOL_VERSION,
     },
-    BYTES_PER_PIXEL,
+    MAX_IMAGES_COUNT,
+};
+use cyberpixie_network::{
+    auth, discovery,
+    dmx::{DmxInput, DmxLines},
+    Client, ClockOffset, Connection, Message, NetworkSocket, NetworkStack, PayloadReader,
+    SocketAddr,
 };
-use cyberpixie_network::{Connection, Message, NetworkSocket, NetworkStack};
 
 use super::{Board, DEFAULT_CLIENT_PORT};
-use crate::{CyberpixieError, CyberpixieResult, Storage};
+use crate::{Configuration, CyberpixieError, CyberpixieResult, Storage};
+
+/// Delay before the next attempt to connect to the main device.
+const SECONDARY_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// Delay before the image starts on the main device and its secondary devices, which is
+/// enough to handle the request by this device.
+const SCHEDULED_START_DELAY: Duration = Duration::from_millis(200);
+/// Additional start delay for each secondary device, which is enough to forward the request
+/// to it.
+const SECONDARY_START_DELAY: Duration = Duration::from_millis(100);
+/// Time limit of the request forwarded to all secondary devices together, so the unresponsive
+/// secondary devices delay the response to the client no longer than that.
+const SECONDARIES_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Capacity of the secondary devices list, which is empty unless their connections are
+/// accepted with the `secondaries` feature.
+const SECONDARIES_CAPACITY: usize = if cfg!(feature = "secondaries") {
+    MAX_SECONDARIES
+} else {
+    0
+};
 
 /// Cyberpixie application runner.
 pub struct App<B: Board> {
     port: u16,
+    secondary_port: Option<u16>,
     network: B::NetworkStack,
     inner: AppInner<B>,
 }
@@ -33,47 +75,263 @@ impl<B: Board> App<B> {
             .expect("Board components has been already taken");
 
         let device_info = crate::read_device_info(&mut storage)?;
+        let images = (0..device_info.images_count.0)
+            .map(|image_id| cached_image(&mut storage, ImageId(image_id)))
+            .collect::<CyberpixieResult<_>>()?;
+        let config = storage.config()?;
+        let playlists = storage.playlists()?;
+        let auth_key = storage.auth_key()?;
+        let challenge_seed = board.random_seed();
         Ok(Self {
             network,
             port,
+            secondary_port: None,
             inner: AppInner {
                 board,
                 storage: Some(storage),
                 render: None,
                 device_info,
+                images,
+                playlists,
+                playlist: None,
+                scheduled: None,
+                dmx: None,
+                dmx_port: None,
+                discovery: None,
+                auth_key,
+                challenges: 0,
+                challenge_seed,
+                secondary_results: SecondaryResults::new(),
+                main_device: config.main_device,
+                config,
             },
         })
     }
 
+    /// Sets up the DMX input, which lines are shown while the device is idle if the input
+    /// is enabled in the configuration.
+    ///
+    /// The given network stack is used to receive the DMX packets, so they can be received
+    /// while the application waits for the client requests. The protocol and the universe
+    /// are taken from the configuration.
+    #[must_use]
+    pub fn with_dmx_input(mut self, stack: B::NetworkStack) -> Self {
+        self.inner.dmx = Some(stack);
+        self
+    }
+
+    /// Receives the DMX packets on the given UDP port instead of the standard port
+    /// of the configured protocol.
+    #[must_use]
+    pub fn with_dmx_port(mut self, port: u16) -> Self {
+        self.inner.dmx_port = Some(port);
+        self
+    }
+
+    /// Answers the discovery requests received on the given UDP port.
+    ///
+    /// The given network stack is used to receive the requests, so they can be answered
+    /// while the application waits for the client requests.
+    #[must_use]
+    pub fn with_discovery(mut self, stack: B::NetworkStack, port: u16) -> Self {
+        self.inner.discovery = Some(DiscoverySettings {
+            stack,
+            port,
+            client_port: self.port,
+        });
+        self
+    }
+
+    /// Accepts connections of the secondary devices on the given port if they are enabled
+    /// in the configuration.
+    ///
+    /// Requests that add, delete, show, hide and clear images and the strip length changes are
+    /// forwarded to the connected secondary devices after they have been handled by this device.
+    /// If this device has a key, the secondary devices have to authenticate with it as well.
+    #[cfg(feature = "secondaries")]
+    #[must_use]
+    pub fn with_secondary_port(mut self, port: u16) -> Self {
+        self.secondary_port = Some(port);
+        self
+    }
+
+    /// Runs this application as a secondary device of the main device with the given address
+    /// instead of the one from the configuration.
+    ///
+    /// Instead of accepting client connections, the application connects to the main device
+    /// and handles its requests. The connection is established again when it drops.
+    #[must_use]
+    pub fn with_main_address(mut self, address: SocketAddr, group_id: Option<u32>) -> Self {
+        self.inner.main_device = Some(MainDevice::new(address, group_id));
+        self
+    }
+
     /// Runs a Cyberpixie application event loop.
     pub async fn run(mut self) -> CyberpixieResult<()> {
+        // Start showing the current image before accepting clients.
+        if let Err(err) = self.inner.autoplay().await {
+            log::warn!("Unable to autoplay the current image: {err}");
+        }
+
         loop {
-            if let Err(_err) = self.run_client_requests_handler().await {
+            let Some(main_device) = self.inner.main_device else {
+                self.run_with_secondaries().await;
+                continue;
+            };
+
+            if let Err(err) = self.run_secondary(main_device.address()).await {
+                log::info!("Closed connection with the main device: {err}");
+            }
+            // The client is able to change the configuration until the next attempt,
+            // otherwise the device with the wrong main address would be unreachable.
+            let now = self.inner.board.now();
+            if let Err(_err) = self
+                .accept_client_until(now + SECONDARY_RECONNECT_DELAY)
+                .await
+            {
                 log::info!("Closed connection with client");
             }
         }
     }
 
-    async fn run_client_requests_handler(&mut self) -> CyberpixieResult<()> {
-        let mut client_socket = self.network.socket();
-        // Wait for a new incoming Client connection
-        let mut peer = Connection::incoming(client_socket.accept(self.port).await?);
-        // Run client requests handler.
-        loop {
-            let mut request = peer.receive_request().await?;
-            let response = self
+    /// Handles a single client connection accepted before the given board time.
+    async fn accept_client_until(&mut self, deadline: Duration) -> CyberpixieResult<()> {
+        let mut socket = self.network.socket();
+        let connection = {
+            let client = pin!(socket.accept(self.port));
+            let sleep = pin!(self.inner.board.sleep_until(deadline));
+            match select(client, sleep).await {
+                Either::Left(connection) => connection?,
+                Either::Right(()) => return Ok(()),
+            }
+        };
+        let peer = Connection::incoming(connection);
+        self.inner.handle_without_secondaries(peer, false).await
+    }
+
+    /// Connects to the main device and handles its requests until the connection is closed.
+    async fn run_secondary(&mut self, address: SocketAddr) -> CyberpixieResult<()> {
+        let mut socket = self.network.socket();
+        let connection = self.inner.run_until(socket.connect(address)).await?;
+        let mut peer = Connection::incoming(connection);
+        // Unlike the client, the secondary device sends the handshake first.
+        peer.send_message(RequestHeader::Handshake(self.inner.peer_info()))
+            .await?;
+        let mut response = peer
+            .receive_response()
+            .await
+            .map_err(CyberpixieError::in_handshake)?
+            .header;
+        // Main device with a key requires the secondary devices to know it.
+        if let ResponseHeader::AuthChallenge(challenge) = response {
+            let key = self
                 .inner
-                .handle_client_request(&mut request)
+                .auth_key
+                .clone()
+                .ok_or(CyberpixieError::AuthenticationRequired)?;
+            peer.send_message(RequestHeader::Authenticate(auth::sign(&key, &challenge)))
+                .await?;
+            response = peer
+                .receive_response()
                 .await
-                .unwrap_or_else(ResponseHeader::Error);
+                .map_err(CyberpixieError::in_handshake)?
+                .header;
+        }
+        let main_info = response.handshake()?;
+        if !main_info.is_compatible() {
+            log::warn!(
+                "Main device uses an unsupported protocol version {}",
+                main_info.version
+            );
+            return Err(CyberpixieError::UnsupportedProtocolVersion);
+        }
 
-            // It the payload has not been read by the handler, we must read it anyway
-            // in order to avoid malformed socked state.
-            if let Some(payload) = request.payload.take() {
-                payload.skip().await.map_err(CyberpixieError::network)?;
+        log::info!("Connected to the main device {address}");
+        // Secondary device trusts the main device it has connected to.
+        self.inner.handle_without_secondaries(peer, true).await
+    }
+
+    /// Returns the port of the secondary devices connections if they are enabled.
+    fn secondary_port(&self) -> Option<u16> {
+        self.secondary_port
+            .filter(|_| self.inner.config.accept_secondaries)
+    }
+
+    /// Handles client connections until the sockets for the secondary devices are used up
+    /// or the secondary devices are switched in the configuration.
+    ///
+    /// Connection with the secondary device borrows its socket, so the socket cannot be
+    /// reused after the device has been disconnected. Once there are no sockets left,
+    /// the remaining secondary devices are disconnected and expected to reconnect.
+    async fn run_with_secondaries(&mut self) {
+        let secondary_port = self.secondary_port();
+        // Sockets for the secondary devices are allocated only if they are accepted.
+        let sockets_len = if secondary_port.is_some() {
+            SECONDARIES_CAPACITY
+        } else {
+            0
+        };
+        let mut stacks: heapless::Vec<B::NetworkStack, SECONDARIES_CAPACITY> =
+            (0..sockets_len).map(|_| self.network.clone()).collect();
+        let mut sockets: heapless::Vec<_, SECONDARIES_CAPACITY> =
+            stacks.iter_mut().map(NetworkStack::socket).collect();
+        let mut free_sockets = sockets.iter_mut();
+        let mut secondaries = Secondaries::new();
+
+        let mut secondary = pin!(accept_secondary(free_sockets.next(), secondary_port));
+        let mut has_free_sockets = true;
+        loop {
+            if !has_free_sockets && !secondaries.is_full() {
+                log::info!("Reconnecting secondary devices");
+                return;
+            }
+            if self.secondary_port() != secondary_port {
+                log::info!("Secondary devices have been switched in the configuration");
+                return;
+            }
+            if self.inner.main_device.is_some() {
+                log::info!("Switching to the secondary device mode");
+                return;
             }
 
-            peer.send_message(response).await?;
+            let mut client_socket = self.network.socket();
+            let client = pin!(client_socket.accept(self.port));
+            let connection = self
+                .inner
+                .run_until(select(client, secondary.as_mut()))
+                .await;
+            match connection {
+                Either::Left(connection) => {
+                    let handled = match connection {
+                        Ok(connection) => {
+                            let peer = Connection::incoming(connection);
+                            self.inner
+                                .handle_client(peer, &mut secondaries, false)
+                                .await
+                        }
+                        Err(err) => Err(err),
+                    };
+                    if let Err(_err) = handled {
+                        log::info!("Closed connection with client");
+                    }
+                }
+
+                Either::Right(connection) => {
+                    let added = match connection {
+                        Ok(connection) => {
+                            self.inner.add_secondary(&mut secondaries, connection).await
+                        }
+                        Err(err) => Err(err),
+                    };
+                    if let Err(err) = added {
+                        log::warn!("Unable to connect a secondary device: {err}");
+                    }
+
+                    let socket = free_sockets.next();
+                    has_free_sockets = socket.is_some();
+                    secondary.set(accept_secondary(socket, secondary_port));
+                }
+            }
         }
     }
 }
@@ -85,6 +343,80 @@ struct AppInner<B: Board> {
     render: Option<B::RenderTask>,
     // Cached device information.
     device_info: DeviceInfo,
+    // Cached metadata of the stored images, which is read while the image is being rendered.
+    images: CachedImages,
+    // Cached stored playlists, which are read while the playlist is running.
+    playlists: Playlists,
+    // Currently running playlist.
+    playlist: Option<PlaylistState>,
+    // Image which should be shown at the given time.
+    scheduled: Option<ScheduledImage>,
+    // Network stack of the DMX input.
+    dmx: Option<B::NetworkStack>,
+    // UDP port of the DMX input which overrides the standard protocol port.
+    dmx_port: Option<u16>,
+    // Network stack and settings of the discovery requests responder.
+    discovery: Option<DiscoverySettings<B::NetworkStack>>,
+    // Cached configuration, which is read while the image is being rendered.
+    config: Configuration,
+    // Cached pre-shared key which the clients have to authenticate with.
+    auth_key: Option<AuthKey>,
+    // Number of the authentication challenges sent since the device start.
+    challenges: u32,
+    // Random seed of the authentication challenges.
+    challenge_seed: u64,
+    // Results of the last request forwarded to the secondary devices.
+    secondary_results: SecondaryResults,
+    // Main device of this one, if it is a secondary device.
+    main_device: Option<MainDevice>,
+}
+
+/// Settings of the discovery requests responder.
+#[derive(Clone)]
+struct DiscoverySettings<S> {
+    stack: S,
+    /// UDP port of the discovery requests.
+    port: u16,
+    /// Port of the client connections reported to the discovery requests.
+    client_port: u16,
+}
+
+/// Image waiting for its start time.
+struct ScheduledImage {
+    image_id: ImageId,
+    playback: Playback,
+    /// Board time when the image should be shown.
+    time: Duration,
+}
+
+/// Cached metadata of the stored image.
+struct CachedImage {
+    metadata: ImageMetadata,
+    name: Option<ImageName>,
+}
+
+/// List of the stored images metadata ordered by the image identifiers.
+type CachedImages = heapless::Vec<CachedImage, MAX_IMAGES_COUNT>;
+
+/// Secondary device connected to this one.
+struct Secondary<C> {
+    client: Client<C>,
+    group_id: Option<u32>,
+    /// Whether the secondary device stores the images under the same indices as this one.
+    images_synced: bool,
+    /// Result of the clock offset measurement made before the last image start.
+    clock_offset: CyberpixieResult<ClockOffset>,
+}
+
+/// List of the connected secondary devices.
+type Secondaries<C> = heapless::Vec<Secondary<C>, SECONDARIES_CAPACITY>;
+
+/// State of the running playlist.
+struct PlaylistState {
+    position: PlaylistPosition,
+    playlist: Playlist,
+    /// Board time when the current entry should be switched to the next one.
+    deadline: Duration,
 }
 
 impl<B: Board> AppInner<B> {
@@ -94,22 +426,35 @@ impl<B: Board> AppInner<B> {
         storage.as_mut().ok_or(CyberpixieError::Internal)
     }
 
-    /// Refreshes cached device information
+    /// Refreshes cached device information and configuration.
     fn refresh_device_info(&mut self) -> CyberpixieResult<()> {
         let storage = Self::storage_mut(&mut self.storage)?;
         self.device_info = crate::read_device_info(storage)?;
+        self.config = storage.config()?;
         Ok(())
     }
 
     /// Returns peer information about this running application for handshake.
     fn peer_info(&mut self) -> PeerInfo {
+        let active = self.render.is_some();
         PeerInfo {
-            role: DeviceRole::Main,
-            group_id: None,
+            role: if self.main_device.is_some() {
+                DeviceRole::Secondary
+            } else {
+                DeviceRole::Main
+            },
+            group_id: self.main_device.and_then(|device| device.group_id),
             device_info: Some(DeviceInfo {
-                active: self.render.is_some(),
+                active,
+                playlist: self
+                    .playlist
+                    .as_ref()
+                    .filter(|_| active)
+                    .map(|state| state.position),
                 ..self.device_info
             }),
+            version: PROTOCOL_VERSION,
+            capabilities: Capabilities::ALL,
         }
     }
 
@@ -129,17 +474,951 @@ impl<B: Board> AppInner<B> {
         Ok(storage.as_mut().unwrap())
     }
 
+    /// Sends an image with the given ID to the peer.
+    ///
+    /// Unlike the image metadata, the image bytes can be read only after the rendering stop.
+    async fn send_image<C: AsyncRead + AsyncWrite>(
+        &mut self,
+        peer: &mut Connection<C>,
+        image_id: ImageId,
+        info: ImageInfo,
+    ) -> CyberpixieResult<()> {
+        let storage =
+            Self::stop_rendering(&mut self.board, &mut self.storage, &mut self.render).await?;
+        let image = storage.read_image(image_id)?;
+        let len = image.bytes.bytes_remaining();
+        peer.send_message_with_payload(
+            ResponseHeader::ReadImage(info),
+            PayloadReader::new(image.bytes, len),
+        )
+        .await
+    }
+
+    /// Sends metadata of the all stored images to the peer.
+    async fn send_images_list<C: AsyncRead + AsyncWrite>(
+        &mut self,
+        peer: &mut Connection<C>,
+        images_count: ImageId,
+    ) -> CyberpixieResult<()> {
+        let len = usize::from(images_count.0) * ImageMetadata::PACKED_LEN;
+        peer.send_message_with_payload(
+            ResponseHeader::ListImages(images_count),
+            PayloadReader::new(ImagesListReader::new(&self.images), len),
+        )
+        .await
+    }
+
+    /// Checks that the image with the given information and length can be shown by this device.
+    fn check_image(&self, info: &ImageInfo, len: usize) -> CyberpixieResult<()> {
+        if self.device_info.strip_len != info.strip_len {
+            return Err(CyberpixieError::StripLengthMismatch);
+        }
+        // Encoded pictures support only RGB pixels.
+        if info.encoding != ImageEncoding::Raw && info.pixel_format != PixelFormat::Rgb {
+            return Err(CyberpixieError::Unsupported);
+        }
+        let chunk_len = match info.encoding {
+            // The length of the picture in bytes should be a multiple of "strip length" * "bytes per pixel".
+            ImageEncoding::Raw => usize::from(info.strip_len) * info.pixel_format.bytes_per_pixel(),
+            // The encoded picture consists of the whole runs, its pixels count is checked
+            // after the picture has been stored.
+            ImageEncoding::Rle => rle::RUN_LEN,
+            // The pixels count of the indexed picture depends on the palette block length,
+            // so it is checked after the picture has been stored.
+            ImageEncoding::Palette => 1,
+        };
+        if len % chunk_len != 0 {
+            return Err(CyberpixieError::ImageLengthMismatch);
+        }
+        Ok(())
+    }
+
+    /// Checks that the pixels count of the stored encoded image is a multiple of the strip length
+    /// and the image has no empty runs.
+    ///
+    /// The invalid image is removed from the storage.
+    fn check_encoded_image(&mut self, image_id: ImageId) -> CyberpixieResult<()> {
+        let strip_len = usize::from(self.device_info.strip_len);
+        let storage = Self::storage_mut(&mut self.storage)?;
+        let (pixels_count, has_empty_runs) = {
+            let mut image = storage.read_image(image_id)?;
+            let pixels_count = image
+                .pixels_count()
+                .map_err(CyberpixieError::storage_read)?;
+            let has_empty_runs = image
+                .has_empty_runs()
+                .map_err(CyberpixieError::storage_read)?;
+            (pixels_count, has_empty_runs)
+        };
+        if pixels_count == 0 || pixels_count % strip_len != 0 || has_empty_runs {
+            storage.delete_image(image_id)?;
+            return Err(CyberpixieError::ImageLengthMismatch);
+        }
+        Ok(())
+    }
+
+    /// Adds a new image with the bytes from the request payload.
+    async fn add_image<R: AsyncRead>(
+        &mut self,
+        info: ImageInfo,
+        payload: Option<PayloadReader<R>>,
+    ) -> CyberpixieResult<ImageId> {
+        // Request should has payload.
+        let image = payload.ok_or(CyberpixieError::ImageLengthMismatch)?;
+        if let Err(err) = self.check_image(&info, image.bytes_remaining()) {
+            // Don't forget to skip the entire payload.
+            image.skip().await.map_err(CyberpixieError::network)?;
+            return Err(err);
+        }
+
+        let storage =
+            Self::stop_rendering(&mut self.board, &mut self.storage, &mut self.render).await?;
+        let encoding = info.encoding;
+        let image_id = storage.add_image(info, image).await?;
+        let checked = match encoding {
+            ImageEncoding::Raw => Ok(()),
+            ImageEncoding::Rle | ImageEncoding::Palette => self.check_encoded_image(image_id),
+        };
+        if checked.is_ok() {
+            let image = cached_image(Self::storage_mut(&mut self.storage)?, image_id)?;
+            self.images
+                .push(image)
+                .map_err(|_| CyberpixieError::ImageRepositoryIsFull)?;
+        }
+
+        // Since we change the number of images we have to refresh device information.
+        self.refresh_device_info()?;
+        checked.map(|()| image_id)
+    }
+
+    /// Returns information about the stored image with the given ID.
+    fn image_info(&self, image_id: ImageId) -> CyberpixieResult<ImageInfo> {
+        let image = self
+            .images
+            .get(usize::from(image_id.0))
+            .ok_or(CyberpixieError::ImageNotFound)?;
+        Ok(ImageInfo {
+            refresh_rate: image.metadata.refresh_rate,
+            strip_len: self.device_info.strip_len,
+            name: image.name.clone(),
+            encoding: image.metadata.encoding,
+            pixel_format: image.metadata.pixel_format,
+        })
+    }
+
+    /// Checks and shows the streamed lines without saving them.
+    async fn stream_lines<R: AsyncRead>(
+        &mut self,
+        refresh_rate: Hertz,
+        payload: Option<PayloadReader<R>>,
+    ) -> CyberpixieResult<()> {
+        // Request should has payload.
+        let lines = payload.ok_or(CyberpixieError::ImageLengthMismatch)?;
+
+        self.playlist = None;
+        self.scheduled = None;
+        let storage =
+            Self::stop_rendering(&mut self.board, &mut self.storage, &mut self.render).await?;
+        let config = storage.config()?;
+        let line_len = usize::from(config.strip_len) * config.pixel_format.bytes_per_pixel();
+        let max_refresh_rate = self.board.firmware_info().max_refresh_rate;
+        let checked = if refresh_rate.0 == 0 || refresh_rate > max_refresh_rate {
+            Err(CyberpixieError::InvalidConfiguration)
+        } else if lines.is_empty() || lines.len() % line_len != 0 {
+            Err(CyberpixieError::ImageLengthMismatch)
+        } else {
+            Ok(())
+        };
+        if let Err(err) = checked {
+            // Don't forget to skip the entire payload.
+            lines.skip().await.map_err(CyberpixieError::network)?;
+            return Err(err);
+        }
+
+        self.board.stream_lines(config, refresh_rate, lines).await
+    }
+
+    /// Checks and applies a new device configuration.
+    async fn set_config(&mut self, config: Configuration) -> CyberpixieResult<()> {
+        if config.strip_len == 0 || config.strip_len > self.board.firmware_info().max_strip_len {
+            return Err(CyberpixieError::StripLengthMismatch);
+        }
+        if config.gamma.0 == 0 {
+            return Err(CyberpixieError::InvalidConfiguration);
+        }
+
+        let storage =
+            Self::stop_rendering(&mut self.board, &mut self.storage, &mut self.render).await?;
+        if config.current_image >= Some(storage.images_count()?) {
+            return Err(CyberpixieError::ImageNotFound);
+        }
+        // Storage removes all images if the strip length changes.
+        let main_device = config.main_device;
+        storage.set_config(config)?;
+        // Storage removes all images if the configuration has breaking changes.
+        if storage.images_count()?.0 == 0 {
+            self.images.clear();
+            self.playlists = storage.playlists()?;
+        }
+        self.main_device = main_device;
+
+        // Since we change the configuration we have to refresh device information.
+        self.refresh_device_info()?;
+        Ok(())
+    }
+
+    /// Starts showing an image with the given ID in the given playback mode.
+    async fn show_image(&mut self, image_id: ImageId, playback: Playback) -> CyberpixieResult<()> {
+        let storage =
+            Self::stop_rendering(&mut self.board, &mut self.storage, &mut self.render).await?;
+        check_playback(storage.read_image(image_id)?.encoding, playback)?;
+        storage.set_current_image_id(image_id)?;
+        // Since we change the current image ID we have to refresh device information.
+        self.refresh_device_info()?;
+
+        let render = self
+            .board
+            .start_rendering(self.storage.take().unwrap(), image_id, playback)
+            .await?;
+        self.render = Some(render);
+        Ok(())
+    }
+
+    /// Checks the image and schedules its showing at the given board time.
+    ///
+    /// The device stays idle until the scheduled time.
+    async fn schedule_image(
+        &mut self,
+        image_id: ImageId,
+        playback: Playback,
+        time: Duration,
+    ) -> CyberpixieResult<()> {
+        let storage =
+            Self::stop_rendering(&mut self.board, &mut self.storage, &mut self.render).await?;
+        check_playback(storage.read_image(image_id)?.encoding, playback)?;
+
+        self.scheduled = Some(ScheduledImage {
+            image_id,
+            playback,
+            time,
+        });
+        Ok(())
+    }
+
+    /// Starts showing the scheduled image.
+    async fn start_scheduled_image(&mut self) {
+        let Some(scheduled) = self.scheduled.take() else {
+            return;
+        };
+
+        if let Err(err) = self
+            .show_image(scheduled.image_id, scheduled.playback)
+            .await
+        {
+            log::warn!("Unable to show the scheduled image: {err}");
+        }
+    }
+
+    /// Handles client requests which start or stop showing images.
+    async fn handle_show_request(
+        &mut self,
+        header: RequestHeader,
+    ) -> CyberpixieResult<ResponseHeader> {
+        self.playlist = None;
+        self.scheduled = None;
+        match header {
+            RequestHeader::ShowImage(image_id) => {
+                self.show_image(image_id, Playback::default()).await?;
+                Ok(ResponseHeader::Empty)
+            }
+
+            RequestHeader::ShowImageWithPlayback(image_id, playback) => {
+                self.show_image(image_id, playback).await?;
+                Ok(ResponseHeader::Empty)
+            }
+
+            RequestHeader::ShowImageAt(image_id, playback, time) => {
+                self.schedule_image(image_id, playback, time.into()).await?;
+                Ok(ResponseHeader::Empty)
+            }
+
+            RequestHeader::HideImage => {
+                Self::stop_rendering(&mut self.board, &mut self.storage, &mut self.render).await?;
+                Ok(ResponseHeader::Empty)
+            }
+
+            _ => unreachable!("Not a show request: {header:?}"),
+        }
+    }
+
+    /// Handles playlist related client requests.
+    async fn handle_playlist_request(
+        &mut self,
+        header: RequestHeader,
+    ) -> CyberpixieResult<ResponseHeader> {
+        match header {
+            RequestHeader::AddPlaylist(playlist) => {
+                let playlist_id = self.add_playlist(playlist).await?;
+                Ok(ResponseHeader::AddPlaylist(playlist_id))
+            }
+
+            RequestHeader::ListPlaylists => {
+                // The playlists count is limited by the `MAX_PLAYLISTS`.
+                #[allow(clippy::cast_possible_truncation)]
+                let count = PlaylistId(self.playlists.len() as u16);
+                Ok(ResponseHeader::ListPlaylists(count))
+            }
+
+            RequestHeader::ReadPlaylist(playlist_id) => {
+                let playlist = self.playlists.get(usize::from(playlist_id.0)).cloned();
+                Ok(ResponseHeader::ReadPlaylist(
+                    playlist.ok_or(CyberpixieError::PlaylistNotFound)?,
+                ))
+            }
+
+            RequestHeader::DeletePlaylist(playlist_id) => {
+                self.delete_playlist(playlist_id).await?;
+                Ok(ResponseHeader::Empty)
+            }
+
+            RequestHeader::StartPlaylist(playlist_id) => {
+                self.start_playlist(playlist_id).await?;
+                Ok(ResponseHeader::Empty)
+            }
+
+            _ => unreachable!("Not a playlist request: {header:?}"),
+        }
+    }
+
+    /// Starts showing the current image if the autoplay is enabled.
+    async fn autoplay(&mut self) -> CyberpixieResult<()> {
+        let storage =
+            Self::stop_rendering(&mut self.board, &mut self.storage, &mut self.render).await?;
+        let config = storage.config()?;
+        let Some(image_id) = config.current_image.filter(|_| config.autoplay) else {
+            return Ok(());
+        };
+
+        log::info!("Autoplaying image {image_id}");
+        self.show_image(image_id, Playback::default()).await
+    }
+
+    /// Checks and stores a new playlist.
+    async fn add_playlist(&mut self, playlist: Playlist) -> CyberpixieResult<PlaylistId> {
+        if playlist.entries.is_empty() || playlist.entries.iter().any(|e| e.length.is_empty()) {
+            return Err(CyberpixieError::InvalidPlaylist);
+        }
+
+        let storage =
+            Self::stop_rendering(&mut self.board, &mut self.storage, &mut self.render).await?;
+        let images_count = storage.images_count()?;
+        if playlist.entries.iter().any(|e| e.image_id >= images_count) {
+            return Err(CyberpixieError::ImageNotFound);
+        }
+
+        let mut playlists = self.playlists.clone();
+        // The playlists count is limited by the `MAX_PLAYLISTS`.
+        #[allow(clippy::cast_possible_truncation)]
+        let playlist_id = PlaylistId(playlists.len() as u16);
+        playlists
+            .push(playlist)
+            .map_err(|_| CyberpixieError::PlaylistRepositoryIsFull)?;
+        storage.set_playlists(&playlists)?;
+        self.playlists = playlists;
+        Ok(playlist_id)
+    }
+
+    /// Removes a playlist with the given ID.
+    async fn delete_playlist(&mut self, playlist_id: PlaylistId) -> CyberpixieResult<()> {
+        let storage =
+            Self::stop_rendering(&mut self.board, &mut self.storage, &mut self.render).await?;
+        let mut playlists = self.playlists.clone();
+        if usize::from(playlist_id.0) >= playlists.len() {
+            return Err(CyberpixieError::PlaylistNotFound);
+        }
+        playlists.remove(usize::from(playlist_id.0));
+        storage.set_playlists(&playlists)?;
+        self.playlists = playlists;
+        Ok(())
+    }
+
+    /// Starts showing images of the playlist with the given ID from the first entry.
+    async fn start_playlist(&mut self, playlist_id: PlaylistId) -> CyberpixieResult<()> {
+        let playlist = self
+            .playlists
+            .get(usize::from(playlist_id.0))
+            .cloned()
+            .ok_or(CyberpixieError::PlaylistNotFound)?;
+        // Playlist may become empty after its images removal.
+        if playlist.entries.is_empty() {
+            return Err(CyberpixieError::InvalidPlaylist);
+        }
+
+        self.show_playlist_entry(PlaylistState {
+            position: PlaylistPosition {
+                playlist_id,
+                entry: 0,
+            },
+            playlist,
+            deadline: Duration::ZERO,
+        })
+        .await
+    }
+
+    /// Starts showing the current entry of the given playlist.
+    async fn show_playlist_entry(&mut self, mut state: PlaylistState) -> CyberpixieResult<()> {
+        self.playlist = None;
+        self.scheduled = None;
+
+        let entry = state.playlist.entries[usize::from(state.position.entry)];
+        let (playback, length) = match entry.length {
+            EntryLength::Millis(millis) => {
+                (Playback::default(), Duration::from_millis(millis.into()))
+            }
+            EntryLength::Loops(loops) => {
+                let storage =
+                    Self::stop_rendering(&mut self.board, &mut self.storage, &mut self.render)
+                        .await?;
+                let metadata = storage.image_metadata(entry.image_id)?;
+                // The refresh rate is the rate of the single image line.
+                let millis = u64::from(metadata.lines) * u64::from(loops) * 1000
+                    / u64::from(metadata.refresh_rate.0.max(1));
+                let playback = Playback {
+                    direction: PlaybackDirection::Forward,
+                    repeat: Some(loops),
+                };
+                (playback, Duration::from_millis(millis))
+            }
+        };
+        self.show_image(entry.image_id, playback).await?;
+
+        state.deadline = self.board.now() + length;
+        self.playlist = Some(state);
+        Ok(())
+    }
+
+    /// Switches the running playlist to the next entry, the playlist is repeated endlessly.
+    async fn next_playlist_entry(&mut self) {
+        let Some(mut state) = self.playlist.take() else {
+            return;
+        };
+
+        let next_entry = (usize::from(state.position.entry) + 1) % state.playlist.entries.len();
+        // The playlist length is limited by the `MAX_PLAYLIST_LEN`.
+        #[allow(clippy::cast_possible_truncation)]
+        let next_entry = next_entry as u16;
+        state.position.entry = next_entry;
+        if let Err(err) = self.show_playlist_entry(state).await {
+            log::warn!("Unable to switch to the next playlist entry: {err}");
+        }
+    }
+
+    /// Shows lines received from the DMX input until an error occurs.
+    async fn stream_dmx(&mut self) -> CyberpixieResult<()> {
+        let Some(stack) = self.dmx.as_mut() else {
+            return Ok(());
+        };
+
+        let config = self.config.clone();
+        let mut input = DmxInput::new(config.dmx_protocol, config.dmx_universe);
+        if let Some(port) = self.dmx_port {
+            input.port = port;
+        }
+        let line_len = usize::from(config.strip_len) * config.pixel_format.bytes_per_pixel();
+        let mut socket = stack.socket();
+        let lines = DmxLines::new(socket.bind(input.port).await?, input, line_len);
+        // Lines are shown as soon as they are received.
+        let refresh_rate = self.board.firmware_info().max_refresh_rate;
+        self.board
+            .stream_lines(config, refresh_rate, PayloadReader::new(lines, usize::MAX))
+            .await
+    }
+
+    /// Waits for the given future to complete and answers the discovery requests in
+    /// the meantime.
+    ///
+    /// The given future is never interrupted, so it is safe to pass any network operation.
+    async fn run_until<F: Future>(&mut self, future: F) -> F::Output {
+        let Some(mut settings) = self.discovery.clone() else {
+            return self.run_playback_until(future).await;
+        };
+
+        // The reply is prepared in advance, since the device state is borrowed by the playback.
+        let reply = DiscoveryReply {
+            name: self.config.name.clone(),
+            port: settings.client_port,
+            peer_info: self.peer_info(),
+        };
+        let mut socket = settings.stack.socket();
+        let responder = pin!(async {
+            let mut udp = socket.bind(settings.port).await?;
+            discovery::answer_requests(&mut udp, &reply).await
+        });
+
+        let mut future = pin!(future);
+        // The playback must be dropped before the future is awaited again.
+        {
+            let playback = pin!(self.run_playback_until(future.as_mut()));
+            match select(playback, responder).await {
+                Either::Left(output) => return output,
+                Either::Right(Err(err)) => {
+                    log::warn!("Discovery responder has been stopped: {err}");
+                }
+                Either::Right(Ok(())) => {}
+            }
+        }
+        self.run_playback_until(future).await
+    }
+
+    /// Waits for the given future to complete and switches the running playlist entries or
+    /// starts the scheduled image in the meantime. If the device is idle, the lines from
+    /// the enabled DMX input are shown instead, unless the device has an authentication key.
+    ///
+    /// The given future is never interrupted, so it is safe to pass any network operation.
+    async fn run_playback_until<F: Future>(&mut self, future: F) -> F::Output {
+        let mut future = pin!(future);
+        loop {
+            // The playlist stops together with the rendering task.
+            if self.render.is_none() {
+                self.playlist = None;
+            }
+            // Scheduled image and the running playlist exclude each other.
+            let deadline = match (&self.scheduled, &self.playlist) {
+                (Some(scheduled), _) => scheduled.time,
+                (None, Some(state)) => state.deadline,
+                (None, None) => break,
+            };
+
+            // The timer must be dropped before the playlist entry switching.
+            {
+                let sleep = pin!(self.board.sleep_until(deadline));
+                if let Either::Left(output) = select(future.as_mut(), sleep).await {
+                    return output;
+                }
+            }
+            if self.scheduled.is_some() {
+                self.start_scheduled_image().await;
+            } else {
+                self.next_playlist_entry().await;
+            }
+        }
+
+        let dmx_enabled = self.config.dmx_input && self.auth_key.is_none();
+        if self.render.is_none() && self.dmx.is_some() && dmx_enabled {
+            // The DMX input must be dropped before the strip clearing.
+            let output = {
+                let dmx = pin!(self.stream_dmx());
+                match select(future.as_mut(), dmx).await {
+                    Either::Left(output) => Some(output),
+                    Either::Right(Err(err)) => {
+                        log::warn!("DMX input has been stopped: {err}");
+                        None
+                    }
+                    Either::Right(Ok(())) => None,
+                }
+            };
+            // Otherwise the last received line stays on the strip.
+            self.board.clear_strip().await;
+            if let Some(output) = output {
+                return output;
+            }
+        }
+        future.await
+    }
+
+    /// Handles requests of the secondary device peer until the connection is closed.
+    async fn handle_without_secondaries<C: AsyncRead + AsyncWrite>(
+        &mut self,
+        peer: Connection<C>,
+        trusted: bool,
+    ) -> CyberpixieResult<()> {
+        // Secondary device doesn't have its own secondary devices.
+        self.handle_client(peer, &mut Secondaries::<C>::new(), trusted)
+            .await
+    }
+
+    /// Handles requests of the connected client until the connection is closed.
+    ///
+    /// Unless the client is trusted, it has to authenticate with the pre-shared key first.
+    async fn handle_client<C, S>(
+        &mut self,
+        mut peer: Connection<C>,
+        secondaries: &mut Secondaries<S>,
+        trusted: bool,
+    ) -> CyberpixieResult<()>
+    where
+        C: AsyncRead + AsyncWrite,
+        S: AsyncRead + AsyncWrite,
+    {
+        // Any client of the device without a key is authenticated, even if it sets a key later.
+        let mut authenticated = trusted || self.auth_key.is_none();
+        let mut challenge = None;
+        let mut first_request = true;
+        loop {
+            let mut request = match self.run_until(peer.receive_request()).await {
+                Ok(request) => request,
+                // Clients start with the handshake, which cannot be decoded if the client
+                // uses an older protocol, so the client is told about it.
+                Err(CyberpixieError::Decode) if first_request => {
+                    let err = CyberpixieError::UnsupportedProtocolVersion;
+                    peer.send_message(ResponseHeader::Error(err)).await?;
+                    return Err(err);
+                }
+                Err(err) => return Err(err),
+            };
+            first_request = false;
+            if let (false, Some(key)) = (authenticated, self.auth_key.clone()) {
+                let response = self
+                    .authenticate(&key, &request.header, &mut challenge)
+                    .unwrap_or_else(ResponseHeader::Error);
+                // The handshake is completed only after the successful authentication.
+                authenticated = matches!(response, ResponseHeader::Handshake(_));
+
+                if let Some(payload) = request.payload.take() {
+                    payload.skip().await.map_err(CyberpixieError::network)?;
+                }
+                peer.send_message(response).await?;
+                continue;
+            }
+
+            // Main device starts the image simultaneously with its secondary devices.
+            let starts_image = matches!(
+                request.header,
+                RequestHeader::ShowImage(_)
+                    | RequestHeader::ShowImageWithPlayback(..)
+                    | RequestHeader::ShowImageAt(..)
+            );
+            if starts_image && !secondaries.is_empty() {
+                // Clocks of the devices drift, so the offsets are measured right before
+                // the start, and only then the start time is chosen.
+                self.measure_clock_offsets(secondaries).await;
+                let secondaries_len = u32::try_from(secondaries.len()).unwrap_or(u32::MAX);
+                let start_delay = SCHEDULED_START_DELAY + SECONDARY_START_DELAY * secondaries_len;
+                let start_time = DeviceTime::from(self.board.now() + start_delay);
+                match request.header {
+                    RequestHeader::ShowImage(image_id) => {
+                        request.header =
+                            RequestHeader::ShowImageAt(image_id, Playback::default(), start_time);
+                    }
+                    RequestHeader::ShowImageWithPlayback(image_id, playback) => {
+                        request.header = RequestHeader::ShowImageAt(image_id, playback, start_time);
+                    }
+                    _ => {}
+                }
+            }
+            let header = request.header.clone();
+            let strip_len = self.device_info.strip_len;
+            let response = self
+                .handle_client_request(&mut request)
+                .await
+                .unwrap_or_else(ResponseHeader::Error);
+
+            // It the payload has not been read by the handler, we must read it anyway
+            // in order to avoid malformed socked state.
+            if let Some(payload) = request.payload.take() {
+                payload.skip().await.map_err(CyberpixieError::network)?;
+            }
+            // Secondary devices should complete the request before the client gets a response.
+            // Only the strip length changes of the configuration are forwarded to them, since
+            // these changes remove all images.
+            if !matches!(header, RequestHeader::SetConfig(_))
+                || self.device_info.strip_len != strip_len
+            {
+                self.forward_request(secondaries, &header, &response).await;
+            }
+
+            match (header, response) {
+                // Image bytes should be sent as a response payload.
+                (RequestHeader::ReadImage(image_id), ResponseHeader::ReadImage(info)) => {
+                    self.send_image(&mut peer, image_id, info).await?;
+                }
+                // Images metadata should be sent as a response payload.
+                (RequestHeader::ListImages, ResponseHeader::ListImages(count)) => {
+                    self.send_images_list(&mut peer, count).await?;
+                }
+                (_, response) => peer.send_message(response).await?,
+            }
+        }
+    }
+
+    /// Handles the request of the client that has not been authenticated yet.
+    ///
+    /// The handshake is answered with a new challenge, and the valid answer to it completes
+    /// the handshake. All other requests are refused.
+    fn authenticate(
+        &mut self,
+        key: &[u8],
+        header: &RequestHeader,
+        challenge: &mut Option<AuthChallenge>,
+    ) -> CyberpixieResult<ResponseHeader> {
+        match header {
+            RequestHeader::Handshake(info) => {
+                check_handshake(info)?;
+                let new_challenge = self.new_challenge(key);
+                *challenge = Some(new_challenge);
+                Ok(ResponseHeader::AuthChallenge(new_challenge))
+            }
+            RequestHeader::Authenticate(tag) => {
+                // Each challenge can be answered only once.
+                let challenge = challenge
+                    .take()
+                    .ok_or(CyberpixieError::AuthenticationFailed)?;
+                if !auth::verify(key, &challenge, tag) {
+                    log::warn!("Client has failed to authenticate");
+                    return Err(CyberpixieError::AuthenticationFailed);
+                }
+                log::info!("Client has been authenticated");
+                Ok(ResponseHeader::Handshake(self.peer_info()))
+            }
+            _ => Err(CyberpixieError::AuthenticationRequired),
+        }
+    }
+
+    /// Creates a new unique authentication challenge.
+    fn new_challenge(&mut self, key: &[u8]) -> AuthChallenge {
+        let challenge = auth::challenge(
+            key,
+            self.challenge_seed,
+            self.board.now().into(),
+            self.challenges,
+        );
+        self.challenges = self.challenges.wrapping_add(1);
+        challenge
+    }
+
+    /// Completes the handshake with the connected secondary device and adds it to the list.
+    ///
+    /// If this device has a key, the secondary device has to authenticate with it,
+    /// just like the clients.
+    async fn add_secondary<C: AsyncRead + AsyncWrite>(
+        &mut self,
+        secondaries: &mut Secondaries<C>,
+        connection: C,
+    ) -> CyberpixieResult<()> {
+        let key = self.auth_key.clone();
+        let auth = key.as_deref().map(|key| (key, self.new_challenge(key)));
+        let (client, info) = Client::from_secondary(connection, self.peer_info(), auth).await?;
+        if info.role != DeviceRole::Secondary {
+            return Err(CyberpixieError::Unsupported);
+        }
+
+        log::info!(
+            "Connected a secondary device from the group {:?}",
+            info.group_id
+        );
+        secondaries
+            .push(Secondary {
+                client,
+                group_id: info.group_id,
+                images_synced: true,
+                // The clock offset is measured before every image start.
+                clock_offset: Err(CyberpixieError::Internal),
+            })
+            .map_err(|_| CyberpixieError::Internal)
+    }
+
+    /// Measures the clock offsets of the connected secondary devices.
+    async fn measure_clock_offsets<C: AsyncRead + AsyncWrite>(
+        &self,
+        secondaries: &mut Secondaries<C>,
+    ) {
+        let deadline = self.board.now() + SECONDARIES_REQUEST_TIMEOUT;
+        for secondary in secondaries {
+            secondary.clock_offset = Self::secondary_request(
+                &self.board,
+                deadline,
+                secondary.client.clock_offset(|| self.board.now()),
+            )
+            .await;
+            if let Err(err) = secondary.clock_offset {
+                log::warn!(
+                    "Unable to synchronize the clock of the secondary device from the group {:?}: {err}",
+                    secondary.group_id
+                );
+            }
+        }
+    }
+
+    /// Waits for the request to the secondary device to complete before the given board time.
+    ///
+    /// The connection state is unknown after the timeout, so it is considered broken.
+    async fn secondary_request<T, F>(
+        board: &B,
+        deadline: Duration,
+        request: F,
+    ) -> CyberpixieResult<T>
+    where
+        F: Future<Output = CyberpixieResult<T>>,
+    {
+        let request = pin!(request);
+        let timeout = pin!(board.sleep_until(deadline));
+        match select(request, timeout).await {
+            Either::Left(result) => result,
+            Either::Right(()) => Err(CyberpixieError::Network),
+        }
+    }
+
+    /// Forwards the successfully handled request to the secondary devices and saves
+    /// their results.
+    ///
+    /// Secondary devices with the broken connection or which have not completed the request
+    /// in time are disconnected, the time limit is common for all secondary devices. Requests that refer to the images by their indices are not
+    /// sent to the secondary devices which images have diverged from the ones of this device
+    /// until the images are cleared.
+    async fn forward_request<C: AsyncRead + AsyncWrite>(
+        &mut self,
+        secondaries: &mut Secondaries<C>,
+        header: &RequestHeader,
+        response: &ResponseHeader,
+    ) {
+        match (header, response) {
+            (RequestHeader::AddImage(_), ResponseHeader::AddImage(_))
+            | (
+                RequestHeader::DeleteImage(_)
+                | RequestHeader::SetConfig(_)
+                | RequestHeader::ShowImage(_)
+                | RequestHeader::ShowImageWithPlayback(..)
+                | RequestHeader::ShowImageAt(..)
+                | RequestHeader::HideImage
+                | RequestHeader::ClearImages,
+                ResponseHeader::Empty,
+            ) => {}
+            _ => return,
+        }
+
+        self.secondary_results.clear();
+        let deadline = self.board.now() + SECONDARIES_REQUEST_TIMEOUT;
+        for secondary in &mut *secondaries {
+            let result = if !secondary.images_synced && refers_to_images(header) {
+                // The same index refers to a different image on the secondary device.
+                Err(CyberpixieError::ImageIdMismatch)
+            } else {
+                Self::secondary_request(
+                    &self.board,
+                    deadline,
+                    Self::forward_to_secondary(&mut self.storage, secondary, header, response),
+                )
+                .await
+            };
+            // Images diverge if the secondary device fails to change them.
+            match (header, result) {
+                (RequestHeader::ClearImages | RequestHeader::SetConfig(_), Ok(())) => {
+                    secondary.images_synced = true;
+                }
+                (
+                    RequestHeader::AddImage(_)
+                    | RequestHeader::DeleteImage(_)
+                    | RequestHeader::ClearImages
+                    | RequestHeader::SetConfig(_),
+                    Err(_),
+                ) => secondary.images_synced = false,
+                _ => {}
+            }
+            if let Err(err) = result {
+                log::warn!(
+                    "Secondary device from the group {:?} has failed the request: {err}",
+                    secondary.group_id
+                );
+            }
+            // The results list is not shorter than the secondary devices one.
+            let _ = self.secondary_results.push(SecondaryResult {
+                group_id: secondary.group_id,
+                error: result.err(),
+            });
+        }
+
+        let mut results = self.secondary_results.iter();
+        secondaries.retain(|_| {
+            results.next().and_then(|result| result.error) != Some(CyberpixieError::Network)
+        });
+    }
+
+    /// Sends the handled request to the single secondary device.
+    async fn forward_to_secondary<C: AsyncRead + AsyncWrite>(
+        storage: &mut Option<B::Storage>,
+        secondary: &mut Secondary<C>,
+        header: &RequestHeader,
+        response: &ResponseHeader,
+    ) -> CyberpixieResult<()> {
+        let client = &mut secondary.client;
+        match (header, response) {
+            (RequestHeader::AddImage(info), ResponseHeader::AddImage(image_id)) => {
+                // Resend the image as it has been stored by this device.
+                let image = Self::storage_mut(storage)?.read_image(*image_id)?;
+                let len = image.bytes.bytes_remaining();
+                let secondary_image_id = client
+                    .add_image_with_payload(info.clone(), PayloadReader::new(image.bytes, len))
+                    .await?;
+                if secondary_image_id != *image_id {
+                    log::warn!(
+                        "Secondary device has stored the image {image_id} as {secondary_image_id}"
+                    );
+                    return Err(CyberpixieError::ImageIdMismatch);
+                }
+                Ok(())
+            }
+            (RequestHeader::DeleteImage(image_id), _) => client.delete_image(*image_id).await,
+            (RequestHeader::SetConfig(config), _) => {
+                // The rest of the configuration belongs to the secondary device itself.
+                let secondary_config = client.config().await?;
+                if secondary_config.strip_len != config.strip_len {
+                    client
+                        .set_config(Configuration {
+                            strip_len: config.strip_len,
+                            current_image: None,
+                            ..secondary_config
+                        })
+                        .await?;
+                }
+                client.clear_images().await
+            }
+            (RequestHeader::ShowImage(image_id), _) => client.start(*image_id).await,
+            (RequestHeader::ShowImageWithPlayback(image_id, playback), _) => {
+                client.start_with_playback(*image_id, *playback).await
+            }
+            (RequestHeader::ShowImageAt(image_id, playback, time), _) => {
+                // The offset has been measured right before the request.
+                let offset = secondary.clock_offset?;
+                client
+                    .start_at(*image_id, *playback, offset.to_peer_time((*time).into()))
+                    .await
+            }
+            (RequestHeader::HideImage, _) => client.stop().await,
+            (RequestHeader::ClearImages, _) => client.clear_images().await,
+            _ => unreachable!("Not a forwarded request: {header:?}"),
+        }
+    }
+
     /// Handles incoming client request
     async fn handle_client_request<R: AsyncRead>(
         &mut self,
         request: &mut Message<R, RequestHeader>,
     ) -> CyberpixieResult<ResponseHeader> {
-        match request.header {
+        match request.header.clone() {
             RequestHeader::Handshake(info) => {
-                log::info!("Got a handshake with: {:?}", info);
+                check_handshake(&info)?;
                 Ok(ResponseHeader::Handshake(self.peer_info()))
             }
 
+            // The client has been already authenticated or the device has no key.
+            RequestHeader::Authenticate(_) => Ok(ResponseHeader::Handshake(self.peer_info())),
+
+            RequestHeader::SetAuthKey(key) => {
+                if key.as_ref().is_some_and(AuthKey::is_empty) {
+                    return Err(CyberpixieError::InvalidConfiguration);
+                }
+                let storage =
+                    Self::stop_rendering(&mut self.board, &mut self.storage, &mut self.render)
+                        .await?;
+                storage.set_auth_key(key.clone())?;
+                self.auth_key = key;
+                Ok(ResponseHeader::Empty)
+            }
+
+            RequestHeader::FirmwareInfo => {
+                Ok(ResponseHeader::FirmwareInfo(self.board.firmware_info()))
+            }
+
             RequestHeader::Debug => {
                 if let Some(payload) = request.payload.take() {
                     self.board.show_debug_message(payload).await?;
@@ -147,53 +1426,43 @@ impl<B: Board> AppInner<B> {
                 Ok(ResponseHeader::Empty)
             }
 
-            RequestHeader::AddImage(ImageInfo {
-                refresh_rate,
-                strip_len,
-            }) => {
-                if self.device_info.strip_len != strip_len {
-                    return Err(CyberpixieError::StripLengthMismatch);
-                }
-                // Request should has payload.
-                let image = request
-                    .payload
-                    .take()
-                    .ok_or(CyberpixieError::ImageLengthMismatch)?;
-                // The length of the picture in bytes should be a multiple of "strip length" * "bytes per pixel".
-                if image.bytes_remaining() % (strip_len as usize * BYTES_PER_PIXEL) != 0 {
-                    // Don't forget to skip the entire payload.
-                    image.skip().await.map_err(CyberpixieError::network)?;
-                    return Err(CyberpixieError::ImageLengthMismatch);
-                }
+            RequestHeader::AddImage(info) => {
+                let image_id = self.add_image(info, request.payload.take()).await?;
+                Ok(ResponseHeader::AddImage(image_id))
+            }
 
-                let storage =
-                    Self::stop_rendering(&mut self.board, &mut self.storage, &mut self.render)
-                        .await?;
-                let image_id = storage.add_image(refresh_rate, image).await?;
+            RequestHeader::ReadImage(image_id) => {
+                // The image bytes will be sent later as a response payload.
+                Ok(ResponseHeader::ReadImage(self.image_info(image_id)?))
+            }
 
-                // Since we change the number of images we have to refresh device information.
-                self.refresh_device_info()?;
-                Ok(ResponseHeader::AddImage(image_id))
+            RequestHeader::FindImage(name) => {
+                let position = self
+                    .images
+                    .iter()
+                    .position(|image| image.name.as_ref() == Some(&name))
+                    .ok_or(CyberpixieError::ImageNotFound)?;
+                // The images count is limited by the `MAX_IMAGES_COUNT`.
+                #[allow(clippy::cast_possible_truncation)]
+                let image_id = ImageId(position as u16);
+                Ok(ResponseHeader::FindImage(image_id))
             }
 
-            RequestHeader::ShowImage(image_id) => {
+            RequestHeader::ListImages => {
+                // The images metadata will be sent later as a response payload.
+                Ok(ResponseHeader::ListImages(self.device_info.images_count))
+            }
+
+            RequestHeader::DeleteImage(image_id) => {
                 let storage =
                     Self::stop_rendering(&mut self.board, &mut self.storage, &mut self.render)
                         .await?;
-                storage.set_current_image_id(image_id)?;
-                // Since we change the current image ID we have to refresh device information.
+                storage.delete_image(image_id)?;
+                self.images.remove(usize::from(image_id.0));
+                // Entries of the removed image are removed from the playlists.
+                self.playlists = storage.playlists()?;
+                // Since we change the number of images we have to refresh device information.
                 self.refresh_device_info()?;
-
-                let render = self
-                    .board
-                    .start_rendering(self.storage.take().unwrap(), image_id)
-                    .await?;
-                self.render = Some(render);
-                Ok(ResponseHeader::Empty)
-            }
-
-            RequestHeader::HideImage => {
-                Self::stop_rendering(&mut self.board, &mut self.storage, &mut self.render).await?;
                 Ok(ResponseHeader::Empty)
             }
 
@@ -202,10 +1471,149 @@ impl<B: Board> AppInner<B> {
                     Self::stop_rendering(&mut self.board, &mut self.storage, &mut self.render)
                         .await?;
                 storage.clear_images()?;
+                self.images.clear();
+                self.playlists = storage.playlists()?;
                 // Since we change the number of images we have to refresh device information.
                 self.refresh_device_info()?;
                 Ok(ResponseHeader::Empty)
             }
+
+            RequestHeader::SetConfig(config) => {
+                self.set_config(config).await?;
+                Ok(ResponseHeader::Empty)
+            }
+
+            RequestHeader::GetConfig => Ok(ResponseHeader::GetConfig(self.config.clone())),
+
+            RequestHeader::SyncClock => Ok(ResponseHeader::SyncClock(self.board.now().into())),
+
+            RequestHeader::StreamLines(refresh_rate) => {
+                self.stream_lines(refresh_rate, request.payload.take())
+                    .await?;
+                Ok(ResponseHeader::Empty)
+            }
+
+            RequestHeader::SecondaryResults => Ok(ResponseHeader::SecondaryResults(
+                self.secondary_results.clone(),
+            )),
+
+            header @ (RequestHeader::ShowImage(_)
+            | RequestHeader::ShowImageWithPlayback(..)
+            | RequestHeader::ShowImageAt(..)
+            | RequestHeader::HideImage) => self.handle_show_request(header).await,
+
+            header @ (RequestHeader::AddPlaylist(_)
+            | RequestHeader::ListPlaylists
+            | RequestHeader::ReadPlaylist(_)
+            | RequestHeader::DeletePlaylist(_)
+            | RequestHeader::StartPlaylist(_)) => self.handle_playlist_request(header).await,
+        }
+    }
+}
+
+/// Reader which encodes the cached images metadata entries one by one.
+struct ImagesListReader<'a> {
+    images: core::slice::Iter<'a, CachedImage>,
+    // Currently encoded metadata entry.
+    entry: [u8; ImageMetadata::PACKED_LEN],
+    entry_pos: usize,
+}
+
+impl<'a> ImagesListReader<'a> {
+    fn new(images: &'a [CachedImage]) -> Self {
+        Self {
+            images: images.iter(),
+            entry: [0_u8; ImageMetadata::PACKED_LEN],
+            entry_pos: ImageMetadata::PACKED_LEN,
         }
     }
 }
+
+impl ErrorType for ImagesListReader<'_> {
+    type Error = CyberpixieError;
+}
+
+impl BlockingRead for ImagesListReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        // Encode a next metadata entry if the current one has been read.
+        if self.entry_pos == self.entry.len() {
+            let Some(image) = self.images.next() else {
+                return Ok(0);
+            };
+
+            image.metadata.encode_as_le_bytes(&mut self.entry);
+            self.entry_pos = 0;
+        }
+
+        let amount = core::cmp::min(buf.len(), self.entry.len() - self.entry_pos);
+        buf[0..amount].copy_from_slice(&self.entry[self.entry_pos..self.entry_pos + amount]);
+        self.entry_pos += amount;
+        Ok(amount)
+    }
+}
+
+/// Reads metadata of the stored image with the given ID to cache it.
+fn cached_image<S: Storage>(storage: &mut S, image_id: ImageId) -> CyberpixieResult<CachedImage> {
+    Ok(CachedImage {
+        metadata: storage.image_metadata(image_id)?,
+        name: storage.image_name(image_id)?,
+    })
+}
+
+/// Checks that the peer which has sent the handshake uses a compatible protocol version.
+fn check_handshake(info: &PeerInfo) -> CyberpixieResult<()> {
+    log::info!("Got a handshake with: {:?}", info);
+    if !info.is_compatible() {
+        log::warn!("Rejecting peer with protocol version {}", info.version);
+        return Err(CyberpixieError::UnsupportedProtocolVersion);
+    }
+    Ok(())
+}
+
+/// Returns `true` if the request refers to the images by their indices.
+fn refers_to_images(header: &RequestHeader) -> bool {
+    matches!(
+        header,
+        RequestHeader::AddImage(_)
+            | RequestHeader::DeleteImage(_)
+            | RequestHeader::ShowImage(_)
+            | RequestHeader::ShowImageWithPlayback(..)
+            | RequestHeader::ShowImageAt(..)
+    )
+}
+
+/// Accepts a secondary device connection on the given socket and port.
+///
+/// Waits forever if there is no socket or port to accept the connection.
+async fn accept_secondary<S: NetworkSocket>(
+    socket: Option<&mut S>,
+    port: Option<u16>,
+) -> CyberpixieResult<S::Connection<'_>> {
+    let (Some(socket), Some(port)) = (socket, port) else {
+        return ::core::future::pending().await;
+    };
+    socket.accept(port).await
+}
+
+/// Output of the [`select`] function.
+enum Either<A, B> {
+    Left(A),
+    Right(B),
+}
+
+/// Waits for the first of the two futures to complete, the other one is left unfinished.
+async fn select<A: Future, B: Future>(
+    mut left: Pin<&mut A>,
+    mut right: Pin<&mut B>,
+) -> Either<A::Output, B::Output> {
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = left.as_mut().poll(cx) {
+            return Poll::Ready(Either::Left(output));
+        }
+        if let Poll::Ready(output) = right.as_mut().poll(cx) {
+            return Poll::Ready(Either::Right(output));
+        }
+        Poll::Pending
+    })
+    .await
+}
@@ -14,21 +14,28 @@
     clippy::missing_const_for_fn
 )]
 
-pub use cyberpixie_core::{self as core, Error as CyberpixieError, Result as CyberpixieResult};
+use ::core::time::Duration;
+pub use cyberpixie_core::{
+    self as core, proto::types::Configuration, Error as CyberpixieError, Result as CyberpixieResult,
+};
 use cyberpixie_core::{
-    io::{image_reader::Image, AsyncRead, BlockingRead, BlockingSeek, ExactSizeRead},
-    proto::types::{DeviceInfo, FirmwareInfo, Hertz, ImageId},
+    io::{image_reader::Image, AsyncRead, AsyncSeek, BlockingRead, BlockingSeek, ExactSizeRead},
+    proto::types::{
+        AuthKey, DeviceInfo, FirmwareInfo, Hertz, ImageId, ImageInfo, ImageMetadata, ImageName,
+        Playback, Playlists,
+    },
 };
 pub use cyberpixie_network as network;
 use cyberpixie_network::{NetworkStack, PayloadReader};
-use serde::{Deserialize, Serialize};
 
 pub use self::app::App;
 
 mod app;
 
 /// Port for the client connection.
-pub const DEFAULT_CLIENT_PORT: u16 = 1800;
+pub const DEFAULT_CLIENT_PORT: u16 = cyberpixie_network::DEFAULT_DEVICE_PORT;
+/// Port for the secondary devices connection.
+pub const DEFAULT_SECONDARY_PORT: u16 = 1801;
 
 /// Board-specific components
 ///
@@ -37,7 +44,9 @@ pub trait Board {
     /// Type provides the internal storage functionality.
     type Storage: Storage;
     /// Type provides the network stack.
-    type NetworkStack: NetworkStack;
+    ///
+    /// Copies of the network stack are used to hold several connections at the same time.
+    type NetworkStack: NetworkStack + Clone;
     /// Type provides a LED strip pictures rendering task.
     type RenderTask;
     /// Returns all board components.
@@ -48,16 +57,43 @@ pub trait Board {
     ///
     /// To prevent data races, this method takes [`Self::Storage`] the entirely, making
     /// it impossible to modify it while the image rendering task is being executed.
+    ///
+    /// The image is expected to be shown in the given playback mode, which is checked
+    /// by the caller to be suitable for the image.
     async fn start_rendering(
         &mut self,
         storage: Self::Storage,
         image_id: ImageId,
+        playback: Playback,
     ) -> CyberpixieResult<Self::RenderTask>;
     /// Stops a LED strip rendering task and returns back previously borrowed storage.
     async fn stop_rendering(&mut self, handle: Self::RenderTask)
         -> CyberpixieResult<Self::Storage>;
     /// Returns a board firmware information.
     fn firmware_info(&self) -> FirmwareInfo;
+    /// Returns a monotonic time elapsed since the board start.
+    fn now(&self) -> Duration;
+    /// Waits until the board time reaches the given value.
+    async fn sleep_until(&self, time: Duration);
+    /// Returns a random number chosen at the board start.
+    ///
+    /// It keeps the authentication challenges unique across the board restarts, so the answers
+    /// to the challenges of the previous starts cannot be replayed. The number should be taken
+    /// from the hardware random number generator.
+    fn random_seed(&self) -> u64;
+    /// Shows the streamed lines straight away, bypassing the storage.
+    ///
+    /// The payload consists of the lines of the strip pixels in the configured pixel format,
+    /// its length is checked by the caller. Each line should be shown as soon as it has been
+    /// received with the given refresh rate, and the strip should be cleared after the last one.
+    async fn stream_lines<R: AsyncRead>(
+        &mut self,
+        config: Configuration,
+        refresh_rate: Hertz,
+        lines: PayloadReader<R>,
+    ) -> CyberpixieResult<()>;
+    /// Clears the strip after the lines streaming has been interrupted.
+    async fn clear_strip(&mut self);
 
     /// Shows a debug message.
     ///
@@ -77,36 +113,19 @@ pub trait Board {
     }
 }
 
-/// A global application configuration.
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
-pub struct Configuration {
-    /// The number of LEDs in the strip.
-    pub strip_len: u16,
-    /// Index of the picture which will be show by default.
-    pub current_image: Option<ImageId>,
-}
-
-impl Configuration {
-    /// Default strip LED length.
-    pub const DEFAULT_STRIP_LED_LEN: u16 = 24;
-}
-
-impl Default for Configuration {
-    fn default() -> Self {
-        Self {
-            strip_len: Self::DEFAULT_STRIP_LED_LEN,
-            current_image: None,
-        }
-    }
-}
-
 /// A type definition to represent an image reader for a certain device.
 pub type ImageReader<'a, S> = Image<<S as Storage>::ImageRead<'a>>;
+/// A type definition to represent an asynchronous image reader for a certain device.
+pub type AsyncImageReader<'a, S> = Image<<S as Storage>::AsyncImageRead<'a>>;
 
 /// Board internal storage.
 pub trait Storage: Send + 'static {
     /// Image reader type.
     type ImageRead<'a>: BlockingRead + BlockingSeek + ExactSizeRead
+    where
+        Self: 'a;
+    /// Asynchronous image reader type.
+    type AsyncImageRead<'a>: AsyncRead + AsyncSeek + ExactSizeRead
     where
         Self: 'a;
     /// Returns an application configuration.
@@ -118,22 +137,55 @@ pub trait Storage: Send + 'static {
     /// - You should check the current image index for the boundaries
     /// - You must invoke [`Self::clear_images`] method if the strip length changes.
     fn set_config(&mut self, config: Configuration) -> CyberpixieResult<()>;
+    /// Returns the pre-shared key which the clients have to authenticate with.
+    fn auth_key(&mut self) -> CyberpixieResult<Option<AuthKey>>;
+    /// Replaces the pre-shared key, the `None` key allows any client.
+    fn set_auth_key(&mut self, key: Option<AuthKey>) -> CyberpixieResult<()>;
     /// Adds a new image.
+    ///
+    /// The strip length in the image information is expected to be checked by the caller.
     async fn add_image<R: AsyncRead + ExactSizeRead>(
         &mut self,
-        refresh_rate: Hertz,
+        info: ImageInfo,
         image: R,
     ) -> CyberpixieResult<ImageId>;
     /// Reads an image with the given identifier.
     fn read_image(&mut self, id: ImageId) -> CyberpixieResult<ImageReader<'_, Self>>;
+    /// Reads an image with the given identifier without blocking the executor.
+    ///
+    /// This reader is intended for the long running tasks like the image rendering.
+    async fn read_image_async(
+        &mut self,
+        id: ImageId,
+    ) -> CyberpixieResult<AsyncImageReader<'_, Self>>;
+    /// Returns a name of the image with the given identifier.
+    fn image_name(&mut self, id: ImageId) -> CyberpixieResult<Option<ImageName>>;
     /// Returns total saved images count.
     fn images_count(&mut self) -> CyberpixieResult<ImageId>;
+    /// Removes an image with the given identifier.
+    ///
+    /// Identifiers of the images that follow the removed one are decreased by one.
+    ///
+    /// # Notice for the board developers
+    ///
+    /// - You should unset the current image ID if it points to the removed image
+    ///   or shift it, if it points to one of the following images.
+    /// - You should remove the image from the stored playlists, see
+    ///   [`Playlist::remove_image`](cyberpixie_core::proto::types::Playlist::remove_image).
+    fn delete_image(&mut self, id: ImageId) -> CyberpixieResult<()>;
     /// Remove all stored images.
     ///
     /// # Notice for the board developers
     ///
     /// - You should set the current image ID to the `None` in the board configuration.
+    /// - You should remove all stored playlists.
     fn clear_images(&mut self) -> CyberpixieResult<()>;
+    /// Returns all stored playlists.
+    fn playlists(&mut self) -> CyberpixieResult<Playlists>;
+    /// Replaces all stored playlists.
+    ///
+    /// Image identifiers in the playlists are expected to be checked by the caller.
+    fn set_playlists(&mut self, playlists: &Playlists) -> CyberpixieResult<()>;
 
     /// Sets an index of image that will be shown.
     fn set_current_image_id<I>(&mut self, id: I) -> CyberpixieResult<()>
@@ -170,6 +222,38 @@ pub trait Storage: Send + 'static {
         Ok(Some(current_image))
     }
 
+    /// Returns metadata of an image with the given identifier.
+    fn image_metadata(&mut self, id: ImageId) -> CyberpixieResult<ImageMetadata> {
+        let strip_len = usize::from(self.config()?.strip_len);
+        let mut image = self.read_image(id)?;
+        let len = image.bytes.bytes_remaining();
+        let lines = image
+            .pixels_count()
+            .map_err(CyberpixieError::storage_read)?
+            / strip_len;
+        // Image length is limited by the storage capacity, so it fits into the u32.
+        #[allow(clippy::cast_possible_truncation)]
+        let metadata = ImageMetadata {
+            len: len as u32,
+            lines: lines as u32,
+            refresh_rate: image.refresh_rate,
+            encoding: image.encoding,
+            pixel_format: image.pixel_format,
+        };
+        Ok(metadata)
+    }
+
+    /// Returns an identifier of the first image with the given name.
+    fn find_image(&mut self, name: &str) -> CyberpixieResult<Option<ImageId>> {
+        for id in 0..self.images_count()?.0 {
+            let id = ImageId(id);
+            if self.image_name(id)?.as_deref() == Some(name) {
+                return Ok(Some(id));
+            }
+        }
+        Ok(None)
+    }
+
     /// Reads a current image.
     fn current_image(&mut self) -> CyberpixieResult<ImageReader<'_, Self>> {
         let image_id = self
@@ -181,6 +265,7 @@ pub trait Storage: Send + 'static {
 
 impl<T: Storage> Storage for &'static mut T {
     type ImageRead<'a> = T::ImageRead<'a>;
+    type AsyncImageRead<'a> = T::AsyncImageRead<'a>;
 
     fn config(&mut self) -> CyberpixieResult<Configuration> {
         T::config(self)
@@ -190,25 +275,56 @@ impl<T: Storage> Storage for &'static mut T {
         T::set_config(self, config)
     }
 
+    fn auth_key(&mut self) -> CyberpixieResult<Option<AuthKey>> {
+        T::auth_key(self)
+    }
+
+    fn set_auth_key(&mut self, key: Option<AuthKey>) -> CyberpixieResult<()> {
+        T::set_auth_key(self, key)
+    }
+
     async fn add_image<R: AsyncRead + ExactSizeRead>(
         &mut self,
-        refresh_rate: Hertz,
+        info: ImageInfo,
         image: R,
     ) -> CyberpixieResult<ImageId> {
-        T::add_image(self, refresh_rate, image).await
+        T::add_image(self, info, image).await
     }
 
     fn read_image(&mut self, id: ImageId) -> CyberpixieResult<ImageReader<'_, Self>> {
         T::read_image(self, id)
     }
 
+    async fn read_image_async(
+        &mut self,
+        id: ImageId,
+    ) -> CyberpixieResult<AsyncImageReader<'_, Self>> {
+        T::read_image_async(self, id).await
+    }
+
+    fn image_name(&mut self, id: ImageId) -> CyberpixieResult<Option<ImageName>> {
+        T::image_name(self, id)
+    }
+
     fn images_count(&mut self) -> CyberpixieResult<ImageId> {
         T::images_count(self)
     }
 
+    fn delete_image(&mut self, id: ImageId) -> CyberpixieResult<()> {
+        T::delete_image(self, id)
+    }
+
     fn clear_images(&mut self) -> CyberpixieResult<()> {
         T::clear_images(self)
     }
+
+    fn playlists(&mut self) -> CyberpixieResult<Playlists> {
+        T::playlists(self)
+    }
+
+    fn set_playlists(&mut self, playlists: &Playlists) -> CyberpixieResult<()> {
+        T::set_playlists(self, playlists)
+    }
 }
 
 pub(crate) fn read_device_info<S: Storage>(storage: &mut S) -> CyberpixieResult<DeviceInfo> {
@@ -218,5 +334,6 @@ pub(crate) fn read_device_info<S: Storage>(storage: &mut S) -> CyberpixieResult<
         images_count: storage.images_count()?,
         current_image: config.current_image,
         active: false,
+        playlist: None,
     })
 }
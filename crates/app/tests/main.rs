@@ -1,55 +1,102 @@
 #![feature(async_fn_in_trait)]
 
-use std::time::Duration;
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use cyberpixie_app::{
-    core::proto::types::{DeviceInfo, DeviceRole, FirmwareInfo, Hertz, ImageId},
-    App, Board, Configuration, CyberpixieError, CyberpixieResult,
+    core::{
+        io::{AsyncRead, AsyncWrite, ExactSizeRead},
+        proto::{
+            packet::{EncodeLE, PackedSize, Packet},
+            types::{
+                AuthKey, Capabilities, ColorOrder, DeviceInfo, DeviceRole, DeviceTime, EntryLength,
+                FirmwareInfo, Gamma, Hertz, ImageEncoding, ImageId, ImageInfo, MainDevice,
+                PeerInfo, PixelFormat, Playback, PlaybackDirection, Playlist, PlaylistEntry,
+                PlaylistId, PlaylistPosition, SecondaryResult,
+            },
+            RequestHeader, ResponseHeader, MIN_PROTOCOL_VERSION,
+        },
+    },
+    App, Board, Configuration, CyberpixieError, CyberpixieResult, Storage,
 };
 use cyberpixie_embedded_storage::{
     test_utils::{leaked_buf, MemoryBackend},
     MemoryLayout, StorageImpl,
 };
 use cyberpixie_network::{
+    auth, discovery,
+    dmx::DmxProtocol,
     tokio::{TokioConnection, TokioSocket, TokioStack},
-    Client, Ipv6Addr, NetworkStack,
+    Client, Connection, Ipv6Addr, NetworkSocket, NetworkStack, PayloadReader, UdpSocket,
 };
 use tokio::task::JoinHandle;
 
 struct BoardStub {
-    memory: Option<MemoryBackend>,
+    storage: Option<StorageImpl<MemoryBackend>>,
+    start: Instant,
+    /// Lines shown by the [`Board::stream_lines`] method.
+    streamed_lines: Arc<Mutex<Vec<Vec<u8>>>>,
+    /// Number of the [`Board::clear_strip`] method calls.
+    strip_clears: Arc<Mutex<usize>>,
+    /// Times of the [`Board::start_rendering`] method calls.
+    render_starts: Arc<Mutex<Vec<Instant>>>,
 }
 
-impl Default for BoardStub {
-    fn default() -> Self {
+impl BoardStub {
+    fn with_storage(storage: StorageImpl<MemoryBackend>) -> Self {
         Self {
-            memory: Some(MemoryBackend::default()),
+            storage: Some(storage),
+            start: Instant::now(),
+            streamed_lines: Arc::default(),
+            strip_clears: Arc::default(),
+            render_starts: Arc::default(),
         }
     }
 }
 
+impl Default for BoardStub {
+    fn default() -> Self {
+        Self::with_storage(init_storage(Configuration::default()))
+    }
+}
+
+/// Creates a board of the main device which accepts the secondary devices.
+fn main_board() -> BoardStub {
+    BoardStub::with_storage(init_storage(Configuration {
+        accept_secondaries: true,
+        ..Configuration::default()
+    }))
+}
+
+fn init_storage(config: Configuration) -> StorageImpl<MemoryBackend> {
+    let memory = MemoryBackend::default();
+    let layout = MemoryLayout {
+        base: 0,
+        size: memory.0.len() as u32,
+    };
+    StorageImpl::init(config, memory, layout, leaked_buf(512)).unwrap()
+}
+
 impl Board for BoardStub {
     type Storage = StorageImpl<MemoryBackend>;
     type NetworkStack = TokioStack;
     type RenderTask = StorageImpl<MemoryBackend>;
 
     fn take_components(&mut self) -> Option<(Self::Storage, Self::NetworkStack)> {
-        let memory = self.memory.take()?;
-        let layout = MemoryLayout {
-            base: 0,
-            size: memory.0.len() as u32,
-        };
-        Some((
-            StorageImpl::init(Configuration::default(), memory, layout, leaked_buf(512)).unwrap(),
-            TokioStack,
-        ))
+        Some((self.storage.take()?, TokioStack))
     }
 
     async fn start_rendering(
         &mut self,
         storage: Self::Storage,
         _image_id: ImageId,
+        _playback: Playback,
     ) -> CyberpixieResult<Self::RenderTask> {
+        self.render_starts.lock().unwrap().push(Instant::now());
         Ok(storage)
     }
 
@@ -61,20 +108,70 @@ impl Board for BoardStub {
     }
 
     fn firmware_info(&self) -> FirmwareInfo {
-        FirmwareInfo
+        FirmwareInfo {
+            version: "0.3.0".into(),
+            board_name: "stub".into(),
+            build_id: "test".into(),
+            max_strip_len: 48,
+            max_refresh_rate: Hertz(500),
+            storage_capacity: 4 * 1024 * 1024,
+        }
+    }
+
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    async fn sleep_until(&self, time: Duration) {
+        tokio::time::sleep(time.saturating_sub(self.now())).await;
+    }
+
+    fn random_seed(&self) -> u64 {
+        RandomState::new().build_hasher().finish()
+    }
+
+    async fn stream_lines<R: AsyncRead>(
+        &mut self,
+        config: Configuration,
+        _refresh_rate: Hertz,
+        mut lines: PayloadReader<R>,
+    ) -> CyberpixieResult<()> {
+        let mut line =
+            vec![0_u8; usize::from(config.strip_len) * config.pixel_format.bytes_per_pixel()];
+        while lines.bytes_remaining() != 0 {
+            lines
+                .read_exact(&mut line)
+                .await
+                .map_err(CyberpixieError::network)?;
+            self.streamed_lines.lock().unwrap().push(line.clone());
+        }
+        Ok(())
+    }
+
+    async fn clear_strip(&mut self) {
+        *self.strip_clears.lock().unwrap() += 1;
     }
 }
 
-async fn create_loopback(
-    socket: &mut TokioSocket,
-    port: u16,
-) -> (JoinHandle<CyberpixieResult<()>>, Client<TokioConnection>) {
+async fn spawn_app(port: u16) -> JoinHandle<CyberpixieResult<()>> {
+    spawn_app_with_board(BoardStub::default(), port).await
+}
+
+async fn spawn_app_with_board(board: BoardStub, port: u16) -> JoinHandle<CyberpixieResult<()>> {
     let _ = env_logger::try_init();
     // Create a thread with an application instance
-    let app = App::with_port(BoardStub::default(), port).unwrap();
+    let app = App::with_port(board, port).unwrap();
     let app_handle = tokio::spawn(app.run());
     // Wait until the socket will be ready to listen a client connection.
     tokio::time::sleep(Duration::from_millis(50)).await;
+    app_handle
+}
+
+async fn create_loopback(
+    socket: &mut TokioSocket,
+    port: u16,
+) -> (JoinHandle<CyberpixieResult<()>>, Client<TokioConnection>) {
+    let app_handle = spawn_app(port).await;
 
     // Create a Cyberpixie client and connect with an application.
     let client = Client::connect(socket, (Ipv6Addr::LOCALHOST, port))
@@ -87,6 +184,18 @@ async fn device_info(client: &mut Client<TokioConnection>) -> DeviceInfo {
     client.peer_info().await.unwrap().device_info.unwrap()
 }
 
+/// Sends the already encoded message header without payload.
+async fn send_raw_header(connection: &mut TokioConnection, header: &[u8]) {
+    let mut packet = [0_u8; Packet::PACKED_LEN];
+    Packet {
+        header_len: header.len() as u32,
+        payload_len: 0,
+    }
+    .encode_as_le_bytes(&mut packet);
+    connection.write_all(&packet).await.unwrap();
+    connection.write_all(header).await.unwrap();
+}
+
 #[tokio::test]
 async fn test_simple_handshake() {
     let mut stack = TokioStack;
@@ -94,12 +203,125 @@ async fn test_simple_handshake() {
 
     let info = client.peer_info().await.unwrap();
     assert_eq!(info.role, DeviceRole::Main);
+    assert_eq!(client.capabilities(), Capabilities::ALL);
 
     client.debug("Hello debug").await.unwrap();
     client.debug("Hello debug 2").await.unwrap();
     drop(client);
 }
 
+#[tokio::test]
+async fn test_firmware_info() {
+    let mut stack = TokioStack;
+    let (_app, mut client) = create_loopback(&mut stack.socket(), 10_237).await;
+
+    let info = client.firmware_info().await.unwrap();
+    assert_eq!(info, BoardStub::default().firmware_info());
+}
+
+#[tokio::test]
+async fn test_handshake_unsupported_version() {
+    let _app = spawn_app(10_236).await;
+
+    let mut socket = TokioStack.socket();
+    let mut connection = Connection::incoming(
+        socket
+            .connect((Ipv6Addr::LOCALHOST, 10_236).into())
+            .await
+            .unwrap(),
+    );
+    // Pretend to be a client with an outdated protocol.
+    connection
+        .send_message(RequestHeader::Handshake(PeerInfo {
+            version: MIN_PROTOCOL_VERSION - 1,
+            ..PeerInfo::client()
+        }))
+        .await
+        .unwrap();
+    let response = connection.receive_response().await.unwrap();
+    assert_eq!(
+        response.header.handshake(),
+        Err(CyberpixieError::UnsupportedProtocolVersion)
+    );
+}
+
+#[tokio::test]
+async fn test_handshake_old_client() {
+    let _app = spawn_app(10_267).await;
+
+    let mut socket = TokioStack.socket();
+    let mut connection = socket
+        .connect((Ipv6Addr::LOCALHOST, 10_267).into())
+        .await
+        .unwrap();
+    // The first protocol version sent the handshake without the version and capabilities:
+    // the handshake tag, the client role, no group and no device information.
+    send_raw_header(&mut connection, &[0, 0, 0, 0]).await;
+    let mut connection = Connection::incoming(connection);
+    let response = connection.receive_response().await.unwrap();
+    assert_eq!(
+        response.header.handshake(),
+        Err(CyberpixieError::UnsupportedProtocolVersion)
+    );
+}
+
+#[tokio::test]
+async fn test_handshake_old_device() {
+    let device = tokio::spawn(async move {
+        let mut socket = TokioStack.socket();
+        let mut connection = socket.accept(10_268).await.unwrap();
+        let mut buf = [0_u8; Packet::MAX_LEN];
+        let _ = connection.read(&mut buf).await.unwrap();
+        // The handshake response of the first protocol version: the handshake tag, the main
+        // device role, no group and no device information.
+        send_raw_header(&mut connection, &[1, 1, 0, 0]).await;
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let result = Client::connect(&mut TokioStack.socket(), (Ipv6Addr::LOCALHOST, 10_268)).await;
+    assert_eq!(
+        result.err(),
+        Some(CyberpixieError::UnsupportedProtocolVersion)
+    );
+    device.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_handshake_downgrade() {
+    // Pretend to be a device with the oldest supported protocol and without the authentication.
+    let old_info = PeerInfo {
+        role: DeviceRole::Main,
+        version: MIN_PROTOCOL_VERSION,
+        capabilities: Capabilities(Capabilities::ALL.0 & !Capabilities::AUTH.0),
+        ..PeerInfo::client()
+    };
+    let device = tokio::spawn(async move {
+        let mut socket = TokioStack.socket();
+        let mut connection = Connection::incoming(socket.accept(10_261).await.unwrap());
+        let request = connection.receive_request().await.unwrap();
+        assert!(matches!(request.header, RequestHeader::Handshake(_)));
+        connection
+            .send_message(ResponseHeader::Handshake(old_info))
+            .await
+            .unwrap();
+        // Keep the connection open until the client closes it.
+        let _ = connection.receive_request().await;
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut client = Client::connect(&mut TokioStack.socket(), (Ipv6Addr::LOCALHOST, 10_261))
+        .await
+        .unwrap();
+    assert_eq!(client.capabilities(), old_info.capabilities);
+    // Features the peer lacks are refused without sending the request.
+    assert_eq!(
+        client.set_auth_key(None).await,
+        Err(CyberpixieError::Unsupported)
+    );
+    drop(client);
+    device.await.unwrap();
+}
+
 #[tokio::test]
 async fn test_images_logic() {
     let image_data = [1_u8; 72];
@@ -111,6 +333,12 @@ async fn test_images_logic() {
     let id = client.add_image(Hertz(50), 24, &image_data).await.unwrap();
     assert_eq!(id, ImageId(0));
     assert_eq!(device_info(&mut client).await.images_count, ImageId(1));
+    // Read image back.
+    let mut image = Vec::new();
+    let info = client.read_image(id, &mut image).await.unwrap();
+    assert_eq!(info.refresh_rate, Hertz(50));
+    assert_eq!(info.strip_len, 24);
+    assert_eq!(image, image_data);
     // Start a first image rendering.
     client.start(id).await.unwrap();
     let info = device_info(&mut client).await;
@@ -124,6 +352,40 @@ async fn test_images_logic() {
     assert!(!info.active);
     assert_eq!(id, ImageId(1));
 
+    // List the stored images.
+    let mut images = Vec::new();
+    assert_eq!(client.list_images(&mut images).await.unwrap(), ImageId(2));
+    assert_eq!(images.len(), 2);
+    assert_eq!(images[0].len, 72);
+    assert_eq!(images[0].lines, 1);
+    assert_eq!(images[0].refresh_rate, Hertz(50));
+    assert_eq!(images[1].refresh_rate, Hertz(250));
+    // Images metadata is read without interrupting the rendering.
+    client.start(id).await.unwrap();
+    client.list_images(&mut Vec::new()).await.unwrap();
+    assert!(device_info(&mut client).await.active);
+
+    // Add a named image and look it up.
+    let named_id = client
+        .add_image_with_info(
+            ImageInfo {
+                name: Some("nyan".into()),
+                ..ImageInfo::new(Hertz(100), 24)
+            },
+            &image_data,
+        )
+        .await
+        .unwrap();
+    // Looking the image up doesn't interrupt the rendering.
+    client.start(named_id).await.unwrap();
+    assert_eq!(client.find_image("nyan").await, Ok(named_id));
+    assert_eq!(
+        client.find_image("cat").await,
+        Err(CyberpixieError::ImageNotFound)
+    );
+    assert!(device_info(&mut client).await.active);
+    client.delete_image(named_id).await.unwrap();
+
     // Start and stop second rendering.
     client.start(id).await.unwrap();
     client.stop().await.unwrap();
@@ -131,6 +393,16 @@ async fn test_images_logic() {
     assert!(!info.active);
     assert_eq!(info.current_image, Some(ImageId(1)));
 
+    // Delete the first picture.
+    client.delete_image(ImageId(0)).await.unwrap();
+    let info = device_info(&mut client).await;
+    assert_eq!(info.current_image, Some(ImageId(0)));
+    assert_eq!(info.images_count, ImageId(1));
+    assert_eq!(
+        client.delete_image(ImageId(1)).await,
+        Err(CyberpixieError::ImageNotFound)
+    );
+
     // Clear pictures stored in the device.
     client.clear_images().await.unwrap();
     let info = device_info(&mut client).await;
@@ -147,9 +419,1097 @@ async fn test_images_logic() {
         Err(CyberpixieError::ImageLengthMismatch),
     );
 
-    // Try to show image
+    // Try to read and show image
+    assert_eq!(
+        client.read_image(ImageId(0), &mut Vec::new()).await,
+        Err(CyberpixieError::ImageNotFound)
+    );
     assert_eq!(
         client.start(ImageId(0)).await,
         Err(CyberpixieError::ImageNotFound)
     );
 }
+
+#[tokio::test]
+async fn test_remote_config() {
+    let image_data = [1_u8; 72];
+
+    let mut stack = TokioStack;
+    let (_app, mut client) = create_loopback(&mut stack.socket(), 10_238).await;
+
+    assert_eq!(client.config().await.unwrap(), Configuration::default());
+
+    // Update the current image without breaking changes.
+    let id = client.add_image(Hertz(50), 24, &image_data).await.unwrap();
+    let config = Configuration {
+        current_image: Some(id),
+        ..Configuration::default()
+    };
+    client.set_config(config.clone()).await.unwrap();
+    assert_eq!(client.config().await.unwrap(), config);
+    assert_eq!(device_info(&mut client).await.images_count, ImageId(1));
+
+    // Update the color correction settings and the device name.
+    let config = Configuration {
+        brightness: 128,
+        gamma: Gamma(22),
+        color_order: ColorOrder::Grb,
+        name: Some("stage-left".into()),
+        ..config
+    };
+    client.set_config(config.clone()).await.unwrap();
+    assert_eq!(client.config().await.unwrap(), config);
+    // The configuration is read without interrupting the rendering.
+    client.start(id).await.unwrap();
+    assert_eq!(client.config().await.unwrap(), config);
+    assert!(device_info(&mut client).await.active);
+
+    // Try to apply incorrect configurations.
+    assert_eq!(
+        client
+            .set_config(Configuration {
+                gamma: Gamma(0),
+                ..config.clone()
+            })
+            .await,
+        Err(CyberpixieError::InvalidConfiguration)
+    );
+    assert_eq!(
+        client
+            .set_config(Configuration {
+                current_image: Some(ImageId(1)),
+                ..config.clone()
+            })
+            .await,
+        Err(CyberpixieError::ImageNotFound)
+    );
+    assert_eq!(
+        client
+            .set_config(Configuration {
+                strip_len: 49,
+                ..config.clone()
+            })
+            .await,
+        Err(CyberpixieError::StripLengthMismatch)
+    );
+
+    // Changing the strip length removes all images.
+    client
+        .set_config(Configuration {
+            strip_len: 48,
+            ..config
+        })
+        .await
+        .unwrap();
+    let info = device_info(&mut client).await;
+    assert_eq!(info.strip_len, 48);
+    assert_eq!(info.images_count, ImageId(0));
+    assert_eq!(info.current_image, None);
+}
+
+#[tokio::test]
+async fn test_rle_images() {
+    let mut stack = TokioStack;
+    let (_app, mut client) = create_loopback(&mut stack.socket(), 10_239).await;
+
+    // Two lines of the 24 pixels strip.
+    let rle_info = ImageInfo {
+        encoding: ImageEncoding::Rle,
+        ..ImageInfo::new(Hertz(50), 24)
+    };
+    let image_data = [[40_u8, 1, 2, 3], [8, 4, 5, 6]].concat();
+    let id = client
+        .add_image_with_info(rle_info.clone(), &image_data)
+        .await
+        .unwrap();
+
+    let mut images = Vec::new();
+    client.list_images(&mut images).await.unwrap();
+    assert_eq!(images[0].encoding, ImageEncoding::Rle);
+    assert_eq!(images[0].len, 8);
+    assert_eq!(images[0].lines, 2);
+    // Image is read back as is.
+    let mut image = Vec::new();
+    let info = client.read_image(id, &mut image).await.unwrap();
+    assert_eq!(info, rle_info);
+    assert_eq!(image, image_data);
+
+    // Try to add incorrect images.
+    assert_eq!(
+        client
+            .add_image_with_info(rle_info.clone(), &image_data[0..6])
+            .await,
+        Err(CyberpixieError::ImageLengthMismatch),
+    );
+    assert_eq!(
+        client
+            .add_image_with_info(rle_info.clone(), &image_data[0..4])
+            .await,
+        Err(CyberpixieError::ImageLengthMismatch),
+    );
+    // The trailing empty run makes the decoder read past the image end.
+    let trailing_empty = [image_data.as_slice(), &[0, 7, 8, 9]].concat();
+    assert_eq!(
+        client.add_image_with_info(rle_info, &trailing_empty).await,
+        Err(CyberpixieError::ImageLengthMismatch),
+    );
+    // Make sure that the incorrect images have been removed.
+    assert_eq!(device_info(&mut client).await.images_count, ImageId(1));
+}
+
+#[tokio::test]
+async fn test_palette_images() {
+    let mut stack = TokioStack;
+    let (_app, mut client) = create_loopback(&mut stack.socket(), 10_240).await;
+
+    let palette_info = ImageInfo {
+        encoding: ImageEncoding::Palette,
+        ..ImageInfo::new(Hertz(50), 24)
+    };
+    // Palette block with two colors followed by the two lines of the 24 pixels strip.
+    let image_data = [&[1_u8, 1, 2, 3, 4, 5, 6][..], &[0_u8; 24], &[1_u8; 24]].concat();
+    client
+        .add_image_with_info(palette_info.clone(), &image_data)
+        .await
+        .unwrap();
+
+    let mut images = Vec::new();
+    client.list_images(&mut images).await.unwrap();
+    assert_eq!(images[0].encoding, ImageEncoding::Palette);
+    assert_eq!(images[0].lines, 2);
+
+    // Try to add incorrect images.
+    assert_eq!(
+        client
+            .add_image_with_info(palette_info.clone(), &image_data[0..7])
+            .await,
+        Err(CyberpixieError::ImageLengthMismatch),
+    );
+    assert_eq!(
+        client
+            .add_image_with_info(palette_info, &image_data[0..30])
+            .await,
+        Err(CyberpixieError::ImageLengthMismatch),
+    );
+    assert_eq!(device_info(&mut client).await.images_count, ImageId(1));
+}
+
+#[tokio::test]
+async fn test_rgbw_images() {
+    let mut stack = TokioStack;
+    let (_app, mut client) = create_loopback(&mut stack.socket(), 10_241).await;
+
+    let rgbw_info = ImageInfo {
+        pixel_format: PixelFormat::Rgbw,
+        ..ImageInfo::new(Hertz(50), 24)
+    };
+    let image_data = [1_u8; 24 * 4 * 2];
+    let id = client
+        .add_image_with_info(rgbw_info.clone(), &image_data)
+        .await
+        .unwrap();
+
+    let mut images = Vec::new();
+    client.list_images(&mut images).await.unwrap();
+    assert_eq!(images[0].pixel_format, PixelFormat::Rgbw);
+    assert_eq!(images[0].lines, 2);
+    let mut image = Vec::new();
+    let info = client.read_image(id, &mut image).await.unwrap();
+    assert_eq!(info, rgbw_info);
+
+    // Try to add incorrect images.
+    assert_eq!(
+        client
+            .add_image_with_info(rgbw_info.clone(), &image_data[0..72])
+            .await,
+        Err(CyberpixieError::ImageLengthMismatch),
+    );
+    assert_eq!(
+        client
+            .add_image_with_info(
+                ImageInfo {
+                    encoding: ImageEncoding::Rle,
+                    ..rgbw_info
+                },
+                &image_data[0..10]
+            )
+            .await,
+        Err(CyberpixieError::Unsupported),
+    );
+}
+
+#[tokio::test]
+async fn test_show_image_with_playback() {
+    let mut stack = TokioStack;
+    let (_app, mut client) = create_loopback(&mut stack.socket(), 10_242).await;
+
+    let raw_id = client
+        .add_image(Hertz(50), 24, &[1_u8; 24 * 3 * 2])
+        .await
+        .unwrap();
+    let rle_id = client
+        .add_image_with_info(
+            ImageInfo {
+                encoding: ImageEncoding::Rle,
+                ..ImageInfo::new(Hertz(50), 24)
+            },
+            &[48_u8, 1, 2, 3],
+        )
+        .await
+        .unwrap();
+
+    let reverse = Playback {
+        direction: PlaybackDirection::Reverse,
+        repeat: Some(3),
+    };
+    client.start_with_playback(raw_id, reverse).await.unwrap();
+    let info = device_info(&mut client).await;
+    assert!(info.active);
+    assert_eq!(info.current_image, Some(raw_id));
+
+    // Run-length encoded images can be played only forward.
+    assert_eq!(
+        client.start_with_playback(rle_id, reverse).await,
+        Err(CyberpixieError::Unsupported)
+    );
+    client
+        .start_with_playback(rle_id, Playback::ONCE)
+        .await
+        .unwrap();
+    assert_eq!(device_info(&mut client).await.current_image, Some(rle_id));
+}
+
+#[tokio::test]
+async fn test_playlists() {
+    let mut stack = TokioStack;
+    let (_app, mut client) = create_loopback(&mut stack.socket(), 10_243).await;
+
+    for i in 0..3 {
+        client
+            .add_image(Hertz(50), 24, &[i; 24 * 3 * 2])
+            .await
+            .unwrap();
+    }
+
+    let mut playlist = Playlist::default();
+    for (image_id, length) in [
+        (ImageId(2), EntryLength::Millis(200)),
+        (ImageId(0), EntryLength::Loops(10)),
+    ] {
+        playlist
+            .entries
+            .push(PlaylistEntry { image_id, length })
+            .unwrap();
+    }
+    let playlist_id = client.add_playlist(&playlist).await.unwrap();
+    assert_eq!(playlist_id, PlaylistId(0));
+
+    let mut playlists = Vec::new();
+    let count = client.list_playlists(&mut playlists).await.unwrap();
+    assert_eq!(count, PlaylistId(1));
+    assert_eq!(playlists, [playlist.clone()]);
+
+    // Try to add incorrect playlists.
+    assert_eq!(
+        client.add_playlist(&Playlist::default()).await,
+        Err(CyberpixieError::InvalidPlaylist)
+    );
+    let mut missing_image = Playlist::default();
+    missing_image
+        .entries
+        .push(PlaylistEntry {
+            image_id: ImageId(3),
+            length: EntryLength::Loops(1),
+        })
+        .unwrap();
+    assert_eq!(
+        client.add_playlist(&missing_image).await,
+        Err(CyberpixieError::ImageNotFound)
+    );
+
+    // Start the playlist and wait for the next entry.
+    client.start_playlist(playlist_id).await.unwrap();
+    let info = device_info(&mut client).await;
+    assert!(info.active);
+    assert_eq!(info.current_image, Some(ImageId(2)));
+    assert_eq!(
+        info.playlist,
+        Some(PlaylistPosition {
+            playlist_id,
+            entry: 0
+        })
+    );
+
+    // Listing the playlists doesn't stop the running one.
+    let mut running = Vec::new();
+    client.list_playlists(&mut running).await.unwrap();
+    assert_eq!(running, [playlist.clone()]);
+    let info = device_info(&mut client).await;
+    assert!(info.active);
+    assert_eq!(info.playlist.unwrap().entry, 0);
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let info = device_info(&mut client).await;
+    assert_eq!(info.current_image, Some(ImageId(0)));
+    assert_eq!(info.playlist.unwrap().entry, 1);
+
+    // Ten loops of the two lines image at 50Hz take 400ms, then the playlist starts again.
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    let info = device_info(&mut client).await;
+    assert_eq!(info.current_image, Some(ImageId(2)));
+    assert_eq!(info.playlist.unwrap().entry, 0);
+
+    // Showing a single image stops the playlist.
+    client.start(ImageId(1)).await.unwrap();
+    assert_eq!(device_info(&mut client).await.playlist, None);
+
+    // Deleted images are removed from the playlist.
+    client.delete_image(ImageId(0)).await.unwrap();
+    playlists.clear();
+    client.list_playlists(&mut playlists).await.unwrap();
+    assert_eq!(
+        playlists[0].entries,
+        [PlaylistEntry {
+            image_id: ImageId(1),
+            length: EntryLength::Millis(200)
+        }]
+    );
+
+    client.delete_playlist(playlist_id).await.unwrap();
+    assert_eq!(
+        client.start_playlist(playlist_id).await,
+        Err(CyberpixieError::PlaylistNotFound)
+    );
+}
+
+#[tokio::test]
+async fn test_autoplay() {
+    for (autoplay, port) in [(false, 10_244), (true, 10_245)] {
+        // Prepare the storage of the device before the boot.
+        let mut storage = init_storage(Configuration::default());
+        let image_id = storage
+            .add_image(ImageInfo::new(Hertz(50), 24), &[1_u8; 72][..])
+            .await
+            .unwrap();
+        storage
+            .set_config(Configuration {
+                current_image: Some(image_id),
+                autoplay,
+                ..Configuration::default()
+            })
+            .unwrap();
+
+        let _app = spawn_app_with_board(BoardStub::with_storage(storage), port).await;
+        let mut client = Client::connect(&mut TokioStack.socket(), (Ipv6Addr::LOCALHOST, port))
+            .await
+            .unwrap();
+        let info = device_info(&mut client).await;
+        assert_eq!(info.active, autoplay);
+        assert_eq!(info.current_image, Some(image_id));
+        assert_eq!(client.config().await.unwrap().autoplay, autoplay);
+    }
+}
+
+#[tokio::test]
+async fn test_stream_lines() {
+    let board = BoardStub::default();
+    let streamed_lines = board.streamed_lines.clone();
+    let _app = spawn_app_with_board(board, 10_246).await;
+    let mut client = Client::connect(&mut TokioStack.socket(), (Ipv6Addr::LOCALHOST, 10_246))
+        .await
+        .unwrap();
+
+    let lines = [1_u8; 24 * 3 * 3];
+    client.stream(Hertz(100), &lines).await.unwrap();
+    assert_eq!(streamed_lines.lock().unwrap().len(), 3);
+    // Streamed lines are not saved.
+    let info = device_info(&mut client).await;
+    assert!(!info.active);
+    assert_eq!(info.images_count, ImageId(0));
+
+    // Try to stream incorrect lines.
+    assert_eq!(
+        client.stream(Hertz(100), &lines[0..100]).await,
+        Err(CyberpixieError::ImageLengthMismatch)
+    );
+    assert_eq!(
+        client.stream(Hertz(0), &lines).await,
+        Err(CyberpixieError::InvalidConfiguration)
+    );
+    assert_eq!(streamed_lines.lock().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_dmx_input() {
+    let _ = env_logger::try_init();
+    let board = BoardStub::default();
+    let streamed_lines = board.streamed_lines.clone();
+    let strip_clears = board.strip_clears.clone();
+    let app = App::with_port(board, 10_247)
+        .unwrap()
+        .with_dmx_input(TokioStack)
+        .with_dmx_port(10_248);
+    let _app = tokio::spawn(app.run());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let artnet_packet = |universe: u8, data: &[u8]| {
+        let mut packet = b"Art-Net\0".to_vec();
+        packet.extend(0x5000_u16.to_le_bytes());
+        packet.extend([0, 14, 0, 0, universe, 0]);
+        packet.extend(u16::try_from(data.len()).unwrap().to_be_bytes());
+        packet.extend(data);
+        packet
+    };
+    let sender = tokio::net::UdpSocket::bind((Ipv6Addr::LOCALHOST, 0))
+        .await
+        .unwrap();
+    let send_packets = || async {
+        // Only the packets of the selected universe are shown.
+        for (universe, value) in [(0, 1), (1, 2)] {
+            sender
+                .send_to(
+                    &artnet_packet(universe, &[value; 24]),
+                    (Ipv6Addr::LOCALHOST, 10_248),
+                )
+                .await
+                .unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    };
+
+    // DMX input is disabled by default.
+    send_packets().await;
+    assert!(streamed_lines.lock().unwrap().is_empty());
+
+    // DMX input does not prevent the client connections.
+    let mut client = Client::connect(&mut TokioStack.socket(), (Ipv6Addr::LOCALHOST, 10_247))
+        .await
+        .unwrap();
+    client
+        .set_config(Configuration {
+            dmx_input: true,
+            dmx_universe: 1,
+            ..Configuration::default()
+        })
+        .await
+        .unwrap();
+    assert!(!device_info(&mut client).await.active);
+    send_packets().await;
+
+    let mut expected_line = vec![0_u8; 24 * 3];
+    expected_line[0..24].fill(2);
+    assert_eq!(*streamed_lines.lock().unwrap(), [expected_line.clone()]);
+
+    // The strip is cleared when a client request interrupts the DMX input.
+    let clears = *strip_clears.lock().unwrap();
+    assert!(!device_info(&mut client).await.active);
+    assert!(*strip_clears.lock().unwrap() > clears);
+
+    // E1.31 universes are selected in the same way.
+    client
+        .set_config(Configuration {
+            dmx_input: true,
+            dmx_protocol: DmxProtocol::E131,
+            dmx_universe: 2,
+            ..Configuration::default()
+        })
+        .await
+        .unwrap();
+    let mut e131_packet = vec![0_u8; 126];
+    e131_packet[4..16].copy_from_slice(b"ASC-E1.17\0\0\0");
+    e131_packet[18..22].copy_from_slice(&4_u32.to_be_bytes());
+    e131_packet[40..44].copy_from_slice(&2_u32.to_be_bytes());
+    e131_packet[113..115].copy_from_slice(&2_u16.to_be_bytes());
+    e131_packet[117] = 0x02;
+    e131_packet[123..125].copy_from_slice(&25_u16.to_be_bytes());
+    e131_packet.extend([3; 24]);
+    sender
+        .send_to(&e131_packet, (Ipv6Addr::LOCALHOST, 10_248))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut e131_line = vec![0_u8; 24 * 3];
+    e131_line[0..24].fill(3);
+    assert_eq!(
+        *streamed_lines.lock().unwrap(),
+        [expected_line.clone(), e131_line.clone()]
+    );
+
+    // DMX input is ignored while the device has a key.
+    client
+        .set_auth_key(Some(AuthKey::from_slice(b"secret").unwrap()))
+        .await
+        .unwrap();
+    send_packets().await;
+    assert_eq!(*streamed_lines.lock().unwrap(), [expected_line, e131_line]);
+}
+
+#[tokio::test]
+async fn test_forward_to_secondaries() {
+    let _ = env_logger::try_init();
+    let app = App::with_port(main_board(), 10_249)
+        .unwrap()
+        .with_secondary_port(10_250);
+    let _app = tokio::spawn(app.run());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // Pretend to be a secondary device which fails to clear images.
+    let mut socket = TokioStack.socket();
+    let mut secondary = Connection::incoming(
+        socket
+            .connect((Ipv6Addr::LOCALHOST, 10_250).into())
+            .await
+            .unwrap(),
+    );
+    secondary
+        .send_message(RequestHeader::Handshake(PeerInfo {
+            role: DeviceRole::Secondary,
+            group_id: Some(42),
+            ..PeerInfo::client()
+        }))
+        .await
+        .unwrap();
+    let main_info = secondary
+        .receive_response()
+        .await
+        .unwrap()
+        .header
+        .handshake()
+        .unwrap();
+    assert_eq!(main_info.role, DeviceRole::Main);
+
+    let forwarded = Arc::new(Mutex::new(Vec::new()));
+    let secondary_forwarded = forwarded.clone();
+    tokio::spawn(async move {
+        loop {
+            let mut request = secondary.receive_request().await.unwrap();
+            let header = request.header.clone();
+            // Pretend that the secondary device has been started with the main one.
+            if header == RequestHeader::SyncClock {
+                let response = ResponseHeader::SyncClock(DeviceTime(0));
+                secondary.send_message(response).await.unwrap();
+                continue;
+            }
+            let payload_len = match request.payload.take() {
+                Some(payload) => {
+                    let len = payload.len();
+                    payload.skip().await.unwrap();
+                    len
+                }
+                None => 0,
+            };
+            secondary_forwarded
+                .lock()
+                .unwrap()
+                .push((header.clone(), payload_len));
+
+            let response = match header {
+                RequestHeader::AddImage(_) => ResponseHeader::AddImage(ImageId(0)),
+                RequestHeader::ClearImages => ResponseHeader::Error(CyberpixieError::Internal),
+                _ => ResponseHeader::Empty,
+            };
+            secondary.send_message(response).await.unwrap();
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut client = Client::connect(&mut TokioStack.socket(), (Ipv6Addr::LOCALHOST, 10_249))
+        .await
+        .unwrap();
+    assert_eq!(client.secondary_results().await.unwrap(), []);
+
+    let image = [1_u8; 24 * 3 * 2];
+    let image_id = client.add_image(Hertz(50), 24, &image).await.unwrap();
+    client.start(image_id).await.unwrap();
+    let success = SecondaryResult {
+        group_id: Some(42),
+        error: None,
+    };
+    assert_eq!(client.secondary_results().await.unwrap(), [success]);
+    // Requests that are not forwarded do not change the results.
+    client.config().await.unwrap();
+    assert_eq!(client.secondary_results().await.unwrap(), [success]);
+
+    // The request succeeds even if the secondary device fails it.
+    client.clear_images().await.unwrap();
+    assert_eq!(
+        client.secondary_results().await.unwrap(),
+        [SecondaryResult {
+            group_id: Some(42),
+            error: Some(CyberpixieError::Internal),
+        }]
+    );
+    // The secondary device has kept its images, so the new image would get another index.
+    let image_id = client.add_image(Hertz(50), 24, &image).await.unwrap();
+    client.start(image_id).await.unwrap();
+    assert_eq!(
+        client.secondary_results().await.unwrap(),
+        [SecondaryResult {
+            group_id: Some(42),
+            error: Some(CyberpixieError::ImageIdMismatch),
+        }]
+    );
+
+    let forwarded = forwarded.lock().unwrap().clone();
+    assert_eq!(forwarded.len(), 3);
+    assert_eq!(
+        forwarded[0],
+        (
+            RequestHeader::AddImage(ImageInfo::new(Hertz(50), 24)),
+            image.len()
+        )
+    );
+    // The image start is scheduled in the secondary device time.
+    let (RequestHeader::ShowImageAt(forwarded_id, playback, time), 0) = forwarded[1] else {
+        panic!("Unexpected forwarded request: {:?}", forwarded[1]);
+    };
+    assert_eq!((forwarded_id, playback), (image_id, Playback::default()));
+    assert!(time > DeviceTime(250_000) && time <= DeviceTime(500_000));
+    assert_eq!(forwarded[2], (RequestHeader::ClearImages, 0));
+}
+
+#[tokio::test]
+async fn test_secondary_timeout() {
+    let _ = env_logger::try_init();
+    let app = App::with_port(main_board(), 10_262)
+        .unwrap()
+        .with_secondary_port(10_263);
+    let _app = tokio::spawn(app.run());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // Pretend to be a secondary device which stops answering after the handshake.
+    let secondary = tokio::spawn(async {
+        let mut socket = TokioStack.socket();
+        let mut secondary = Connection::incoming(
+            socket
+                .connect((Ipv6Addr::LOCALHOST, 10_263).into())
+                .await
+                .unwrap(),
+        );
+        secondary
+            .send_message(RequestHeader::Handshake(PeerInfo {
+                role: DeviceRole::Secondary,
+                group_id: Some(42),
+                ..PeerInfo::client()
+            }))
+            .await
+            .unwrap();
+        secondary.receive_response().await.unwrap();
+        // Keep the connection open until the main device closes it.
+        while secondary.receive_request().await.is_ok() {}
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut client = Client::connect(&mut TokioStack.socket(), (Ipv6Addr::LOCALHOST, 10_262))
+        .await
+        .unwrap();
+    client.stop().await.unwrap();
+    assert_eq!(
+        client.secondary_results().await.unwrap(),
+        [SecondaryResult {
+            group_id: Some(42),
+            error: Some(CyberpixieError::Network),
+        }]
+    );
+    // The secondary device has been disconnected.
+    client.stop().await.unwrap();
+    assert_eq!(client.secondary_results().await.unwrap(), []);
+    secondary.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_secondary_auth() {
+    let _ = env_logger::try_init();
+    let key = AuthKey::from_slice(b"secret").unwrap();
+    let mut storage = init_storage(Configuration {
+        accept_secondaries: true,
+        ..Configuration::default()
+    });
+    storage.set_auth_key(Some(key.clone())).unwrap();
+    let main = App::with_port(BoardStub::with_storage(storage), 10_269)
+        .unwrap()
+        .with_secondary_port(10_270);
+    let _main = tokio::spawn(main.run());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // Secondary device without the key is refused.
+    let mut socket = TokioStack.socket();
+    let mut impostor = Connection::incoming(
+        socket
+            .connect((Ipv6Addr::LOCALHOST, 10_270).into())
+            .await
+            .unwrap(),
+    );
+    impostor
+        .send_message(RequestHeader::Handshake(PeerInfo {
+            role: DeviceRole::Secondary,
+            group_id: Some(13),
+            ..PeerInfo::client()
+        }))
+        .await
+        .unwrap();
+    let response = impostor.receive_response().await.unwrap().header;
+    let ResponseHeader::AuthChallenge(challenge) = response else {
+        panic!("Unexpected response: {response:?}");
+    };
+    impostor
+        .send_message(RequestHeader::Authenticate(auth::sign(
+            b"guess", &challenge,
+        )))
+        .await
+        .unwrap();
+    assert_eq!(
+        impostor
+            .receive_response()
+            .await
+            .unwrap()
+            .header
+            .handshake(),
+        Err(CyberpixieError::AuthenticationFailed)
+    );
+
+    // Secondary device with the same key is accepted.
+    let mut storage = init_storage(Configuration {
+        main_device: Some(MainDevice::new(
+            (Ipv6Addr::LOCALHOST, 10_270).into(),
+            Some(7),
+        )),
+        ..Configuration::default()
+    });
+    storage.set_auth_key(Some(key)).unwrap();
+    let secondary = App::with_port(BoardStub::with_storage(storage), 10_271).unwrap();
+    let _secondary = tokio::spawn(secondary.run());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = Client::connect_with_key(
+        &mut TokioStack.socket(),
+        (Ipv6Addr::LOCALHOST, 10_269),
+        Some(b"secret"),
+    )
+    .await
+    .unwrap();
+    client.stop().await.unwrap();
+    assert_eq!(
+        client.secondary_results().await.unwrap(),
+        [SecondaryResult {
+            group_id: Some(7),
+            error: None,
+        }]
+    );
+}
+
+#[tokio::test]
+async fn test_secondary_app() {
+    let _ = env_logger::try_init();
+    // The secondary device keeps trying to connect until the main one is started.
+    let storage = init_storage(Configuration {
+        main_device: Some(MainDevice::new(
+            (Ipv6Addr::LOCALHOST, 10_252).into(),
+            Some(7),
+        )),
+        ..Configuration::default()
+    });
+    let secondary = App::with_port(BoardStub::with_storage(storage), 10_253).unwrap();
+    let _secondary = tokio::spawn(secondary.run());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let main = App::with_port(main_board(), 10_251)
+        .unwrap()
+        .with_secondary_port(10_252);
+    let _main = tokio::spawn(main.run());
+    tokio::time::sleep(Duration::from_millis(1_500)).await;
+
+    let mut client = Client::connect(&mut TokioStack.socket(), (Ipv6Addr::LOCALHOST, 10_251))
+        .await
+        .unwrap();
+    let success = SecondaryResult {
+        group_id: Some(7),
+        error: None,
+    };
+    let image_id = client
+        .add_image(Hertz(50), 24, &[1_u8; 24 * 3])
+        .await
+        .unwrap();
+    assert_eq!(client.secondary_results().await.unwrap(), [success]);
+    // The image has been stored by the secondary device, so it is able to show it.
+    client.start(image_id).await.unwrap();
+    assert_eq!(client.secondary_results().await.unwrap(), [success]);
+    client.stop().await.unwrap();
+    assert_eq!(client.secondary_results().await.unwrap(), [success]);
+
+    // Images are removed from the secondary device together with the main one.
+    client.delete_image(image_id).await.unwrap();
+    assert_eq!(client.secondary_results().await.unwrap(), [success]);
+    client
+        .add_image(Hertz(50), 24, &[1_u8; 24 * 3])
+        .await
+        .unwrap();
+    client
+        .set_config(Configuration {
+            strip_len: 48,
+            accept_secondaries: true,
+            ..Configuration::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(client.secondary_results().await.unwrap(), [success]);
+    // Both devices store the new image under the same index.
+    let image_id = client
+        .add_image(Hertz(50), 48, &[1_u8; 48 * 3])
+        .await
+        .unwrap();
+    assert_eq!(image_id, ImageId(0));
+    assert_eq!(client.secondary_results().await.unwrap(), [success]);
+}
+
+#[tokio::test]
+async fn test_secondary_reconfiguration() {
+    let _ = env_logger::try_init();
+    // There is no main device, so the secondary device fails to connect to it.
+    let storage = init_storage(Configuration {
+        main_device: Some(MainDevice::new(
+            (Ipv6Addr::LOCALHOST, 10_265).into(),
+            Some(7),
+        )),
+        ..Configuration::default()
+    });
+    let app = App::with_port(BoardStub::with_storage(storage), 10_264).unwrap();
+    let _app = tokio::spawn(app.run());
+
+    // The client connections are accepted in between the attempts.
+    let connect = || async {
+        for _ in 0..30 {
+            let client =
+                Client::connect(&mut TokioStack.socket(), (Ipv6Addr::LOCALHOST, 10_264)).await;
+            if let Ok(client) = client {
+                return client;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("Unable to connect to the secondary device");
+    };
+    let mut client = connect().await;
+    let info = client.peer_info().await.unwrap();
+    assert_eq!((info.role, info.group_id), (DeviceRole::Secondary, Some(7)));
+    let config = client.config().await.unwrap();
+    client
+        .set_config(Configuration {
+            main_device: None,
+            ..config
+        })
+        .await
+        .unwrap();
+    drop(client);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // The device runs as the main one after the client has disconnected.
+    let mut client = connect().await;
+    let info = client.peer_info().await.unwrap();
+    assert_eq!((info.role, info.group_id), (DeviceRole::Main, None));
+}
+
+#[tokio::test]
+async fn test_synchronized_start() {
+    let _ = env_logger::try_init();
+    let board = main_board();
+    let mut render_starts = vec![board.render_starts.clone()];
+    let main = App::with_port(board, 10_254)
+        .unwrap()
+        .with_secondary_port(10_255);
+    let _main = tokio::spawn(main.run());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // Each device has its own clock, so the clocks of the secondary devices are shifted.
+    for (shift, port) in [(10, 10_256), (20, 10_257), (30, 10_266)] {
+        let board = BoardStub {
+            start: Instant::now()
+                .checked_sub(Duration::from_secs(shift))
+                .unwrap(),
+            ..BoardStub::default()
+        };
+        render_starts.push(board.render_starts.clone());
+        let secondary = App::with_port(board, port)
+            .unwrap()
+            .with_main_address((Ipv6Addr::LOCALHOST, 10_255).into(), None);
+        tokio::spawn(secondary.run());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let mut client = Client::connect(&mut TokioStack.socket(), (Ipv6Addr::LOCALHOST, 10_254))
+        .await
+        .unwrap();
+    let image_id = client
+        .add_image(Hertz(50), 24, &[1_u8; 24 * 3])
+        .await
+        .unwrap();
+    client.start(image_id).await.unwrap();
+    let started = Instant::now();
+    let results = client.secondary_results().await.unwrap();
+    assert_eq!(results.len(), 3);
+    assert!(results.iter().all(|result| result.error.is_none()));
+    // Wait until the scheduled start.
+    tokio::time::sleep(Duration::from_millis(1_000)).await;
+
+    let starts = render_starts
+        .iter()
+        .map(|starts| *starts.lock().unwrap().last().unwrap())
+        .collect::<Vec<_>>();
+    let first = *starts.iter().min().unwrap();
+    let last = *starts.iter().max().unwrap();
+    // The start has been scheduled after the request has been sent to every device.
+    assert!(first > started, "{starts:?}");
+    assert!(last - first < Duration::from_millis(20), "{starts:?}");
+
+    // The scheduled image is checked by the device itself.
+    let local_start = Instant::now();
+    let offset = client.clock_offset(|| local_start.elapsed()).await.unwrap();
+    assert!(offset.round_trip < Duration::from_millis(100));
+    assert_eq!(
+        client
+            .start_at(ImageId(42), Playback::default(), DeviceTime(0))
+            .await,
+        Err(CyberpixieError::ImageNotFound)
+    );
+}
+
+#[tokio::test]
+async fn test_discovery() {
+    let _ = env_logger::try_init();
+    let app = App::with_port(BoardStub::default(), 10_258)
+        .unwrap()
+        .with_discovery(TokioStack, 10_259);
+    let _app = tokio::spawn(app.run());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut client = Client::connect(&mut TokioStack.socket(), (Ipv6Addr::LOCALHOST, 10_258))
+        .await
+        .unwrap();
+    client
+        .set_config(Configuration {
+            name: Some("stage-left".into()),
+            ..Configuration::default()
+        })
+        .await
+        .unwrap();
+
+    let mut socket = TokioStack.socket();
+    let mut udp = socket.bind(0).await.unwrap();
+    // Other datagrams are not answered.
+    udp.send_to(b"hello", (Ipv6Addr::LOCALHOST, 10_259).into())
+        .await
+        .unwrap();
+    discovery::send_request(&mut udp, (Ipv6Addr::LOCALHOST, 10_259).into())
+        .await
+        .unwrap();
+    let device = tokio::time::timeout(Duration::from_secs(1), discovery::receive_reply(&mut udp))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(device.address, (Ipv6Addr::LOCALHOST, 10_258).into());
+    assert_eq!(device.name.as_deref(), Some("stage-left"));
+    assert_eq!(device.peer_info.role, DeviceRole::Main);
+    assert_eq!(device.peer_info.capabilities, Capabilities::ALL);
+
+    // The discovered device accepts client connections.
+    drop(client);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let mut client = Client::connect(&mut TokioStack.socket(), device.address)
+        .await
+        .unwrap();
+    assert_eq!(client.config().await.unwrap().name, device.name);
+}
+
+#[tokio::test]
+async fn test_authentication() {
+    let _ = env_logger::try_init();
+    let mut storage = init_storage(Configuration::default());
+    storage
+        .set_auth_key(Some(AuthKey::from_slice(b"secret").unwrap()))
+        .unwrap();
+    let app = App::with_port(BoardStub::with_storage(storage), 10_260).unwrap();
+    let _app = tokio::spawn(app.run());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let address = (Ipv6Addr::LOCALHOST, 10_260);
+
+    // Requests of the client without a key are refused.
+    let mut socket = TokioStack.socket();
+    let mut connection = Connection::incoming(socket.connect(address.into()).await.unwrap());
+    connection
+        .send_message(RequestHeader::ClearImages)
+        .await
+        .unwrap();
+    let response = connection.receive_response().await.unwrap();
+    assert_eq!(
+        response.header.empty(),
+        Err(CyberpixieError::AuthenticationRequired)
+    );
+    // The handshake is answered with a challenge, which cannot be answered twice.
+    connection
+        .send_message(RequestHeader::Handshake(PeerInfo::client()))
+        .await
+        .unwrap();
+    let ResponseHeader::AuthChallenge(challenge) =
+        connection.receive_response().await.unwrap().header
+    else {
+        panic!("Device should send the authentication challenge");
+    };
+    connection
+        .send_message(RequestHeader::Authenticate(auth::sign(
+            b"wrong", &challenge,
+        )))
+        .await
+        .unwrap();
+    let response = connection.receive_response().await.unwrap();
+    assert_eq!(
+        response.header.handshake(),
+        Err(CyberpixieError::AuthenticationFailed)
+    );
+    connection
+        .send_message(RequestHeader::Authenticate(auth::sign(
+            b"secret", &challenge,
+        )))
+        .await
+        .unwrap();
+    let response = connection.receive_response().await.unwrap();
+    assert_eq!(
+        response.header.handshake(),
+        Err(CyberpixieError::AuthenticationFailed)
+    );
+    drop(connection);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    assert_eq!(
+        Client::connect(&mut TokioStack.socket(), address)
+            .await
+            .err(),
+        Some(CyberpixieError::AuthenticationRequired)
+    );
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(
+        Client::connect_with_key(&mut TokioStack.socket(), address, Some(b"wrong"))
+            .await
+            .err(),
+        Some(CyberpixieError::AuthenticationFailed)
+    );
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // Authenticated client is able to remove the key.
+    let mut client = Client::connect_with_key(&mut TokioStack.socket(), address, Some(b"secret"))
+        .await
+        .unwrap();
+    assert_eq!(device_info(&mut client).await.images_count, ImageId(0));
+    client.set_auth_key(None).await.unwrap();
+    drop(client);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut client = Client::connect(&mut TokioStack.socket(), address)
+        .await
+        .unwrap();
+    client.clear_images().await.unwrap();
+    // Empty key is not allowed.
+    assert_eq!(
+        client.set_auth_key(Some(AuthKey::new())).await,
+        Err(CyberpixieError::InvalidConfiguration)
+    );
+}
@@ -0,0 +1,277 @@
+//! Color correction facilities applied to the image lines before they are sent to the strip.
+
+use rgb::RGB8;
+
+use crate::proto::types::{ColorOrder, Configuration, Gamma};
+
+/// A pixel with the additional white channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+pub struct RGBW8 {
+    /// Red channel.
+    pub r: u8,
+    /// Green channel.
+    pub g: u8,
+    /// Blue channel.
+    pub b: u8,
+    /// White channel.
+    pub w: u8,
+}
+
+impl RGBW8 {
+    /// Creates a new RGBW pixel.
+    #[must_use]
+    pub const fn new(r: u8, g: u8, b: u8, w: u8) -> Self {
+        Self { r, g, b, w }
+    }
+
+    /// Converts the given RGB pixel by moving its common white component to the white channel.
+    #[must_use]
+    pub fn extract_white(pixel: RGB8) -> Self {
+        let w = pixel.r.min(pixel.g).min(pixel.b);
+        Self::new(pixel.r - w, pixel.g - w, pixel.b - w, w)
+    }
+
+    /// Returns the RGB pixel with the white channel mixed into the color channels.
+    #[must_use]
+    pub const fn to_rgb(self) -> RGB8 {
+        RGB8 {
+            r: self.r.saturating_add(self.w),
+            g: self.g.saturating_add(self.w),
+            b: self.b.saturating_add(self.w),
+        }
+    }
+}
+
+impl From<RGB8> for RGBW8 {
+    fn from(pixel: RGB8) -> Self {
+        Self::new(pixel.r, pixel.g, pixel.b, 0)
+    }
+}
+
+/// Brightness and gamma correction lookup table.
+///
+/// Each color channel value is replaced by the corresponding table entry, so the correction
+/// is cheap enough to be applied to every line in the render loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorCorrection {
+    lut: [u8; 256],
+}
+
+impl ColorCorrection {
+    /// Color correction that leaves colors unchanged.
+    pub const IDENTITY: Self = Self::identity();
+
+    /// Creates a lookup table for the given brightness and gamma curve.
+    ///
+    /// Each channel value `v` is mapped to `brightness * (v / 255) ^ gamma`.
+    #[must_use]
+    pub fn new(brightness: u8, gamma: Gamma) -> Self {
+        let gamma = gamma.as_f32();
+        let brightness = f32::from(brightness);
+
+        let mut lut = [0_u8; 256];
+        for (value, entry) in (0_u8..=255).zip(lut.iter_mut()) {
+            let normalized = f32::from(value) / 255_f32;
+            let corrected = libm::roundf(libm::powf(normalized, gamma) * brightness);
+            // The corrected value is always in the range `0..=brightness`.
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let corrected = corrected as u8;
+            *entry = corrected;
+        }
+        Self { lut }
+    }
+
+    const fn identity() -> Self {
+        let mut lut = [0_u8; 256];
+        let mut value = 0;
+        while value < lut.len() {
+            // The loop index is lesser than the table length.
+            #[allow(clippy::cast_possible_truncation)]
+            let entry = value as u8;
+            lut[value] = entry;
+            value += 1;
+        }
+        Self { lut }
+    }
+
+    /// Returns `true` if this correction leaves colors unchanged.
+    #[must_use]
+    pub fn is_identity(&self) -> bool {
+        self == &Self::IDENTITY
+    }
+
+    /// Applies correction to the single color channel value.
+    #[must_use]
+    #[inline]
+    pub const fn channel(&self, value: u8) -> u8 {
+        self.lut[value as usize]
+    }
+
+    /// Applies correction to the given pixel.
+    #[must_use]
+    #[inline]
+    pub const fn pixel(&self, pixel: RGB8) -> RGB8 {
+        RGB8 {
+            r: self.channel(pixel.r),
+            g: self.channel(pixel.g),
+            b: self.channel(pixel.b),
+        }
+    }
+
+    /// Applies correction to the given RGBW pixel.
+    #[must_use]
+    #[inline]
+    pub const fn rgbw_pixel(&self, pixel: RGBW8) -> RGBW8 {
+        RGBW8 {
+            r: self.channel(pixel.r),
+            g: self.channel(pixel.g),
+            b: self.channel(pixel.b),
+            w: self.channel(pixel.w),
+        }
+    }
+}
+
+impl Default for ColorCorrection {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl From<&Configuration> for ColorCorrection {
+    fn from(config: &Configuration) -> Self {
+        Self::new(config.brightness, config.gamma)
+    }
+}
+
+impl ColorOrder {
+    /// Returns color channels of the given pixel in the order in which the strip receives them.
+    #[must_use]
+    #[inline]
+    pub const fn wire_bytes(self, pixel: RGB8) -> [u8; 3] {
+        let RGB8 { r, g, b } = pixel;
+        match self {
+            Self::Rgb => [r, g, b],
+            Self::Grb => [g, r, b],
+            Self::Bgr => [b, g, r],
+        }
+    }
+
+    /// Reorders channels of the given RGB pixel for the ws2812 driver.
+    ///
+    /// The driver sends the pixel channels in the GRB order, so they are permuted to make
+    /// the strip receive them in this color order.
+    #[must_use]
+    #[inline]
+    pub const fn reorder(self, pixel: RGB8) -> RGB8 {
+        let [first, second, third] = self.wire_bytes(pixel);
+        RGB8 {
+            r: second,
+            g: first,
+            b: third,
+        }
+    }
+
+    /// Reorders color channels of the given RGBW pixel for the ws2812 driver, the white
+    /// channel stays in place.
+    #[must_use]
+    #[inline]
+    pub const fn reorder_rgbw(self, pixel: RGBW8) -> RGBW8 {
+        let RGB8 { r, g, b } = self.reorder(RGB8 {
+            r: pixel.r,
+            g: pixel.g,
+            b: pixel.b,
+        });
+        RGBW8 {
+            r,
+            g,
+            b,
+            w: pixel.w,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rgb::RGB8;
+
+    use super::{ColorCorrection, RGBW8};
+    use crate::proto::types::{ColorOrder, Gamma};
+
+    #[test]
+    fn test_identity_correction() {
+        let correction = ColorCorrection::new(255, Gamma::LINEAR);
+        assert!(correction.is_identity());
+        assert_eq!(correction, ColorCorrection::default());
+
+        let pixel = RGB8::new(1, 128, 255);
+        assert_eq!(correction.pixel(pixel), pixel);
+    }
+
+    #[test]
+    fn test_brightness_correction() {
+        let correction = ColorCorrection::new(128, Gamma::LINEAR);
+        assert!(!correction.is_identity());
+
+        assert_eq!(correction.channel(0), 0);
+        assert_eq!(correction.channel(255), 128);
+        assert_eq!(correction.channel(128), 64);
+        assert_eq!(
+            correction.pixel(RGB8::new(2, 128, 255)),
+            RGB8::new(1, 64, 128)
+        );
+
+        let off = ColorCorrection::new(0, Gamma::LINEAR);
+        assert!((0..=255).all(|value| off.channel(value) == 0));
+    }
+
+    #[test]
+    fn test_gamma_correction() {
+        let correction = ColorCorrection::new(255, Gamma(22));
+
+        // Endpoints are preserved.
+        assert_eq!(correction.channel(0), 0);
+        assert_eq!(correction.channel(255), 255);
+        // Half brightness is significantly darker: 255 * 0.5 ^ 2.2 ~ 55.
+        assert_eq!(correction.channel(128), 56);
+        // The curve is monotonic.
+        for value in 1..=255 {
+            assert!(correction.channel(value - 1) <= correction.channel(value));
+        }
+    }
+
+    #[test]
+    fn test_color_order() {
+        // The ws2812 driver sends the RGB pixels in the GRB order, and the RGBW pixels
+        // are packed in the GRBW order.
+        let driver_bytes = |pixel: RGB8| [pixel.g, pixel.r, pixel.b];
+        let driver_rgbw_bytes = |pixel: RGBW8| [pixel.g, pixel.r, pixel.b, pixel.w];
+
+        let pixel = RGB8::new(1, 2, 3);
+        for (order, wire) in [
+            (ColorOrder::Rgb, [1, 2, 3]),
+            (ColorOrder::Grb, [2, 1, 3]),
+            (ColorOrder::Bgr, [3, 2, 1]),
+        ] {
+            assert_eq!(order.wire_bytes(pixel), wire);
+            assert_eq!(driver_bytes(order.reorder(pixel)), wire);
+
+            let [first, second, third] = wire;
+            assert_eq!(
+                driver_rgbw_bytes(order.reorder_rgbw(RGBW8::new(1, 2, 3, 4))),
+                [first, second, third, 4]
+            );
+        }
+        assert_eq!(ColorOrder::default(), ColorOrder::Grb);
+    }
+
+    #[test]
+    fn test_rgbw_conversion() {
+        let pixel = RGB8::new(200, 100, 50);
+        let rgbw = RGBW8::extract_white(pixel);
+        assert_eq!(rgbw, RGBW8::new(150, 50, 0, 50));
+        assert_eq!(rgbw.to_rgb(), pixel);
+
+        assert_eq!(RGBW8::from(pixel), RGBW8::new(200, 100, 50, 0));
+        assert_eq!(RGBW8::new(250, 0, 0, 10).to_rgb(), RGB8::new(255, 10, 10));
+    }
+}
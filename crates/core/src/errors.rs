@@ -41,6 +41,24 @@ pub enum Error {
     ImageRenderIsBusy = 13,
     /// Internal device error.
     Internal = 14,
+    /// The peer uses an incompatible protocol version.
+    UnsupportedProtocolVersion = 15,
+    /// The requested operation is not supported by the peer.
+    Unsupported = 16,
+    /// The given configuration contains invalid values.
+    InvalidConfiguration = 17,
+    /// The specified playlist index is greater than the total amount of the stored playlists.
+    PlaylistNotFound = 18,
+    /// The playlists repository on the device is full.
+    PlaylistRepositoryIsFull = 19,
+    /// The playlist is empty or contains entries that are never shown.
+    InvalidPlaylist = 20,
+    /// The device requires the client to authenticate with the pre-shared key.
+    AuthenticationRequired = 21,
+    /// The client has failed to prove the pre-shared key possession.
+    AuthenticationFailed = 22,
+    /// The secondary device has stored the images under the different indices.
+    ImageIdMismatch = 23,
     /// Unspecified or unknown error.
     Unspecified(u16),
 }
@@ -63,6 +81,15 @@ impl Error {
             11 => Self::Decode,
             12 => Self::Encode,
             13 => Self::ImageRenderIsBusy,
+            15 => Self::UnsupportedProtocolVersion,
+            16 => Self::Unsupported,
+            17 => Self::InvalidConfiguration,
+            18 => Self::PlaylistNotFound,
+            19 => Self::PlaylistRepositoryIsFull,
+            20 => Self::InvalidPlaylist,
+            21 => Self::AuthenticationRequired,
+            22 => Self::AuthenticationFailed,
+            23 => Self::ImageIdMismatch,
             42 => Self::Internal,
 
             other => Self::Unspecified(other),
@@ -87,11 +114,32 @@ impl Error {
             Self::Encode => 12,
             Self::ImageRenderIsBusy => 13,
             Self::Internal => 14,
+            Self::UnsupportedProtocolVersion => 15,
+            Self::Unsupported => 16,
+            Self::InvalidConfiguration => 17,
+            Self::PlaylistNotFound => 18,
+            Self::PlaylistRepositoryIsFull => 19,
+            Self::InvalidPlaylist => 20,
+            Self::AuthenticationRequired => 21,
+            Self::AuthenticationFailed => 22,
+            Self::ImageIdMismatch => 23,
 
             Self::Unspecified(other) => other,
         }
     }
 
+    /// Converts the error of receiving the peer handshake.
+    ///
+    /// Peers with an older protocol send a shorter handshake that cannot be decoded.
+    #[must_use]
+    #[inline]
+    pub const fn in_handshake(self) -> Self {
+        match self {
+            Self::Decode => Self::UnsupportedProtocolVersion,
+            other => other,
+        }
+    }
+
     /// Creates a new storage read error.
     #[must_use]
     #[inline]
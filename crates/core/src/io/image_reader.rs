@@ -1,11 +1,18 @@
 //! Image reader wrapper raw embedded I/O reader.
 
-use rgb::{FromSlice, RGB8};
+use embedded_io::SeekFrom;
+use rgb::RGB8;
 
 use crate::{
-    io::{BlockingRead, BlockingReadExactError, BlockingSeek, ExactSizeRead},
-    proto::types::Hertz,
-    BYTES_PER_PIXEL,
+    color::RGBW8,
+    io::{
+        palette::{self, Palette},
+        rle::{self, RleDecoder},
+        AsyncRead, AsyncReadExactError, AsyncSeek, BlockingRead, BlockingReadExactError,
+        BlockingSeek, ExactSizeRead,
+    },
+    proto::types::{Hertz, ImageEncoding, PixelFormat, Playback, PlaybackDirection},
+    Error, BYTES_PER_PIXEL,
 };
 
 #[derive(Debug)]
@@ -15,6 +22,10 @@ pub struct Image<R> {
     /// That is, the refresh rate of a single line of a picture is the refresh rate of
     /// the entire image multiplied by the strip length.
     pub refresh_rate: Hertz,
+    /// Encoding of the image bytes.
+    pub encoding: ImageEncoding,
+    /// Format of the image pixels.
+    pub pixel_format: PixelFormat,
     pub bytes: R,
 }
 
@@ -30,14 +41,60 @@ where
     }
 }
 
-/// An endless iterator over the image lines, then it reaches the end of image, it rewinds to the beginning.
+impl<R> Image<R>
+where
+    R: BlockingRead + BlockingSeek + ExactSizeRead,
+{
+    /// Returns the number of the image pixels.
+    ///
+    /// Encoded images are read to the end to count pixels and then rewound.
+    pub fn pixels_count(&mut self) -> Result<usize, BlockingReadExactError<R::Error>> {
+        match self.encoding {
+            ImageEncoding::Raw => {
+                Ok(self.bytes.bytes_remaining() / self.pixel_format.bytes_per_pixel())
+            }
+            ImageEncoding::Rle => {
+                let count = rle::pixels_count(&mut self.bytes)?;
+                self.rewind().map_err(BlockingReadExactError::Other)?;
+                Ok(count)
+            }
+            ImageEncoding::Palette => {
+                let len = self.bytes.bytes_remaining();
+                if len == 0 {
+                    return Ok(0);
+                }
+                // Pixels count is determined by the palette block length.
+                let mut header = [0_u8];
+                self.bytes.read_exact(&mut header)?;
+                self.rewind().map_err(BlockingReadExactError::Other)?;
+                Ok(len.saturating_sub(palette::block_len(header[0])))
+            }
+        }
+    }
+
+    /// Returns `true` if the image contains runs without pixels, which are not allowed.
+    ///
+    /// Only the RLE encoded images are read to the end and then rewound.
+    pub fn has_empty_runs(&mut self) -> Result<bool, BlockingReadExactError<R::Error>> {
+        if self.encoding != ImageEncoding::Rle {
+            return Ok(false);
+        }
+        let empty = rle::has_empty_runs(&mut self.bytes)?;
+        self.rewind().map_err(BlockingReadExactError::Other)?;
+        Ok(empty)
+    }
+}
+
+/// An iterator over the image lines in the given playback mode.
+///
+/// By default, it is endless and rewinds to the beginning then it reaches the end of image.
 pub struct ImageLines<R, B>
 where
     B: AsMut<[u8]>,
 {
     image: Image<R>,
-    strip_line_len: usize,
     strip_line_buf: B,
+    state: LinesState,
 }
 
 impl<R, B> ImageLines<R, B>
@@ -47,62 +104,630 @@ where
 {
     /// Creates a new image lines iterator.
     ///
-    /// # Panics
-    ///
-    /// - If the image length is lesser that the single strip line length
-    /// - If the image length in pixels is not a multiple of the strip length
-    pub fn new(image: Image<R>, strip_len: u16, mut strip_line_buf: B) -> Self {
-        let strip_len: usize = strip_len.into();
-        let strip_line_len = strip_len * BYTES_PER_PIXEL;
-        // Check preconditions.
-        assert!(
-            image.bytes.bytes_remaining() >= strip_line_len,
-            "The given image should have at least {strip_line_len} bytes"
-        );
-        assert!(
-            image.bytes.bytes_remaining() % strip_line_len == 0,
-            "The length of the given image in pixels `{}` is not a multiple of the given strip length `{}`.",
-            image.bytes.bytes_remaining(),
-            strip_line_len
-        );
-        assert!(
-            strip_line_buf.as_mut().len() >= strip_line_len,
-            "Given buffer capacity is not enough"
-        );
-
-        Self {
+    /// # Errors
+    ///
+    /// - [`Error::StripLengthMismatch`] if the strip line doesn't fit into the given buffer
+    /// - [`Error::Unsupported`] if the encoded image has not the RGB pixel format
+    /// - [`Error::ImageLengthMismatch`] if the raw image length is not a multiple of the strip
+    ///   line length, the run-length encoded image length is not a multiple of the run length
+    ///   or the palette-indexed image is empty
+    pub fn new(image: Image<R>, strip_len: u16, mut strip_line_buf: B) -> crate::Result<Self> {
+        Ok(Self {
+            state: LinesState::new(&image, strip_len, strip_line_buf.as_mut().len())?,
             image,
-            strip_line_len,
             strip_line_buf,
-        }
+        })
     }
 
-    /// Returns a refresh line fo the single strip line.
+    /// Sets the image playback mode.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::Unsupported`] if the run-length encoded image is played not in the forward
+    ///   direction, since its lines can be decoded only sequentially.
+    pub fn with_playback(mut self, playback: Playback) -> crate::Result<Self> {
+        self.state.set_playback(playback)?;
+        Ok(self)
+    }
+
+    /// Returns a refresh rate of the single strip line.
     pub const fn refresh_rate(&self) -> Hertz {
         self.image.refresh_rate
     }
 
+    /// Returns `true` if the all image repetitions have been shown.
+    ///
+    /// The finished iterator returns blank lines.
+    pub const fn is_finished(&self) -> bool {
+        self.state.playback.finished
+    }
+
     /// Reads and returns a next image line.
+    ///
+    /// The white channel of the RGBW pixels is mixed into the color channels.
     #[inline]
     pub fn next_line(
         &mut self,
     ) -> Result<impl Iterator<Item = RGB8> + '_, BlockingReadExactError<R::Error>> {
-        let line = self.fill_next_line()?.as_rgb().iter().copied();
-        Ok(line)
+        let pixel_format = self.image.pixel_format;
+        Ok(rgb_pixels(pixel_format, self.fill_next_line()?))
+    }
+
+    /// Reads and returns a next image line with the RGBW pixels.
+    ///
+    /// The white channel of the RGB pixels is always zero.
+    #[inline]
+    pub fn next_rgbw_line(
+        &mut self,
+    ) -> Result<impl Iterator<Item = RGBW8> + '_, BlockingReadExactError<R::Error>> {
+        let pixel_format = self.image.pixel_format;
+        Ok(rgbw_pixels(pixel_format, self.fill_next_line()?))
     }
 
     #[inline]
     fn fill_next_line(&mut self) -> Result<&[u8], BlockingReadExactError<R::Error>> {
-        // In this case we reached the end of file and have to rewind to the beginning
-        if self.image.bytes.bytes_remaining() == 0 {
-            self.image
-                .bytes
-                .rewind()
-                .map_err(BlockingReadExactError::Other)?;
-        }
-        // Fill the buffer with by the bytes of the next image line
-        let buf = &mut self.strip_line_buf.as_mut()[0..self.strip_line_len];
-        self.image.bytes.read_exact(buf)?;
+        let buf = &mut self.strip_line_buf.as_mut()[0..self.state.strip_line_len];
+        let bytes = &mut self.image.bytes;
+        if self.state.needs_palette() {
+            self.state.palette = Some(Palette::read(bytes)?);
+        }
+
+        match self.state.next_step(bytes.bytes_remaining()) {
+            LineStep::Blank => buf.fill(0),
+            LineStep::Read { offset, len } => {
+                bytes
+                    .seek(SeekFrom::Start(offset))
+                    .map_err(BlockingReadExactError::Other)?;
+                bytes.read_exact(&mut buf[0..len])?;
+                self.state.complete_read(buf);
+            }
+            LineStep::DecodeRuns => {
+                for pixel in buf.chunks_exact_mut(BYTES_PER_PIXEL) {
+                    if self.state.needs_rewind(bytes.bytes_remaining()) {
+                        bytes.rewind().map_err(BlockingReadExactError::Other)?;
+                    }
+                    pixel.copy_from_slice(&self.state.rle.next_pixel(bytes)?);
+                }
+            }
+        }
         Ok(buf)
     }
 }
+
+/// An asynchronous counterpart of the [`ImageLines`], which doesn't block the executor
+/// while reading the image bytes.
+pub struct AsyncImageLines<R, B>
+where
+    B: AsMut<[u8]>,
+{
+    image: Image<R>,
+    strip_line_buf: B,
+    state: LinesState,
+}
+
+impl<R, B> AsyncImageLines<R, B>
+where
+    B: AsMut<[u8]>,
+    R: AsyncRead + AsyncSeek + ExactSizeRead,
+{
+    /// Creates a new asynchronous image lines iterator.
+    ///
+    /// # Errors
+    ///
+    /// The same as in the [`ImageLines::new`].
+    pub fn new(image: Image<R>, strip_len: u16, mut strip_line_buf: B) -> crate::Result<Self> {
+        Ok(Self {
+            state: LinesState::new(&image, strip_len, strip_line_buf.as_mut().len())?,
+            image,
+            strip_line_buf,
+        })
+    }
+
+    /// Sets the image playback mode.
+    ///
+    /// # Errors
+    ///
+    /// The same as in the [`ImageLines::with_playback`].
+    pub fn with_playback(mut self, playback: Playback) -> crate::Result<Self> {
+        self.state.set_playback(playback)?;
+        Ok(self)
+    }
+
+    /// Returns a refresh rate of the single strip line.
+    pub const fn refresh_rate(&self) -> Hertz {
+        self.image.refresh_rate
+    }
+
+    /// Returns `true` if the all image repetitions have been shown.
+    ///
+    /// The finished iterator returns blank lines.
+    pub const fn is_finished(&self) -> bool {
+        self.state.playback.finished
+    }
+
+    /// Reads and returns a next image line.
+    ///
+    /// The white channel of the RGBW pixels is mixed into the color channels.
+    #[inline]
+    pub async fn next_line(
+        &mut self,
+    ) -> Result<impl Iterator<Item = RGB8> + '_, AsyncReadExactError<R::Error>> {
+        let pixel_format = self.image.pixel_format;
+        Ok(rgb_pixels(pixel_format, self.fill_next_line().await?))
+    }
+
+    /// Reads and returns a next image line with the RGBW pixels.
+    ///
+    /// The white channel of the RGB pixels is always zero.
+    #[inline]
+    pub async fn next_rgbw_line(
+        &mut self,
+    ) -> Result<impl Iterator<Item = RGBW8> + '_, AsyncReadExactError<R::Error>> {
+        let pixel_format = self.image.pixel_format;
+        Ok(rgbw_pixels(pixel_format, self.fill_next_line().await?))
+    }
+
+    async fn fill_next_line(&mut self) -> Result<&[u8], AsyncReadExactError<R::Error>> {
+        let buf = &mut self.strip_line_buf.as_mut()[0..self.state.strip_line_len];
+        let bytes = &mut self.image.bytes;
+        if self.state.needs_palette() {
+            self.state.palette = Some(Palette::read_async(bytes).await?);
+        }
+
+        match self.state.next_step(bytes.bytes_remaining()) {
+            LineStep::Blank => buf.fill(0),
+            LineStep::Read { offset, len } => {
+                bytes
+                    .seek(SeekFrom::Start(offset))
+                    .await
+                    .map_err(AsyncReadExactError::Other)?;
+                bytes.read_exact(&mut buf[0..len]).await?;
+                self.state.complete_read(buf);
+            }
+            LineStep::DecodeRuns => {
+                for pixel in buf.chunks_exact_mut(BYTES_PER_PIXEL) {
+                    if self.state.needs_rewind(bytes.bytes_remaining()) {
+                        bytes.rewind().await.map_err(AsyncReadExactError::Other)?;
+                    }
+                    pixel.copy_from_slice(&self.state.rle.next_pixel_async(bytes).await?);
+                }
+            }
+        }
+        Ok(buf)
+    }
+}
+
+/// I/O operation which fills the next image line.
+enum LineStep {
+    /// The line is blank, since the playback has been finished.
+    Blank,
+    /// The given number of bytes at the given image offset are read into the line buffer.
+    Read { offset: u64, len: usize },
+    /// The line is decoded pixel by pixel from the run-length encoded image, the image is
+    /// rewound whenever it has been read to the end.
+    DecodeRuns,
+}
+
+/// Decoding and playback state shared by the [`ImageLines`] and [`AsyncImageLines`], which
+/// only perform the I/O operations requested by it.
+struct LinesState {
+    encoding: ImageEncoding,
+    image_len: usize,
+    strip_line_len: usize,
+    rle: RleDecoder,
+    palette: Option<Palette>,
+    playback: PlaybackState,
+}
+
+impl LinesState {
+    fn new<R: ExactSizeRead>(
+        image: &Image<R>,
+        strip_len: u16,
+        buf_len: usize,
+    ) -> crate::Result<Self> {
+        Ok(Self {
+            encoding: image.encoding,
+            image_len: image.bytes.bytes_remaining(),
+            strip_line_len: check_preconditions(image, strip_len, buf_len)?,
+            rle: RleDecoder::new(),
+            palette: None,
+            playback: PlaybackState::new(Playback::default()),
+        })
+    }
+
+    fn set_playback(&mut self, playback: Playback) -> crate::Result<()> {
+        check_playback(self.encoding, playback)?;
+        self.playback = PlaybackState::new(playback);
+        Ok(())
+    }
+
+    /// Returns `true` if the palette block should be read at the beginning of the image.
+    fn needs_palette(&self) -> bool {
+        self.encoding == ImageEncoding::Palette && self.palette.is_none() && !self.playback.finished
+    }
+
+    /// Returns `true` if the run-length encoded image has been read to the end and should be
+    /// rewound to the beginning.
+    const fn needs_rewind(&self, bytes_remaining: usize) -> bool {
+        self.rle.is_run_finished() && bytes_remaining == 0
+    }
+
+    /// Advances the playback and returns the operation which fills the next line.
+    fn next_step(&mut self, bytes_remaining: usize) -> LineStep {
+        if self.playback.finished {
+            return LineStep::Blank;
+        }
+
+        let line_step = |line: Option<usize>, offset: usize, len: usize| match line {
+            Some(line) => LineStep::Read {
+                offset: (offset + line * len) as u64,
+                len,
+            },
+            None => LineStep::Blank,
+        };
+        match self.encoding {
+            ImageEncoding::Raw => {
+                let lines = self.image_len / self.strip_line_len;
+                line_step(self.playback.next_line(lines), 0, self.strip_line_len)
+            }
+            // The end of the image completes the playback cycle.
+            ImageEncoding::Rle
+                if self.needs_rewind(bytes_remaining) && !self.playback.complete_cycle() =>
+            {
+                LineStep::Blank
+            }
+            ImageEncoding::Rle => LineStep::DecodeRuns,
+            ImageEncoding::Palette => {
+                // Indices of the line pixels follow the palette block.
+                let block_len = self.palette.as_ref().map_or(0, Palette::block_len);
+                let pixels = self.strip_line_len / BYTES_PER_PIXEL;
+                let lines = self.image_len.saturating_sub(block_len) / pixels;
+                line_step(self.playback.next_line(lines), block_len, pixels)
+            }
+        }
+    }
+
+    /// Completes the line which bytes have been read by the [`LineStep::Read`] operation.
+    fn complete_read(&self, buf: &mut [u8]) {
+        if let Some(palette) = &self.palette {
+            expand_palette_indices(palette, buf, buf.len() / BYTES_PER_PIXEL);
+        }
+    }
+}
+
+/// Position of the image lines playback.
+#[derive(Debug, Clone, Copy)]
+struct PlaybackState {
+    playback: Playback,
+    /// Position of the next line in the current playback cycle.
+    position: usize,
+    /// The number of the completed playback cycles.
+    cycles: u16,
+    finished: bool,
+}
+
+impl PlaybackState {
+    fn new(playback: Playback) -> Self {
+        Self {
+            playback,
+            position: 0,
+            cycles: 0,
+            finished: playback.repeat == Some(0),
+        }
+    }
+
+    /// Completes the current playback cycle and returns `false` if it was the last one.
+    fn complete_cycle(&mut self) -> bool {
+        self.position = 0;
+        self.cycles = self.cycles.saturating_add(1);
+        self.finished = self
+            .playback
+            .repeat
+            .is_some_and(|repeat| self.cycles >= repeat);
+        !self.finished
+    }
+
+    /// Returns the index of the next line of the image with the given number of lines, or
+    /// `None` if the playback is finished.
+    fn next_line(&mut self, lines: usize) -> Option<usize> {
+        if lines == 0 {
+            return None;
+        }
+
+        let period = match self.playback.direction {
+            PlaybackDirection::Forward | PlaybackDirection::Reverse => lines,
+            // The first and the last lines are shown once per cycle.
+            PlaybackDirection::PingPong => core::cmp::max(2 * lines - 2, 1),
+        };
+        if self.position >= period && !self.complete_cycle() {
+            return None;
+        }
+
+        let position = self.position;
+        self.position += 1;
+        let line = match self.playback.direction {
+            PlaybackDirection::Forward => position,
+            PlaybackDirection::Reverse => lines - 1 - position,
+            PlaybackDirection::PingPong if position < lines => position,
+            PlaybackDirection::PingPong => 2 * lines - 2 - position,
+        };
+        Some(line)
+    }
+}
+
+/// Checks that the image with the given encoding can be shown in the given playback mode.
+pub fn check_playback(encoding: ImageEncoding, playback: Playback) -> crate::Result<()> {
+    if encoding == ImageEncoding::Rle && playback.direction != PlaybackDirection::Forward {
+        log::warn!("Run-length encoded images can be played only in the forward direction");
+        return Err(Error::Unsupported);
+    }
+    Ok(())
+}
+
+/// Checks the image lines iterator preconditions and returns the strip line length in bytes.
+fn check_preconditions<R: ExactSizeRead>(
+    image: &Image<R>,
+    strip_len: u16,
+    buf_len: usize,
+) -> crate::Result<usize> {
+    let strip_line_len = usize::from(strip_len) * image.pixel_format.bytes_per_pixel();
+    if strip_line_len == 0 || buf_len < strip_line_len {
+        log::warn!(
+            "The strip line of {strip_line_len} bytes doesn't fit into the {buf_len} bytes buffer"
+        );
+        return Err(Error::StripLengthMismatch);
+    }
+    if image.encoding != ImageEncoding::Raw && image.pixel_format != PixelFormat::Rgb {
+        log::warn!("Encoded images should have the RGB pixel format");
+        return Err(Error::Unsupported);
+    }
+
+    let len = image.bytes.bytes_remaining();
+    let is_valid_len = match image.encoding {
+        // The image should have at least one whole line.
+        ImageEncoding::Raw => len >= strip_line_len && len % strip_line_len == 0,
+        // The image should have at least one whole run.
+        ImageEncoding::Rle => len >= rle::RUN_LEN && len % rle::RUN_LEN == 0,
+        // The image should have at least a palette block header.
+        ImageEncoding::Palette => len > 0,
+    };
+    if !is_valid_len {
+        log::warn!(
+            "The {:?} image length `{len}` doesn't match the strip line length `{strip_line_len}`",
+            image.encoding
+        );
+        return Err(Error::ImageLengthMismatch);
+    }
+    Ok(strip_line_len)
+}
+
+/// Converts the line bytes into the RGB pixels, the white channel is mixed into the color channels.
+fn rgb_pixels(pixel_format: PixelFormat, line: &[u8]) -> impl Iterator<Item = RGB8> + '_ {
+    line.chunks_exact(pixel_format.bytes_per_pixel())
+        .map(move |bytes| match pixel_format {
+            PixelFormat::Rgb => RGB8::new(bytes[0], bytes[1], bytes[2]),
+            PixelFormat::Rgbw => RGBW8::new(bytes[0], bytes[1], bytes[2], bytes[3]).to_rgb(),
+        })
+}
+
+/// Converts the line bytes into the RGBW pixels, the white channel of the RGB pixels is zero.
+fn rgbw_pixels(pixel_format: PixelFormat, line: &[u8]) -> impl Iterator<Item = RGBW8> + '_ {
+    line.chunks_exact(pixel_format.bytes_per_pixel())
+        .map(move |bytes| match pixel_format {
+            PixelFormat::Rgb => RGBW8::new(bytes[0], bytes[1], bytes[2], 0),
+            PixelFormat::Rgbw => RGBW8::new(bytes[0], bytes[1], bytes[2], bytes[3]),
+        })
+}
+
+/// Expands the palette indices at the beginning of the line buffer into the RGB pixels.
+fn expand_palette_indices(palette: &Palette, buf: &mut [u8], pixels: usize) {
+    // Expand indices in place starting from the end of line, so the not yet
+    // expanded indices are never overwritten.
+    for i in (0..pixels).rev() {
+        let color = palette.color(buf[i]);
+        buf[i * BYTES_PER_PIXEL..(i + 1) * BYTES_PER_PIXEL]
+            .copy_from_slice(&[color.r, color.g, color.b]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_io::SeekFrom;
+    use rgb::RGB8;
+
+    use super::{Image, ImageLines};
+    use crate::{
+        io::{palette, rle::RleEncoder, BlockingRead, BlockingSeek, ErrorType, ExactSizeRead},
+        proto::types::{Hertz, ImageEncoding, PixelFormat, Playback, PlaybackDirection},
+        Error,
+    };
+
+    /// In-memory image bytes reader.
+    struct Bytes {
+        bytes: Vec<u8>,
+        pos: usize,
+    }
+
+    impl ErrorType for Bytes {
+        type Error = core::convert::Infallible;
+    }
+
+    impl BlockingRead for Bytes {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let amount = core::cmp::min(buf.len(), self.bytes_remaining());
+            buf[0..amount].copy_from_slice(&self.bytes[self.pos..self.pos + amount]);
+            self.pos += amount;
+            Ok(amount)
+        }
+    }
+
+    impl BlockingSeek for Bytes {
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+            let SeekFrom::Start(pos) = pos else {
+                unimplemented!("Only seeking from the start is used");
+            };
+            self.pos = usize::try_from(pos).unwrap();
+            Ok(pos)
+        }
+    }
+
+    impl ExactSizeRead for Bytes {
+        fn bytes_remaining(&self) -> usize {
+            self.bytes.len() - self.pos
+        }
+    }
+
+    /// Creates lines iterator of the single pixel strip, where the line pixel components
+    /// are equal to the line index.
+    fn image_lines(encoding: ImageEncoding, lines: u8) -> ImageLines<Bytes, [u8; 3]> {
+        let pixels: Vec<u8> = (0..lines).flat_map(|line| [line; 3]).collect();
+        let bytes = match encoding {
+            ImageEncoding::Raw => pixels,
+            ImageEncoding::Rle => RleEncoder::new(&pixels).flatten().collect(),
+            ImageEncoding::Palette => {
+                let colors: Vec<_> = (0..lines).map(|line| RGB8::new(line, line, line)).collect();
+                palette::encode_block(&colors).chain(0..lines).collect()
+            }
+        };
+        let image = Image {
+            refresh_rate: Hertz(50),
+            encoding,
+            pixel_format: PixelFormat::Rgb,
+            bytes: Bytes { bytes, pos: 0 },
+        };
+        ImageLines::new(image, 1, [0_u8; 3]).unwrap()
+    }
+
+    /// Returns indices of the next lines, blank lines are returned as `None`.
+    fn next_lines(lines: &mut ImageLines<Bytes, [u8; 3]>, count: usize) -> Vec<Option<u8>> {
+        (0..count)
+            .map(|_| {
+                let line = lines.next_line().unwrap().next().unwrap();
+                (!lines.is_finished()).then_some(line.r)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_playback_forward() {
+        for encoding in [
+            ImageEncoding::Raw,
+            ImageEncoding::Rle,
+            ImageEncoding::Palette,
+        ] {
+            // Images are played endlessly by default.
+            let mut lines = image_lines(encoding, 3);
+            assert_eq!(
+                next_lines(&mut lines, 7),
+                [
+                    Some(0),
+                    Some(1),
+                    Some(2),
+                    Some(0),
+                    Some(1),
+                    Some(2),
+                    Some(0)
+                ]
+            );
+
+            let mut lines = image_lines(encoding, 3)
+                .with_playback(Playback::ONCE)
+                .unwrap();
+            assert_eq!(
+                next_lines(&mut lines, 5),
+                [Some(0), Some(1), Some(2), None, None]
+            );
+        }
+    }
+
+    #[test]
+    fn test_playback_repeat() {
+        let playback = Playback {
+            direction: PlaybackDirection::Forward,
+            repeat: Some(2),
+        };
+        let mut lines = image_lines(ImageEncoding::Rle, 2)
+            .with_playback(playback)
+            .unwrap();
+        assert_eq!(
+            next_lines(&mut lines, 5),
+            [Some(0), Some(1), Some(0), Some(1), None]
+        );
+
+        let playback = Playback {
+            direction: PlaybackDirection::Forward,
+            repeat: Some(0),
+        };
+        let mut lines = image_lines(ImageEncoding::Raw, 2)
+            .with_playback(playback)
+            .unwrap();
+        assert!(lines.is_finished());
+        assert_eq!(next_lines(&mut lines, 1), [None]);
+    }
+
+    #[test]
+    fn test_playback_reverse() {
+        let playback = Playback {
+            direction: PlaybackDirection::Reverse,
+            repeat: Some(2),
+        };
+        for encoding in [ImageEncoding::Raw, ImageEncoding::Palette] {
+            let mut lines = image_lines(encoding, 3).with_playback(playback).unwrap();
+            assert_eq!(
+                next_lines(&mut lines, 7),
+                [Some(2), Some(1), Some(0), Some(2), Some(1), Some(0), None]
+            );
+        }
+
+        // Run-length encoded images can be decoded only sequentially.
+        assert_eq!(
+            image_lines(ImageEncoding::Rle, 3)
+                .with_playback(playback)
+                .err(),
+            Some(Error::Unsupported)
+        );
+    }
+
+    #[test]
+    fn test_playback_ping_pong() {
+        let playback = Playback {
+            direction: PlaybackDirection::PingPong,
+            repeat: None,
+        };
+        let mut lines = image_lines(ImageEncoding::Raw, 3)
+            .with_playback(playback)
+            .unwrap();
+        assert_eq!(
+            next_lines(&mut lines, 9),
+            [
+                Some(0),
+                Some(1),
+                Some(2),
+                Some(1),
+                Some(0),
+                Some(1),
+                Some(2),
+                Some(1),
+                Some(0)
+            ]
+        );
+
+        let playback = Playback {
+            direction: PlaybackDirection::PingPong,
+            repeat: Some(1),
+        };
+        let mut lines = image_lines(ImageEncoding::Palette, 3)
+            .with_playback(playback)
+            .unwrap();
+        assert_eq!(
+            next_lines(&mut lines, 5),
+            [Some(0), Some(1), Some(2), Some(1), None]
+        );
+
+        // Single line images are just repeated.
+        let mut lines = image_lines(ImageEncoding::Raw, 1)
+            .with_playback(playback)
+            .unwrap();
+        assert_eq!(next_lines(&mut lines, 2), [Some(0), None]);
+    }
+}
@@ -13,6 +13,8 @@ pub use embedded_io::{
 };
 
 pub mod image_reader;
+pub mod palette;
+pub mod rle;
 
 /// The reader with the exact number of bytes to read.
 pub trait ExactSizeRead {
@@ -0,0 +1,122 @@
+//! Palette-indexed images.
+//!
+//! The indexed image begins with a palette block: a one byte colors count minus one
+//! followed by the palette colors bytes. The block is followed by one palette index per pixel.
+
+use rgb::RGB8;
+
+use crate::{
+    io::{AsyncRead, AsyncReadExactError, BlockingRead, BlockingReadExactError},
+    BYTES_PER_PIXEL,
+};
+
+/// The maximum number of the palette colors.
+pub const MAX_COLORS: usize = 256;
+
+/// Returns the palette block length by the first byte of the block.
+#[must_use]
+pub const fn block_len(header: u8) -> usize {
+    1 + (header as usize + 1) * BYTES_PER_PIXEL
+}
+
+/// Encodes the palette block with the given colors.
+///
+/// # Panics
+///
+/// - If the colors count is zero or greater than [`MAX_COLORS`].
+pub fn encode_block(colors: &[RGB8]) -> impl Iterator<Item = u8> + '_ {
+    assert!(
+        (1..=MAX_COLORS).contains(&colors.len()),
+        "Palette should have from 1 to {MAX_COLORS} colors"
+    );
+
+    // The colors count is checked above.
+    #[allow(clippy::cast_possible_truncation)]
+    let header = (colors.len() - 1) as u8;
+    core::iter::once(header).chain(colors.iter().flat_map(|color| [color.r, color.g, color.b]))
+}
+
+/// Image palette colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    colors: [RGB8; MAX_COLORS],
+    len: usize,
+}
+
+impl Palette {
+    /// Reads the palette block from the given reader.
+    pub fn read<R: BlockingRead>(reader: &mut R) -> Result<Self, BlockingReadExactError<R::Error>> {
+        let mut header = [0_u8];
+        reader.read_exact(&mut header)?;
+
+        let mut palette = Self {
+            colors: [RGB8::default(); MAX_COLORS],
+            len: usize::from(header[0]) + 1,
+        };
+        for color in &mut palette.colors[0..palette.len] {
+            let mut bytes = [0_u8; BYTES_PER_PIXEL];
+            reader.read_exact(&mut bytes)?;
+            *color = RGB8::new(bytes[0], bytes[1], bytes[2]);
+        }
+        Ok(palette)
+    }
+
+    /// Asynchronously reads the palette block from the given reader.
+    pub async fn read_async<R: AsyncRead>(
+        reader: &mut R,
+    ) -> Result<Self, AsyncReadExactError<R::Error>> {
+        let mut header = [0_u8];
+        reader.read_exact(&mut header).await?;
+
+        let mut palette = Self {
+            colors: [RGB8::default(); MAX_COLORS],
+            len: usize::from(header[0]) + 1,
+        };
+        for color in &mut palette.colors[0..palette.len] {
+            let mut bytes = [0_u8; BYTES_PER_PIXEL];
+            reader.read_exact(&mut bytes).await?;
+            *color = RGB8::new(bytes[0], bytes[1], bytes[2]);
+        }
+        Ok(palette)
+    }
+
+    /// Returns the palette colors.
+    #[must_use]
+    pub fn colors(&self) -> &[RGB8] {
+        &self.colors[0..self.len]
+    }
+
+    /// Returns the length of the palette block in bytes.
+    #[must_use]
+    pub const fn block_len(&self) -> usize {
+        1 + self.len * BYTES_PER_PIXEL
+    }
+
+    /// Returns a color with the given index, indices outside the palette are mapped to black.
+    #[must_use]
+    #[inline]
+    pub const fn color(&self, index: u8) -> RGB8 {
+        self.colors[index as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rgb::RGB8;
+
+    use super::{block_len, encode_block, Palette};
+
+    #[test]
+    fn test_palette_read_write() {
+        let colors = [RGB8::new(1, 2, 3), RGB8::new(4, 5, 6)];
+        let block = encode_block(&colors).collect::<Vec<_>>();
+        assert_eq!(block, [1, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(block_len(block[0]), block.len());
+
+        let palette = Palette::read(&mut block.as_slice()).unwrap();
+        assert_eq!(palette.colors(), colors);
+        assert_eq!(palette.block_len(), block.len());
+        assert_eq!(palette.color(1), colors[1]);
+        assert_eq!(palette.color(42), RGB8::default());
+    }
+}
@@ -0,0 +1,177 @@
+//! Run-length encoding of the image pixels.
+//!
+//! The encoded image is a sequence of runs, each run is a one byte pixels count followed
+//! by the pixel bytes. Runs are not aligned to the strip lines, so a single run may span
+//! several lines.
+
+use crate::{
+    io::{AsyncRead, AsyncReadExactError, BlockingRead, BlockingReadExactError, ExactSizeRead},
+    BYTES_PER_PIXEL,
+};
+
+/// The length of the single encoded run in bytes.
+pub const RUN_LEN: usize = 1 + BYTES_PER_PIXEL;
+
+/// A single encoded run.
+pub type Run = [u8; RUN_LEN];
+
+/// Streaming run-length decoder, which reads runs from the underlying reader on demand.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RleDecoder {
+    remaining: u8,
+    pixel: [u8; BYTES_PER_PIXEL],
+}
+
+impl RleDecoder {
+    /// Creates a new decoder without a pending run.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            remaining: 0,
+            pixel: [0; BYTES_PER_PIXEL],
+        }
+    }
+
+    /// Returns `true` if all pixels of the current run have been decoded.
+    #[must_use]
+    pub const fn is_run_finished(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Decodes a next pixel, the next run is read from the given reader if the current one
+    /// is finished.
+    pub fn next_pixel<R: BlockingRead>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<[u8; BYTES_PER_PIXEL], BlockingReadExactError<R::Error>> {
+        // Runs with zero length are just skipped.
+        while self.remaining == 0 {
+            let mut run: Run = [0; RUN_LEN];
+            reader.read_exact(&mut run)?;
+            self.remaining = run[0];
+            self.pixel.copy_from_slice(&run[1..]);
+        }
+
+        self.remaining -= 1;
+        Ok(self.pixel)
+    }
+
+    /// Asynchronously decodes a next pixel, the same as the [`Self::next_pixel`].
+    pub async fn next_pixel_async<R: AsyncRead>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<[u8; BYTES_PER_PIXEL], AsyncReadExactError<R::Error>> {
+        while self.remaining == 0 {
+            let mut run: Run = [0; RUN_LEN];
+            reader.read_exact(&mut run).await?;
+            self.remaining = run[0];
+            self.pixel.copy_from_slice(&run[1..]);
+        }
+
+        self.remaining -= 1;
+        Ok(self.pixel)
+    }
+}
+
+/// Returns the number of pixels in the encoded image by reading all its runs.
+pub fn pixels_count<R: BlockingRead + ExactSizeRead>(
+    reader: &mut R,
+) -> Result<usize, BlockingReadExactError<R::Error>> {
+    let mut count = 0;
+    let mut run: Run = [0; RUN_LEN];
+    while reader.bytes_remaining() >= RUN_LEN {
+        reader.read_exact(&mut run)?;
+        count += usize::from(run[0]);
+    }
+    Ok(count)
+}
+
+/// Returns `true` if the encoded image contains runs without pixels.
+///
+/// The decoder skips such runs, but the trailing one makes it read past the image end.
+pub fn has_empty_runs<R: BlockingRead + ExactSizeRead>(
+    reader: &mut R,
+) -> Result<bool, BlockingReadExactError<R::Error>> {
+    let mut empty = false;
+    let mut run: Run = [0; RUN_LEN];
+    while reader.bytes_remaining() >= RUN_LEN {
+        reader.read_exact(&mut run)?;
+        empty |= run[0] == 0;
+    }
+    Ok(empty)
+}
+
+/// An iterator over the runs of the encoded raw pixel bytes.
+#[derive(Debug, Clone)]
+pub struct RleEncoder<'a> {
+    pixels: core::iter::Peekable<core::slice::ChunksExact<'a, u8>>,
+}
+
+impl<'a> RleEncoder<'a> {
+    /// Creates a new encoder of the given raw pixel bytes.
+    ///
+    /// Trailing bytes that do not form a whole pixel are ignored.
+    #[must_use]
+    pub fn new(pixels: &'a [u8]) -> Self {
+        Self {
+            pixels: pixels.chunks_exact(BYTES_PER_PIXEL).peekable(),
+        }
+    }
+}
+
+impl Iterator for RleEncoder<'_> {
+    type Item = Run;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pixel = self.pixels.next()?;
+        let mut count = 1_u8;
+        while count < u8::MAX && self.pixels.next_if_eq(&pixel).is_some() {
+            count += 1;
+        }
+
+        let mut run: Run = [count; RUN_LEN];
+        run[1..].copy_from_slice(pixel);
+        Some(run)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{has_empty_runs, pixels_count, RleDecoder, RleEncoder, RUN_LEN};
+
+    #[test]
+    fn test_rle_encode() {
+        let pixels = [1, 2, 3, 1, 2, 3, 1, 2, 3, 4, 5, 6];
+        let runs = RleEncoder::new(&pixels).collect::<Vec<_>>();
+        assert_eq!(runs, [[3, 1, 2, 3], [1, 4, 5, 6]]);
+
+        // Long runs are split.
+        let pixels = [7_u8; 300 * 3];
+        let runs = RleEncoder::new(&pixels).collect::<Vec<_>>();
+        assert_eq!(runs, [[255, 7, 7, 7], [45, 7, 7, 7]]);
+    }
+
+    #[test]
+    fn test_rle_decode() {
+        let pixels = (0..60_u8).map(|i| i / 9).collect::<Vec<_>>();
+        let encoded = RleEncoder::new(&pixels).flatten().collect::<Vec<_>>();
+        assert_eq!(encoded.len() % RUN_LEN, 0);
+        assert!(encoded.len() < pixels.len());
+
+        assert_eq!(pixels_count(&mut encoded.as_slice()).unwrap(), 20);
+        assert!(!has_empty_runs(&mut encoded.as_slice()).unwrap());
+        let trailing_empty = [encoded.as_slice(), &[0, 1, 2, 3]].concat();
+        assert_eq!(pixels_count(&mut trailing_empty.as_slice()).unwrap(), 20);
+        assert!(has_empty_runs(&mut trailing_empty.as_slice()).unwrap());
+
+        let mut reader = encoded.as_slice();
+        let mut decoder = RleDecoder::new();
+        let mut output = Vec::new();
+        for _ in 0..20 {
+            output.extend(decoder.next_pixel(&mut reader).unwrap());
+        }
+        assert!(decoder.is_run_finished());
+        assert!(reader.is_empty());
+        assert_eq!(output, pixels);
+    }
+}
@@ -11,7 +11,9 @@
 )]
 
 pub use errors::{Error, Result};
+pub use rgb;
 
+pub mod color;
 pub mod errors;
 pub mod io;
 pub mod proto;
@@ -21,5 +23,9 @@ pub mod proto;
 /// It doesn't make sense to create pixel devices with strip longer than this one,
 /// the ws2812 protocol has not enough refresh rate.
 pub const MAX_STRIP_LEN: usize = 48;
-/// Bytes count per single pixel.
+/// Bytes count per single RGB pixel.
 pub const BYTES_PER_PIXEL: usize = 3;
+/// The maximum bytes count per single pixel among the all supported pixel formats.
+pub const MAX_BYTES_PER_PIXEL: usize = 4;
+/// The maximum number of the images stored on the device.
+pub const MAX_IMAGES_COUNT: usize = 127;
@@ -1,12 +1,52 @@
 use postcard::experimental::max_size::MaxSize;
 use serde::{Deserialize, Serialize};
 
-use self::types::{ImageId, ImageInfo, PeerInfo};
+use self::types::{
+    AuthChallenge, AuthKey, AuthTag, Configuration, DeviceTime, FirmwareInfo, Hertz, ImageId,
+    ImageInfo, ImageName, PeerInfo, Playback, Playlist, PlaylistId, SecondaryResults,
+};
 
 pub mod packet;
 pub mod types;
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug, MaxSize)]
+/// The version of the Cyberpixie protocol implemented by this crate.
+///
+/// The version is bumped on every wire format change:
+///
+/// 1. Handshake with the protocol version and capabilities.
+/// 2. Full firmware information.
+/// 3. Removing a single image.
+/// 4. Reading images back.
+/// 5. Listing images metadata.
+/// 6. Image names in the image information.
+/// 7. Remote configuration requests.
+/// 8. Brightness and gamma correction in the configuration.
+/// 9. Color order in the configuration.
+/// 10. Encoding in the image information.
+/// 11. Palette-indexed image encoding.
+/// 12. Pixel format in the image information and the configuration.
+/// 13. Playback modes of the show image request.
+/// 14. Playlists and the playlist position in the device information.
+/// 15. Autoplay flag in the configuration.
+/// 16. Streaming lines.
+/// 17. Results of the requests forwarded to the secondary devices.
+/// 18. Clock synchronisation and scheduled image start.
+/// 19. Device name in the configuration.
+/// 20. Pre-shared key authentication.
+/// 21. DMX input and secondary devices switches in the configuration.
+/// 22. Image index mismatch error of the secondary devices.
+/// 23. Main device address in the configuration.
+/// 24. DMX protocol and universe in the configuration.
+/// 25. Secondary devices authenticate with the key of the main device.
+pub const PROTOCOL_VERSION: u16 = 25;
+/// The oldest protocol version this crate is still able to talk with.
+///
+/// Versions that only append new requests and responses keep the older peers compatible,
+/// the features they lack are excluded from the peer capabilities. Versions that change
+/// the encoding of the existing messages raise this one.
+pub const MIN_PROTOCOL_VERSION: u16 = 25;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, MaxSize)]
 pub enum RequestHeader {
     Handshake(PeerInfo),
     AddImage(ImageInfo),
@@ -16,18 +56,88 @@ pub enum RequestHeader {
     HideImage,
     ClearImages,
     Debug,
+    /// Request the device firmware information.
+    FirmwareInfo,
+    /// Remove image with the specified ID
+    DeleteImage(ImageId),
+    /// Read image with the specified ID, image bytes are sent as a response payload.
+    ReadImage(ImageId),
+    /// List metadata of the all stored images, metadata entries are sent as a response payload.
+    ListImages,
+    /// Find image with the specified name.
+    FindImage(ImageName),
+    /// Update the device configuration.
+    ///
+    /// Changing the strip length removes all stored images.
+    SetConfig(Configuration),
+    /// Request the current device configuration.
+    GetConfig,
+    /// Start showing image with the specified ID in the given playback mode.
+    ShowImageWithPlayback(ImageId, Playback),
+    /// Store a new playlist.
+    AddPlaylist(Playlist),
+    /// Request the number of the stored playlists.
+    ListPlaylists,
+    /// Read playlist with the specified ID.
+    ReadPlaylist(PlaylistId),
+    /// Remove playlist with the specified ID.
+    ///
+    /// Identifiers of the playlists that follow the removed one are decreased by one.
+    DeletePlaylist(PlaylistId),
+    /// Start showing images of the playlist with the specified ID one after another.
+    StartPlaylist(PlaylistId),
+    /// Show lines with the specified refresh rate straight away without saving them.
+    ///
+    /// Lines of the strip pixels in the configured pixel format are sent as a request payload,
+    /// the response is sent after the last line has been shown.
+    StreamLines(Hertz),
+    /// Request results of the last request forwarded to the secondary devices.
+    ///
+    /// The main device forwards the `AddImage`, `ShowImage`, `ShowImageWithPlayback`,
+    /// `ShowImageAt`, `HideImage` and `ClearImages` requests to the connected secondary devices
+    /// after it has handled them itself. The shown images are started on all devices at the
+    /// same time.
+    SecondaryResults,
+    /// Request the current device time to estimate the offset between the peer clocks.
+    SyncClock,
+    /// Start showing image with the specified ID in the given playback mode when the device
+    /// time reaches the specified value.
+    ///
+    /// The image is shown immediately if the time has already passed.
+    ShowImageAt(ImageId, Playback, DeviceTime),
+    /// Answer the authentication challenge sent in reply to the handshake.
+    ///
+    /// The device completes the handshake if the tag matches its pre-shared key, until then
+    /// all other requests are refused.
+    Authenticate(AuthTag),
+    /// Replace the pre-shared key of the device or remove it to allow any client.
+    ///
+    /// The new key is sent unencrypted.
+    SetAuthKey(Option<AuthKey>),
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug, MaxSize)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, MaxSize)]
 pub enum ResponseHeader {
     Empty,
     Handshake(PeerInfo),
     AddImage(ImageId),
     Error(crate::Error),
+    FirmwareInfo(FirmwareInfo),
+    ReadImage(ImageInfo),
+    ListImages(ImageId),
+    FindImage(ImageId),
+    GetConfig(Configuration),
+    AddPlaylist(PlaylistId),
+    ListPlaylists(PlaylistId),
+    ReadPlaylist(Playlist),
+    SecondaryResults(SecondaryResults),
+    SyncClock(DeviceTime),
+    /// The device requires the client to authenticate before the handshake is completed.
+    AuthChallenge(AuthChallenge),
 }
 
 impl ResponseHeader {
-    pub const fn empty(self) -> crate::Result<()> {
+    pub fn empty(self) -> crate::Result<()> {
         match self {
             Self::Empty => Ok(()),
             Self::Error(err) => Err(err),
@@ -35,7 +145,7 @@ impl ResponseHeader {
         }
     }
 
-    pub const fn handshake(self) -> crate::Result<PeerInfo> {
+    pub fn handshake(self) -> crate::Result<PeerInfo> {
         match self {
             Self::Handshake(info) => Ok(info),
             Self::Error(err) => Err(err),
@@ -43,17 +153,97 @@ impl ResponseHeader {
         }
     }
 
-    pub const fn add_image(self) -> crate::Result<ImageId> {
+    pub fn add_image(self) -> crate::Result<ImageId> {
         match self {
             Self::AddImage(id) => Ok(id),
             Self::Error(err) => Err(err),
             _ => Err(crate::Error::UnexpectedResponse),
         }
     }
+
+    pub fn read_image(self) -> crate::Result<ImageInfo> {
+        match self {
+            Self::ReadImage(info) => Ok(info),
+            Self::Error(err) => Err(err),
+            _ => Err(crate::Error::UnexpectedResponse),
+        }
+    }
+
+    pub fn list_images(self) -> crate::Result<ImageId> {
+        match self {
+            Self::ListImages(count) => Ok(count),
+            Self::Error(err) => Err(err),
+            _ => Err(crate::Error::UnexpectedResponse),
+        }
+    }
+
+    pub fn find_image(self) -> crate::Result<ImageId> {
+        match self {
+            Self::FindImage(id) => Ok(id),
+            Self::Error(err) => Err(err),
+            _ => Err(crate::Error::UnexpectedResponse),
+        }
+    }
+
+    pub fn config(self) -> crate::Result<Configuration> {
+        match self {
+            Self::GetConfig(config) => Ok(config),
+            Self::Error(err) => Err(err),
+            _ => Err(crate::Error::UnexpectedResponse),
+        }
+    }
+
+    pub fn add_playlist(self) -> crate::Result<PlaylistId> {
+        match self {
+            Self::AddPlaylist(id) => Ok(id),
+            Self::Error(err) => Err(err),
+            _ => Err(crate::Error::UnexpectedResponse),
+        }
+    }
+
+    pub fn list_playlists(self) -> crate::Result<PlaylistId> {
+        match self {
+            Self::ListPlaylists(count) => Ok(count),
+            Self::Error(err) => Err(err),
+            _ => Err(crate::Error::UnexpectedResponse),
+        }
+    }
+
+    pub fn read_playlist(self) -> crate::Result<Playlist> {
+        match self {
+            Self::ReadPlaylist(playlist) => Ok(playlist),
+            Self::Error(err) => Err(err),
+            _ => Err(crate::Error::UnexpectedResponse),
+        }
+    }
+
+    pub fn secondary_results(self) -> crate::Result<SecondaryResults> {
+        match self {
+            Self::SecondaryResults(results) => Ok(results),
+            Self::Error(err) => Err(err),
+            _ => Err(crate::Error::UnexpectedResponse),
+        }
+    }
+
+    pub fn sync_clock(self) -> crate::Result<DeviceTime> {
+        match self {
+            Self::SyncClock(time) => Ok(time),
+            Self::Error(err) => Err(err),
+            _ => Err(crate::Error::UnexpectedResponse),
+        }
+    }
+
+    pub fn firmware_info(self) -> crate::Result<FirmwareInfo> {
+        match self {
+            Self::FirmwareInfo(info) => Ok(info),
+            Self::Error(err) => Err(err),
+            _ => Err(crate::Error::UnexpectedResponse),
+        }
+    }
 }
 
 /// Possible header types.
-#[derive(Serialize, PartialEq, Eq, Clone, Copy, Debug, MaxSize)]
+#[derive(Serialize, PartialEq, Eq, Clone, Debug, MaxSize)]
 #[serde(untagged)]
 pub enum Headers {
     Request(RequestHeader),
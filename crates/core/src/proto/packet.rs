@@ -1,8 +1,7 @@
-pub use endian_codec::PackedSize;
-use endian_codec::{DecodeLE, EncodeLE};
+pub use endian_codec::{DecodeLE, EncodeLE, PackedSize};
 use postcard::experimental::max_size::MaxSize;
 
-use super::{Headers, RequestHeader, ResponseHeader};
+use super::{types::DiscoveryReply, Headers, RequestHeader, ResponseHeader};
 use crate::io::{BlockingRead, BlockingReadExactError};
 
 pub trait FromPacket: Sized {
@@ -128,6 +127,20 @@ impl FromPacket for ResponseHeader {
     }
 }
 
+impl DiscoveryReply {
+    /// Max encoded reply length.
+    pub const MAX_LEN: usize = Self::POSTCARD_MAX_SIZE;
+
+    pub fn encode<'a>(&self, buf: &'a mut [u8]) -> &'a mut [u8] {
+        assert!(buf.len() >= Self::MAX_LEN);
+        postcard::to_slice(self, buf).unwrap()
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, postcard::Error> {
+        postcard::from_bytes(buf)
+    }
+}
+
 impl Headers {
     pub fn encode<'a>(&self, buf: &'a mut [u8], payload_len: usize) -> &'a mut [u8] {
         assert!(buf.len() >= Packet::MAX_LEN);
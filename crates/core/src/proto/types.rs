@@ -1,9 +1,17 @@
-use core::{fmt::Display, str::FromStr, time::Duration};
+use core::{
+    fmt::Display,
+    ops::{BitAnd, BitOr},
+    str::FromStr,
+    time::Duration,
+};
 
 use endian_codec::{DecodeLE, EncodeLE, PackedSize};
+use no_std_net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use postcard::experimental::max_size::MaxSize;
 use serde::{Deserialize, Serialize};
 
+use super::{MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+
 #[repr(u8)]
 #[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Copy, Debug)]
 pub enum DeviceRole {
@@ -31,6 +39,13 @@ pub struct PeerInfo {
     pub role: DeviceRole,
     pub group_id: Option<u32>,
     pub device_info: Option<DeviceInfo>,
+    /// Protocol version implemented by the peer.
+    ///
+    /// This field and the capabilities go last: an older peer just ignores them, while a newer
+    /// peer fails to decode the handshake of an older one instead of misinterpreting it.
+    pub version: u16,
+    /// Optional protocol features supported by the peer.
+    pub capabilities: Capabilities,
 }
 
 impl PeerInfo {
@@ -40,8 +55,112 @@ impl PeerInfo {
             role: DeviceRole::Client,
             group_id: None,
             device_info: None,
+            version: PROTOCOL_VERSION,
+            capabilities: Capabilities::ALL,
         }
     }
+
+    /// Returns `true` if this implementation is able to talk with the peer.
+    #[must_use]
+    pub const fn is_compatible(&self) -> bool {
+        self.version >= MIN_PROTOCOL_VERSION
+    }
+}
+
+/// Reply of the device to the discovery request.
+#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Debug)]
+pub struct DiscoveryReply {
+    /// Name of the device in the network.
+    pub name: Option<DeviceName>,
+    /// Port on which the device accepts client connections.
+    pub port: u16,
+    /// The same information which the device reports in the handshake.
+    pub peer_info: PeerInfo,
+}
+
+/// A set of optional protocol features supported by a peer.
+#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Copy, Debug, Hash, Default)]
+pub struct Capabilities(pub u32);
+
+impl Capabilities {
+    /// No optional features.
+    pub const NONE: Self = Self(0);
+    /// Storing images and rendering them on the strip.
+    pub const IMAGES: Self = Self(1 << 0);
+    /// Printing debug messages in the device log.
+    pub const DEBUG: Self = Self(1 << 1);
+    /// Requesting the full firmware information.
+    pub const FIRMWARE_INFO: Self = Self(1 << 2);
+    /// Removing a single image.
+    pub const DELETE_IMAGE: Self = Self(1 << 3);
+    /// Reading stored images back from the device.
+    pub const READ_IMAGE: Self = Self(1 << 4);
+    /// Listing metadata of the stored images.
+    pub const LIST_IMAGES: Self = Self(1 << 5);
+    /// Storing image names and looking images up by name.
+    pub const IMAGE_NAMES: Self = Self(1 << 6);
+    /// Device configuration can be read and updated remotely.
+    pub const CONFIG: Self = Self(1 << 7);
+    /// Storing run-length encoded images.
+    pub const RLE_IMAGES: Self = Self(1 << 8);
+    /// Storing palette-indexed images.
+    pub const PALETTE_IMAGES: Self = Self(1 << 9);
+    /// Storing and rendering RGBW images.
+    pub const RGBW_PIXELS: Self = Self(1 << 10);
+    /// Selecting the image playback mode per show request.
+    pub const PLAYBACK_MODES: Self = Self(1 << 11);
+    /// Storing and running playlists on the device.
+    pub const PLAYLISTS: Self = Self(1 << 12);
+    /// Device is able to show streamed lines without saving them.
+    pub const STREAMING: Self = Self(1 << 13);
+    /// Forwarding commands to the connected secondary devices.
+    pub const SECONDARIES: Self = Self(1 << 14);
+    /// Synchronising clocks and starting images at the given device time.
+    pub const CLOCK_SYNC: Self = Self(1 << 15);
+    /// Authenticating the clients with the pre-shared key in the handshake.
+    pub const AUTH: Self = Self(1 << 16);
+    /// All features known by this implementation.
+    pub const ALL: Self = Self(
+        Self::IMAGES.0
+            | Self::DEBUG.0
+            | Self::FIRMWARE_INFO.0
+            | Self::DELETE_IMAGE.0
+            | Self::READ_IMAGE.0
+            | Self::LIST_IMAGES.0
+            | Self::IMAGE_NAMES.0
+            | Self::CONFIG.0
+            | Self::RLE_IMAGES.0
+            | Self::PALETTE_IMAGES.0
+            | Self::RGBW_PIXELS.0
+            | Self::PLAYBACK_MODES.0
+            | Self::PLAYLISTS.0
+            | Self::STREAMING.0
+            | Self::SECONDARIES.0
+            | Self::CLOCK_SYNC.0
+            | Self::AUTH.0,
+    );
+
+    /// Returns `true` if all of the `other` features are present in this set.
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitAnd for Capabilities {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
 }
 
 #[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Copy, Debug)]
@@ -51,6 +170,8 @@ pub struct DeviceInfo {
     pub current_image: Option<ImageId>,
     /// Indicates whether there is an active image rendering task.
     pub active: bool,
+    /// Position of the running playlist.
+    pub playlist: Option<PlaylistPosition>,
 }
 
 impl DeviceInfo {
@@ -61,18 +182,418 @@ impl DeviceInfo {
             images_count: ImageId(0),
             current_image: None,
             active: false,
+            playlist: None,
         }
     }
 }
 
-#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Copy, Debug)]
+/// A global application configuration.
+#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Debug)]
+pub struct Configuration {
+    /// The number of LEDs in the strip.
+    pub strip_len: u16,
+    /// Index of the picture which will be show by default.
+    pub current_image: Option<ImageId>,
+    /// Global brightness of the strip, `255` means the full brightness.
+    pub brightness: u8,
+    /// Gamma correction curve applied to the image colors.
+    pub gamma: Gamma,
+    /// Order of the color channels expected by the strip.
+    pub color_order: ColorOrder,
+    /// Pixel format of the strip LEDs.
+    pub pixel_format: PixelFormat,
+    /// Start showing the current image right after the device boot.
+    pub autoplay: bool,
+    /// Name of the device in the network.
+    pub name: Option<DeviceName>,
+    /// Show the lines received from the DMX input while the device is idle.
+    ///
+    /// The input is ignored while the device has an authentication key, since anyone
+    /// in the network is able to send the DMX packets.
+    pub dmx_input: bool,
+    /// DMX over UDP protocol of the input.
+    pub dmx_protocol: DmxProtocol,
+    /// DMX universe which is mapped onto the strip.
+    pub dmx_universe: u16,
+    /// Accept connections of the secondary devices.
+    pub accept_secondaries: bool,
+    /// Run the device as a secondary one of the given main device.
+    pub main_device: Option<MainDevice>,
+}
+
+impl Configuration {
+    /// Default strip LED length.
+    pub const DEFAULT_STRIP_LED_LEN: u16 = 24;
+}
+
+impl Default for Configuration {
+    fn default() -> Self {
+        Self {
+            strip_len: Self::DEFAULT_STRIP_LED_LEN,
+            current_image: None,
+            brightness: u8::MAX,
+            gamma: Gamma::LINEAR,
+            color_order: ColorOrder::Grb,
+            pixel_format: PixelFormat::Rgb,
+            autoplay: false,
+            name: None,
+            dmx_input: false,
+            dmx_protocol: DmxProtocol::ArtNet,
+            dmx_universe: 0,
+            accept_secondaries: false,
+            main_device: None,
+        }
+    }
+}
+
+/// Order in which the LED strip receives the color channels of the pixel.
+///
+/// Images are always stored in the RGB order, so pixels are reordered before sending them
+/// to the strip with the different order. The most common ws2812 strips expect the GRB order.
+#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum ColorOrder {
+    Rgb,
+    #[default]
+    Grb,
+    Bgr,
+}
+
+/// DMX over UDP protocol.
+#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum DmxProtocol {
+    /// Art-Net protocol, universes are numbered from zero.
+    #[default]
+    ArtNet,
+    /// E1.31 (sACN) protocol, universes are numbered from one.
+    E131,
+}
+
+impl DmxProtocol {
+    /// Returns the standard UDP port of the protocol.
+    #[must_use]
+    pub const fn default_port(self) -> u16 {
+        match self {
+            Self::ArtNet => 6454,
+            Self::E131 => 5568,
+        }
+    }
+}
+
+/// Gamma correction exponent multiplied by ten, i.e. `Gamma(22)` means the `2.2` exponent.
+#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Copy, Debug, PartialOrd, Ord)]
+pub struct Gamma(pub u8);
+
+impl Gamma {
+    /// Linear curve which leaves colors unchanged.
+    pub const LINEAR: Self = Self(10);
+
+    /// Returns the gamma exponent value.
+    #[must_use]
+    pub fn as_f32(self) -> f32 {
+        f32::from(self.0) / 10_f32
+    }
+}
+
+impl Default for Gamma {
+    fn default() -> Self {
+        Self::LINEAR
+    }
+}
+
+/// The maximum length of the image name in bytes.
+pub const IMAGE_NAME_LEN: usize = 16;
+/// A short human-readable image name.
+pub type ImageName = heapless::String<IMAGE_NAME_LEN>;
+/// The maximum length of the device name in bytes.
+pub const DEVICE_NAME_LEN: usize = 16;
+/// A short human-readable device name, which is reported to the discovery requests.
+pub type DeviceName = heapless::String<DEVICE_NAME_LEN>;
+
+#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Debug)]
 pub struct ImageInfo {
     pub refresh_rate: Hertz,
     pub strip_len: u16,
+    /// Optional image name, which can be used to look the image up.
+    pub name: Option<ImageName>,
+    /// Encoding of the image bytes.
+    pub encoding: ImageEncoding,
+    /// Format of the image pixels.
+    pub pixel_format: PixelFormat,
+}
+
+impl ImageInfo {
+    /// Creates an information about the unnamed image.
+    #[must_use]
+    pub const fn new(refresh_rate: Hertz, strip_len: u16) -> Self {
+        Self {
+            refresh_rate,
+            strip_len,
+            name: None,
+            encoding: ImageEncoding::Raw,
+            pixel_format: PixelFormat::Rgb,
+        }
+    }
+}
+
+/// Format of the single pixel.
+#[repr(u8)]
+#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum PixelFormat {
+    /// Red, green and blue channels.
+    #[default]
+    Rgb = 0,
+    /// Red, green, blue and white channels.
+    Rgbw = 1,
+}
+
+impl PixelFormat {
+    /// Returns the pixel length in bytes.
+    #[must_use]
+    pub const fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Rgb => 3,
+            Self::Rgbw => 4,
+        }
+    }
+}
+
+impl PackedSize for PixelFormat {
+    const PACKED_LEN: usize = 1;
+}
+
+impl EncodeLE for PixelFormat {
+    fn encode_as_le_bytes(&self, bytes: &mut [u8]) {
+        (*self as u8).encode_as_le_bytes(bytes);
+    }
 }
 
+impl DecodeLE for PixelFormat {
+    fn decode_from_le_bytes(bytes: &[u8]) -> Self {
+        match u8::decode_from_le_bytes(bytes) {
+            1 => Self::Rgbw,
+            _ => Self::Rgb,
+        }
+    }
+}
+
+/// Encoding of the stored image bytes.
+#[repr(u8)]
+#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum ImageEncoding {
+    /// Raw pixel bytes.
+    #[default]
+    Raw = 0,
+    /// Run-length encoded pixels, see the [`crate::io::rle`] module for details.
+    Rle = 1,
+    /// Palette-indexed pixels, see the [`crate::io::palette`] module for details.
+    Palette = 2,
+}
+
+impl PackedSize for ImageEncoding {
+    const PACKED_LEN: usize = 1;
+}
+
+impl EncodeLE for ImageEncoding {
+    fn encode_as_le_bytes(&self, bytes: &mut [u8]) {
+        (*self as u8).encode_as_le_bytes(bytes);
+    }
+}
+
+impl DecodeLE for ImageEncoding {
+    fn decode_from_le_bytes(bytes: &[u8]) -> Self {
+        match u8::decode_from_le_bytes(bytes) {
+            1 => Self::Rle,
+            2 => Self::Palette,
+            _ => Self::Raw,
+        }
+    }
+}
+
+/// Metadata of the stored image.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, PackedSize, EncodeLE, DecodeLE)]
+pub struct ImageMetadata {
+    /// Image length in bytes.
+    pub len: u32,
+    /// The number of the image lines.
+    pub lines: u32,
+    /// Refresh rate of the single image line.
+    pub refresh_rate: Hertz,
+    /// Encoding of the image bytes.
+    pub encoding: ImageEncoding,
+    /// Format of the image pixels.
+    pub pixel_format: PixelFormat,
+}
+
+/// Order in which the image lines are shown.
+#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum PlaybackDirection {
+    /// From the first line to the last one.
+    #[default]
+    Forward,
+    /// From the last line to the first one.
+    Reverse,
+    /// Forward and then backward without repeating the turning lines.
+    PingPong,
+}
+
+/// Image playback mode.
+#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct Playback {
+    /// Order in which the image lines are shown.
+    pub direction: PlaybackDirection,
+    /// The number of the image repetitions, the strip is blanked after the last one.
+    ///
+    /// The image is repeated endlessly if it is not set.
+    pub repeat: Option<u16>,
+}
+
+impl Playback {
+    /// Shows the image once and then blanks the strip.
+    pub const ONCE: Self = Self {
+        direction: PlaybackDirection::Forward,
+        repeat: Some(1),
+    };
+}
+
+/// The maximum number of the stored playlists.
+pub const MAX_PLAYLISTS: usize = 4;
+/// The maximum number of the playlist entries.
+pub const MAX_PLAYLIST_LEN: usize = 12;
+
+/// The maximum number of the secondary devices connected to the main one.
+pub const MAX_SECONDARIES: usize = 8;
+
+/// Result of the request forwarded to a single secondary device.
+#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Copy, Debug)]
+pub struct SecondaryResult {
+    /// Group identifier of the secondary device.
+    pub group_id: Option<u32>,
+    /// Error returned by the secondary device, if the request has failed.
+    pub error: Option<crate::Error>,
+}
+
+/// Results of the request forwarded to the connected secondary devices.
+pub type SecondaryResults = heapless::Vec<SecondaryResult, MAX_SECONDARIES>;
+
+/// Main device which the secondary device connects to.
+#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Copy, Debug)]
+pub struct MainDevice {
+    /// IP address of the main device, IPv4 addresses are stored as the IPv4-mapped ones.
+    pub ip: [u8; 16],
+    /// Port of the main device which accepts the secondary devices.
+    pub port: u16,
+    /// Group identifier of the secondary device.
+    pub group_id: Option<u32>,
+}
+
+impl MainDevice {
+    /// Creates a new main device with the given socket address.
+    #[must_use]
+    pub const fn new(address: SocketAddr, group_id: Option<u32>) -> Self {
+        let ip = match address.ip() {
+            IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+            IpAddr::V6(ip) => ip,
+        };
+        Self {
+            ip: ip.octets(),
+            port: address.port(),
+            group_id,
+        }
+    }
+
+    /// Returns the socket address of the main device.
+    #[must_use]
+    pub fn address(&self) -> SocketAddr {
+        let ip = match self.ip {
+            [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, a, b, c, d] => {
+                IpAddr::V4(Ipv4Addr::new(a, b, c, d))
+            }
+            octets => IpAddr::V6(Ipv6Addr::from(octets)),
+        };
+        SocketAddr::new(ip, self.port)
+    }
+}
+
+/// How long the playlist entry is shown.
+#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum EntryLength {
+    /// Show the image for the given number of milliseconds.
+    Millis(u32),
+    /// Show the image the given number of times.
+    Loops(u16),
+}
+
+impl EntryLength {
+    /// Returns `true` if the entry is not shown at all.
+    #[must_use]
+    pub const fn is_empty(self) -> bool {
+        matches!(self, Self::Millis(0) | Self::Loops(0))
+    }
+}
+
+/// A single playlist entry.
 #[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Copy, Debug)]
-pub struct FirmwareInfo;
+pub struct PlaylistEntry {
+    /// Identifier of the shown image.
+    pub image_id: ImageId,
+    /// How long the image is shown.
+    pub length: EntryLength,
+}
+
+/// An ordered list of images which are shown one after another.
+#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Debug, Default)]
+pub struct Playlist {
+    pub entries: heapless::Vec<PlaylistEntry, MAX_PLAYLIST_LEN>,
+}
+
+impl Playlist {
+    /// Removes entries of the image with the given identifier and decreases identifiers
+    /// of the images that follow the removed one.
+    pub fn remove_image(&mut self, image_id: ImageId) {
+        self.entries.retain(|entry| entry.image_id != image_id);
+        for entry in &mut self.entries {
+            if entry.image_id > image_id {
+                entry.image_id.0 -= 1;
+            }
+        }
+    }
+}
+
+/// All stored playlists.
+pub type Playlists = heapless::Vec<Playlist, MAX_PLAYLISTS>;
+
+/// Position of the running playlist.
+#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Copy, Debug)]
+pub struct PlaylistPosition {
+    /// Identifier of the running playlist.
+    pub playlist_id: PlaylistId,
+    /// Index of the currently shown entry.
+    pub entry: u16,
+}
+
+/// The maximum length of the firmware version in bytes.
+pub const FIRMWARE_VERSION_LEN: usize = 16;
+/// The maximum length of the board name in bytes.
+pub const BOARD_NAME_LEN: usize = 16;
+/// The maximum length of the firmware build identifier in bytes.
+pub const BUILD_ID_LEN: usize = 40;
+
+/// Information about the device firmware and hardware limits.
+#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Debug)]
+pub struct FirmwareInfo {
+    /// Firmware version.
+    pub version: heapless::String<FIRMWARE_VERSION_LEN>,
+    /// Name of the board the firmware was built for.
+    pub board_name: heapless::String<BOARD_NAME_LEN>,
+    /// Firmware build identifier, usually a commit hash.
+    pub build_id: heapless::String<BUILD_ID_LEN>,
+    /// The maximum supported length of the LED strip.
+    pub max_strip_len: u16,
+    /// The maximum supported refresh rate of the single strip line.
+    pub max_refresh_rate: Hertz,
+    /// Storage capacity for the images in bytes.
+    pub storage_capacity: u32,
+}
 
 #[derive(
     Serialize,
@@ -107,6 +628,66 @@ pub struct Hertz(pub u32);
 )]
 pub struct ImageId(pub u16);
 
+#[derive(
+    Serialize,
+    Deserialize,
+    MaxSize,
+    PartialEq,
+    Eq,
+    Clone,
+    Copy,
+    Debug,
+    PartialOrd,
+    Ord,
+    Hash,
+    Default,
+)]
+pub struct PlaylistId(pub u16);
+
+/// Monotonic device time in microseconds elapsed since the device start.
+#[derive(
+    Serialize,
+    Deserialize,
+    MaxSize,
+    PartialEq,
+    Eq,
+    Clone,
+    Copy,
+    Debug,
+    PartialOrd,
+    Ord,
+    Hash,
+    Default,
+)]
+pub struct DeviceTime(pub u64);
+
+impl From<Duration> for DeviceTime {
+    fn from(value: Duration) -> Self {
+        // The device time doesn't reach the `u64::MAX` microseconds in practice.
+        Self(u64::try_from(value.as_micros()).unwrap_or(u64::MAX))
+    }
+}
+
+impl From<DeviceTime> for Duration {
+    fn from(value: DeviceTime) -> Self {
+        Self::from_micros(value.0)
+    }
+}
+
+/// The maximum length of the pre-shared authentication key in bytes.
+pub const AUTH_KEY_LEN: usize = 64;
+/// A secret key shared by the device and its clients.
+pub type AuthKey = heapless::Vec<u8, AUTH_KEY_LEN>;
+
+/// Random challenge which the device sends in reply to the handshake of the client
+/// that has to authenticate.
+#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Copy, Debug)]
+pub struct AuthChallenge(pub [u8; 16]);
+
+/// HMAC-SHA256 of the authentication challenge computed with the pre-shared key.
+#[derive(Serialize, Deserialize, MaxSize, PartialEq, Eq, Clone, Copy, Debug)]
+pub struct AuthTag(pub [u8; 32]);
+
 impl FromStr for Hertz {
     type Err = <u32 as FromStr>::Err;
 
@@ -145,6 +726,31 @@ impl Display for ImageId {
     }
 }
 
+impl Display for PlaylistId {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for Gamma {
+    type Err = core::num::ParseFloatError;
+
+    /// Parses a gamma exponent in the decimal notation, e.g. `2.2`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = f32::from_str(s)?;
+        // Exponent value is clamped to the representable range.
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let gamma = libm::roundf(value.clamp(0_f32, 25.5) * 10_f32) as u8;
+        Ok(Self(gamma))
+    }
+}
+
+impl Display for Gamma {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}.{}", self.0 / 10, self.0 % 10)
+    }
+}
+
 impl Display for Hertz {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         self.0.fmt(f)
@@ -0,0 +1,83 @@
+//! Pre-shared key authentication of the clients.
+//!
+//! The device with the pre-shared key answers the handshake with a challenge, and the client
+//! proves that it knows the key by sending back the HMAC-SHA256 of the challenge. The key itself
+//! is never sent over the network, except when it is replaced by an authenticated client.
+
+use cyberpixie_core::proto::types::{AuthChallenge, AuthTag, DeviceTime};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Prefix of the data from which the device derives its challenges.
+const CHALLENGE_PREFIX: &[u8] = b"CPXA";
+
+/// Creates a new challenge for the client.
+///
+/// The challenge is derived from the key, the random seed chosen at the device start,
+/// the device time and the sequence number of the challenge. So it cannot be predicted
+/// without the key, never repeats while the device is running and differs from the challenges
+/// of the previous device starts unless the seed repeats.
+#[must_use]
+pub fn challenge(key: &[u8], seed: u64, time: DeviceTime, counter: u32) -> AuthChallenge {
+    let mut mac = new_mac(key);
+    mac.update(CHALLENGE_PREFIX);
+    mac.update(&seed.to_le_bytes());
+    mac.update(&time.0.to_le_bytes());
+    mac.update(&counter.to_le_bytes());
+
+    let bytes = mac.finalize().into_bytes();
+    let mut challenge = AuthChallenge([0_u8; 16]);
+    let len = challenge.0.len();
+    challenge.0.copy_from_slice(&bytes[..len]);
+    challenge
+}
+
+/// Computes the answer to the given challenge.
+#[must_use]
+pub fn sign(key: &[u8], challenge: &AuthChallenge) -> AuthTag {
+    let mut mac = new_mac(key);
+    mac.update(&challenge.0);
+    AuthTag(mac.finalize().into_bytes().into())
+}
+
+/// Checks the answer to the given challenge in constant time.
+#[must_use]
+pub fn verify(key: &[u8], challenge: &AuthChallenge, tag: &AuthTag) -> bool {
+    let mut mac = new_mac(key);
+    mac.update(&challenge.0);
+    mac.verify_slice(&tag.0).is_ok()
+}
+
+fn new_mac(key: &[u8]) -> HmacSha256 {
+    // HMAC accepts keys of any length.
+    HmacSha256::new_from_slice(key).expect("HMAC key of any length is valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use cyberpixie_core::proto::types::{AuthChallenge, DeviceTime};
+
+    use super::{challenge, sign, verify};
+
+    #[test]
+    fn test_sign_and_verify() {
+        let challenge = AuthChallenge(*b"what do ya want ");
+        let tag = sign(b"Jefe", &challenge);
+        assert!(verify(b"Jefe", &challenge, &tag));
+        assert!(!verify(b"Jeff", &challenge, &tag));
+        assert!(!verify(b"Jefe", &AuthChallenge([0_u8; 16]), &tag));
+    }
+
+    #[test]
+    fn test_challenges_differ() {
+        let first = challenge(b"key", 42, DeviceTime(1_000), 0);
+        assert_ne!(first, challenge(b"key", 42, DeviceTime(1_000), 1));
+        assert_ne!(first, challenge(b"key", 42, DeviceTime(1_001), 0));
+        assert_ne!(first, challenge(b"other key", 42, DeviceTime(1_000), 0));
+        // The same time and counter after the device restart.
+        assert_ne!(first, challenge(b"key", 43, DeviceTime(1_000), 0));
+        assert_eq!(first, challenge(b"key", 42, DeviceTime(1_000), 0));
+    }
+}
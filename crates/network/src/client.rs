@@ -1,49 +1,254 @@
+use core::time::Duration;
+
 use cyberpixie_core::{
-    io::{AsyncRead, AsyncWrite},
+    io::{AsyncRead, AsyncWrite, BlockingRead, ExactSizeRead},
     proto::{
-        types::{Hertz, ImageId, ImageInfo, PeerInfo},
-        RequestHeader,
+        packet::{DecodeLE, PackedSize},
+        types::{
+            AuthChallenge, AuthKey, Capabilities, Configuration, DeviceTime, FirmwareInfo, Hertz,
+            ImageEncoding, ImageId, ImageInfo, ImageMetadata, PeerInfo, PixelFormat, Playback,
+            Playlist, PlaylistId, SecondaryResults,
+        },
+        RequestHeader, ResponseHeader,
     },
 };
 
-use crate::{connection::Connection, CyberpixieResult, NetworkSocket, SocketAddr};
+use crate::{
+    auth, clock::ClockOffset, connection::Connection, CyberpixieError, CyberpixieResult,
+    NetworkSocket, PayloadReader, SocketAddr,
+};
+
+/// The number of time exchanges used to estimate the clock offset.
+const CLOCK_SYNC_ROUNDS: usize = 4;
 
 /// Cyberpixie network async client.
 pub struct Client<C> {
     connection: Connection<C>,
+    /// Features supported by both this client and the connected peer.
+    capabilities: Capabilities,
 }
 
 impl<C: AsyncRead + AsyncWrite> Client<C> {
     /// Establish connection with the given peer.
     pub async fn connect<'a, S, I>(socket: &'a mut S, address: I) -> CyberpixieResult<Self>
+    where
+        S: NetworkSocket<Connection<'a> = C>,
+        I: Into<SocketAddr>,
+    {
+        Self::connect_with_key(socket, address, None).await
+    }
+
+    /// Establish connection with the given peer and authenticate with the given pre-shared
+    /// key if the peer requires it.
+    pub async fn connect_with_key<'a, S, I>(
+        socket: &'a mut S,
+        address: I,
+        key: Option<&[u8]>,
+    ) -> CyberpixieResult<Self>
     where
         S: NetworkSocket<Connection<'a> = C>,
         I: Into<SocketAddr>,
     {
         let address = address.into();
         let socket = socket.connect(address).await?;
-        Self::new(Connection::incoming(socket)).await
+        Self::new(Connection::incoming(socket), key).await
     }
 
     /// Creates a new client on top of the given connection.
-    async fn new(connection: Connection<C>) -> CyberpixieResult<Self> {
-        let mut client = Self { connection };
-        let peer_info = client.handshake(PeerInfo::client()).await?;
+    ///
+    /// The client refuses to work with a peer that uses an incompatible protocol version,
+    /// otherwise it only uses features supported by both sides.
+    async fn new(connection: Connection<C>, key: Option<&[u8]>) -> CyberpixieResult<Self> {
+        let mut client = Self {
+            connection,
+            capabilities: Capabilities::NONE,
+        };
+        let peer_info = client.handshake(PeerInfo::client(), key).await?;
         log::info!("Handshake with the {peer_info:?}");
         Ok(client)
     }
 
+    /// Creates a new client on top of the connection established by a secondary device.
+    ///
+    /// Unlike the [`Self::connect`] method, the handshake is sent by the secondary device,
+    /// it is answered with the given host information. If the key is given, the secondary
+    /// device has to answer the given challenge with it first. Returns the client together
+    /// with the secondary device information.
+    pub async fn from_secondary(
+        connection: C,
+        host_info: PeerInfo,
+        auth: Option<(&[u8], AuthChallenge)>,
+    ) -> CyberpixieResult<(Self, PeerInfo)> {
+        let mut connection = Connection::incoming(connection);
+        let request = match connection.receive_request().await {
+            Ok(request) => request,
+            Err(err) => {
+                let err = err.in_handshake();
+                if err == CyberpixieError::UnsupportedProtocolVersion {
+                    connection.send_message(ResponseHeader::Error(err)).await?;
+                }
+                return Err(err);
+            }
+        };
+        let RequestHeader::Handshake(peer_info) = request.header else {
+            return Err(CyberpixieError::UnexpectedResponse);
+        };
+        if request.payload.is_some() {
+            return Err(CyberpixieError::UnexpectedResponse);
+        }
+
+        if !peer_info.is_compatible() {
+            log::warn!(
+                "Secondary device uses an unsupported protocol version {}",
+                peer_info.version
+            );
+            connection
+                .send_message(ResponseHeader::Error(
+                    CyberpixieError::UnsupportedProtocolVersion,
+                ))
+                .await?;
+            return Err(CyberpixieError::UnsupportedProtocolVersion);
+        }
+        if let Some((key, challenge)) = auth {
+            connection
+                .send_message(ResponseHeader::AuthChallenge(challenge))
+                .await?;
+            let request = connection.receive_request().await?;
+            let err = match request.header {
+                RequestHeader::Authenticate(tag) if auth::verify(key, &challenge, &tag) => None,
+                RequestHeader::Authenticate(_) => Some(CyberpixieError::AuthenticationFailed),
+                _ => Some(CyberpixieError::AuthenticationRequired),
+            };
+            if let Some(err) = err {
+                log::warn!("Secondary device has failed to authenticate");
+                connection.send_message(ResponseHeader::Error(err)).await?;
+                return Err(err);
+            }
+        }
+        connection
+            .send_message(ResponseHeader::Handshake(host_info))
+            .await?;
+
+        let client = Self {
+            connection,
+            capabilities: host_info.capabilities & peer_info.capabilities,
+        };
+        Ok((client, peer_info))
+    }
+
     /// Performs handshake between peers and returns the information about the connected peer.
-    async fn handshake(&mut self, host_info: PeerInfo) -> CyberpixieResult<PeerInfo> {
+    ///
+    /// If the peer answers with the authentication challenge, the given key is used to
+    /// answer it.
+    async fn handshake(
+        &mut self,
+        host_info: PeerInfo,
+        key: Option<&[u8]>,
+    ) -> CyberpixieResult<PeerInfo> {
         self.connection
             .send_message(RequestHeader::Handshake(host_info))
             .await?;
-        self.connection.receive_response().await?.header.handshake()
+        let mut response = self
+            .connection
+            .receive_response()
+            .await
+            .map_err(CyberpixieError::in_handshake)?
+            .header;
+        if let ResponseHeader::AuthChallenge(challenge) = response {
+            let key = key.ok_or(CyberpixieError::AuthenticationRequired)?;
+            self.connection
+                .send_message(RequestHeader::Authenticate(auth::sign(key, &challenge)))
+                .await?;
+            response = self
+                .connection
+                .receive_response()
+                .await
+                .map_err(CyberpixieError::in_handshake)?
+                .header;
+        }
+
+        let peer_info = response.handshake()?;
+
+        if !peer_info.is_compatible() {
+            log::warn!(
+                "Peer uses an unsupported protocol version {}",
+                peer_info.version
+            );
+            return Err(CyberpixieError::UnsupportedProtocolVersion);
+        }
+        self.capabilities = host_info.capabilities & peer_info.capabilities;
+        Ok(peer_info)
+    }
+
+    /// Requests the firmware information of the connected device.
+    pub async fn firmware_info(&mut self) -> CyberpixieResult<FirmwareInfo> {
+        self.ensure_capabilities(Capabilities::FIRMWARE_INFO)?;
+        self.connection
+            .send_message(RequestHeader::FirmwareInfo)
+            .await?;
+
+        let response = self.connection.receive_response().await?;
+        response.header.firmware_info()
+    }
+
+    /// Requests the current configuration of the connected device.
+    pub async fn config(&mut self) -> CyberpixieResult<Configuration> {
+        self.ensure_capabilities(Capabilities::CONFIG)?;
+        self.connection
+            .send_message(RequestHeader::GetConfig)
+            .await?;
+
+        let response = self.connection.receive_response().await?;
+        response.header.config()
+    }
+
+    /// Sends a new configuration to the connected device.
+    ///
+    /// Changing the strip length removes all images stored in the device memory.
+    pub async fn set_config(&mut self, config: Configuration) -> CyberpixieResult<()> {
+        self.ensure_capabilities(Capabilities::CONFIG)?;
+        self.connection
+            .send_message(RequestHeader::SetConfig(config))
+            .await?;
+
+        let response = self.connection.receive_response().await?;
+        response.header.empty()
+    }
+
+    /// Replaces the pre-shared key of the connected device.
+    ///
+    /// Once the key is set, the device refuses the requests of the clients that have not been
+    /// authenticated with it, the `None` key allows any client again.
+    ///
+    /// The new key is sent unencrypted, so anyone who observes the traffic learns it.
+    pub async fn set_auth_key(&mut self, key: Option<AuthKey>) -> CyberpixieResult<()> {
+        self.ensure_capabilities(Capabilities::AUTH)?;
+        self.connection
+            .send_message(RequestHeader::SetAuthKey(key))
+            .await?;
+
+        let response = self.connection.receive_response().await?;
+        response.header.empty()
+    }
+
+    /// Returns the protocol features supported by both this client and the connected peer.
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Checks that the connected peer supports the given features.
+    fn ensure_capabilities(&self, capabilities: Capabilities) -> CyberpixieResult<()> {
+        if self.capabilities.contains(capabilities) {
+            Ok(())
+        } else {
+            Err(CyberpixieError::Unsupported)
+        }
     }
 
     /// Requests an actual information about the connected peer.
     pub async fn peer_info(&mut self) -> CyberpixieResult<PeerInfo> {
-        self.handshake(PeerInfo::client()).await
+        // The connection has been already authenticated.
+        self.handshake(PeerInfo::client(), None).await
     }
 
     /// Sends a new picture to the device and returns a resulting ID.
@@ -53,22 +258,61 @@ impl<C: AsyncRead + AsyncWrite> Client<C> {
         strip_len: u16,
         picture: &[u8],
     ) -> CyberpixieResult<ImageId> {
+        self.add_image_with_info(ImageInfo::new(refresh_rate, strip_len), picture)
+            .await
+    }
+
+    /// Sends a new picture with the given information to the device and returns a resulting ID.
+    pub async fn add_image_with_info(
+        &mut self,
+        info: ImageInfo,
+        picture: &[u8],
+    ) -> CyberpixieResult<ImageId> {
+        self.add_image_with_payload(info, picture.into()).await
+    }
+
+    /// Sends a new picture read from the given payload to the device and returns a resulting ID.
+    pub async fn add_image_with_payload<R: BlockingRead>(
+        &mut self,
+        info: ImageInfo,
+        picture: PayloadReader<R>,
+    ) -> CyberpixieResult<ImageId> {
+        self.ensure_capabilities(Capabilities::IMAGES)?;
+        if info.name.is_some() {
+            self.ensure_capabilities(Capabilities::IMAGE_NAMES)?;
+        }
+        match info.encoding {
+            ImageEncoding::Raw => {}
+            ImageEncoding::Rle => self.ensure_capabilities(Capabilities::RLE_IMAGES)?,
+            ImageEncoding::Palette => self.ensure_capabilities(Capabilities::PALETTE_IMAGES)?,
+        }
+        if info.pixel_format == PixelFormat::Rgbw {
+            self.ensure_capabilities(Capabilities::RGBW_PIXELS)?;
+        }
         self.connection
-            .send_message_with_payload(
-                RequestHeader::AddImage(ImageInfo {
-                    refresh_rate,
-                    strip_len,
-                }),
-                picture,
-            )
+            .send_message_with_payload(RequestHeader::AddImage(info), picture)
             .await?;
 
         let response = self.connection.receive_response().await?;
         response.header.add_image()
     }
 
+    /// Looks up an image with the given name and returns its ID.
+    pub async fn find_image(&mut self, name: &str) -> CyberpixieResult<ImageId> {
+        self.ensure_capabilities(Capabilities::IMAGE_NAMES)?;
+        // Image with the too long name definitely doesn't exist.
+        let name = name.parse().map_err(|()| CyberpixieError::ImageNotFound)?;
+        self.connection
+            .send_message(RequestHeader::FindImage(name))
+            .await?;
+
+        let response = self.connection.receive_response().await?;
+        response.header.find_image()
+    }
+
     /// Sends a debug message to the device, this message will be printed in the device log.
     pub async fn debug(&mut self, msg: &str) -> CyberpixieResult<()> {
+        self.ensure_capabilities(Capabilities::DEBUG)?;
         self.connection
             .send_message_with_payload(RequestHeader::Debug, msg.as_bytes())
             .await?;
@@ -81,6 +325,7 @@ impl<C: AsyncRead + AsyncWrite> Client<C> {
     ///
     /// The whole pictures stored in the device memory will be removed.
     pub async fn clear_images(&mut self) -> CyberpixieResult<()> {
+        self.ensure_capabilities(Capabilities::IMAGES)?;
         self.connection
             .send_message(RequestHeader::ClearImages)
             .await?;
@@ -89,8 +334,83 @@ impl<C: AsyncRead + AsyncWrite> Client<C> {
         response.header.empty()
     }
 
+    /// Sends a delete image with the given ID command.
+    ///
+    /// The identifiers of the images following the removed one are decreased by one.
+    pub async fn delete_image(&mut self, image_id: ImageId) -> CyberpixieResult<()> {
+        self.ensure_capabilities(Capabilities::DELETE_IMAGE)?;
+        self.connection
+            .send_message(RequestHeader::DeleteImage(image_id))
+            .await?;
+
+        let response = self.connection.receive_response().await?;
+        response.header.empty()
+    }
+
+    /// Reads an image with the given ID from the device and writes its bytes to the given output.
+    ///
+    /// Returns the image information, the strip length in it is the image width in pixels.
+    pub async fn read_image<W: AsyncWrite>(
+        &mut self,
+        image_id: ImageId,
+        mut output: W,
+    ) -> CyberpixieResult<ImageInfo> {
+        self.ensure_capabilities(Capabilities::READ_IMAGE)?;
+        self.connection
+            .send_message(RequestHeader::ReadImage(image_id))
+            .await?;
+
+        let response = self.connection.receive_response().await?;
+        let info = response.header.read_image()?;
+        if let Some(mut payload) = response.payload {
+            let mut buf = [0_u8; 256];
+            while payload.bytes_remaining() != 0 {
+                let bytes_read = payload
+                    .read(&mut buf)
+                    .await
+                    .map_err(CyberpixieError::network)?;
+                if bytes_read == 0 {
+                    return Err(CyberpixieError::Network);
+                }
+                output
+                    .write_all(&buf[0..bytes_read])
+                    .await
+                    .map_err(CyberpixieError::storage_write)?;
+            }
+        }
+        Ok(info)
+    }
+
+    /// Requests metadata of the all stored images and appends it to the given output.
+    ///
+    /// Position of the metadata entry matches the image ID, returns the total images count.
+    pub async fn list_images<E: Extend<ImageMetadata>>(
+        &mut self,
+        output: &mut E,
+    ) -> CyberpixieResult<ImageId> {
+        self.ensure_capabilities(Capabilities::LIST_IMAGES)?;
+        self.connection
+            .send_message(RequestHeader::ListImages)
+            .await?;
+
+        let response = self.connection.receive_response().await?;
+        let images_count = response.header.list_images()?;
+        if let Some(mut payload) = response.payload {
+            let mut buf = [0_u8; ImageMetadata::PACKED_LEN];
+            while payload.bytes_remaining() != 0 {
+                payload
+                    .read_exact(&mut buf)
+                    .await
+                    .map_err(CyberpixieError::network)?;
+                output.extend(Some(ImageMetadata::decode_from_le_bytes(&buf)));
+            }
+        }
+        Ok(images_count)
+    }
+
     /// Sends a show image with the given ID command.
     pub async fn start(&mut self, image_id: ImageId) -> CyberpixieResult<()> {
+        self.ensure_capabilities(Capabilities::IMAGES)?;
         self.connection
             .send_message(RequestHeader::ShowImage(image_id))
             .await?;
@@ -99,10 +419,161 @@ impl<C: AsyncRead + AsyncWrite> Client<C> {
         response.header.empty()
     }
 
+    /// Sends a show image with the given ID command in the given playback mode.
+    pub async fn start_with_playback(
+        &mut self,
+        image_id: ImageId,
+        playback: Playback,
+    ) -> CyberpixieResult<()> {
+        // The default playback mode is supported by the all devices.
+        if playback == Playback::default() {
+            return self.start(image_id).await;
+        }
+
+        self.ensure_capabilities(Capabilities::PLAYBACK_MODES)?;
+        self.connection
+            .send_message(RequestHeader::ShowImageWithPlayback(image_id, playback))
+            .await?;
+
+        let response = self.connection.receive_response().await?;
+        response.header.empty()
+    }
+
+    /// Sends a show image command which starts at the given peer device time.
+    ///
+    /// Use [`Client::clock_offset`] to convert the local time into the peer device time.
+    pub async fn start_at(
+        &mut self,
+        image_id: ImageId,
+        playback: Playback,
+        time: DeviceTime,
+    ) -> CyberpixieResult<()> {
+        self.ensure_capabilities(Capabilities::CLOCK_SYNC)?;
+        self.connection
+            .send_message(RequestHeader::ShowImageAt(image_id, playback, time))
+            .await?;
+
+        let response = self.connection.receive_response().await?;
+        response.header.empty()
+    }
+
+    /// Estimates the offset between the local and the peer device clocks.
+    ///
+    /// The `now` function returns the current local time. The time is exchanged several
+    /// times and the estimation with the shortest round trip is kept, since it has
+    /// the smallest error.
+    pub async fn clock_offset<F: FnMut() -> Duration>(
+        &mut self,
+        mut now: F,
+    ) -> CyberpixieResult<ClockOffset> {
+        self.ensure_capabilities(Capabilities::CLOCK_SYNC)?;
+
+        let mut best: Option<ClockOffset> = None;
+        for _ in 0..CLOCK_SYNC_ROUNDS {
+            let sent = now();
+            self.connection
+                .send_message(RequestHeader::SyncClock)
+                .await?;
+            let response = self.connection.receive_response().await?;
+            let received = now();
+
+            let offset = ClockOffset::from_exchange(sent, response.header.sync_clock()?, received);
+            best = match best {
+                Some(best) if best.round_trip <= offset.round_trip => Some(best),
+                _ => Some(offset),
+            };
+        }
+        // The loop above runs at least once.
+        Ok(best.unwrap())
+    }
+
+    /// Adds a new playlist and returns its identifier.
+    pub async fn add_playlist(&mut self, playlist: &Playlist) -> CyberpixieResult<PlaylistId> {
+        self.ensure_capabilities(Capabilities::PLAYLISTS)?;
+        self.connection
+            .send_message(RequestHeader::AddPlaylist(playlist.clone()))
+            .await?;
+
+        let response = self.connection.receive_response().await?;
+        response.header.add_playlist()
+    }
+
+    /// Reads all stored playlists into the given output and returns their count.
+    pub async fn list_playlists<E: Extend<Playlist>>(
+        &mut self,
+        output: &mut E,
+    ) -> CyberpixieResult<PlaylistId> {
+        self.ensure_capabilities(Capabilities::PLAYLISTS)?;
+        self.connection
+            .send_message(RequestHeader::ListPlaylists)
+            .await?;
+
+        let response = self.connection.receive_response().await?;
+        let playlists_count = response.header.list_playlists()?;
+        for playlist_id in 0..playlists_count.0 {
+            self.connection
+                .send_message(RequestHeader::ReadPlaylist(PlaylistId(playlist_id)))
+                .await?;
+
+            let response = self.connection.receive_response().await?;
+            output.extend(Some(response.header.read_playlist()?));
+        }
+        Ok(playlists_count)
+    }
+
+    /// Deletes a playlist with the given ID.
+    pub async fn delete_playlist(&mut self, playlist_id: PlaylistId) -> CyberpixieResult<()> {
+        self.ensure_capabilities(Capabilities::PLAYLISTS)?;
+        self.connection
+            .send_message(RequestHeader::DeletePlaylist(playlist_id))
+            .await?;
+
+        let response = self.connection.receive_response().await?;
+        response.header.empty()
+    }
+
+    /// Starts showing images of the playlist with the given ID.
+    pub async fn start_playlist(&mut self, playlist_id: PlaylistId) -> CyberpixieResult<()> {
+        self.ensure_capabilities(Capabilities::PLAYLISTS)?;
+        self.connection
+            .send_message(RequestHeader::StartPlaylist(playlist_id))
+            .await?;
+
+        let response = self.connection.receive_response().await?;
+        response.header.empty()
+    }
+
+    /// Streams the given lines to the device, which shows them straight away without saving.
+    ///
+    /// The lines should consist of the strip pixels in the device pixel format.
+    /// This method returns after the last line has been shown.
+    pub async fn stream(&mut self, refresh_rate: Hertz, lines: &[u8]) -> CyberpixieResult<()> {
+        self.ensure_capabilities(Capabilities::STREAMING)?;
+        self.connection
+            .send_message_with_payload(RequestHeader::StreamLines(refresh_rate), lines)
+            .await?;
+
+        let response = self.connection.receive_response().await?;
+        response.header.empty()
+    }
+
+    /// Requests results of the last request forwarded by the main device to the connected
+    /// secondary devices.
+    pub async fn secondary_results(&mut self) -> CyberpixieResult<SecondaryResults> {
+        self.ensure_capabilities(Capabilities::SECONDARIES)?;
+        self.connection
+            .send_message(RequestHeader::SecondaryResults)
+            .await?;
+
+        let response = self.connection.receive_response().await?;
+        response.header.secondary_results()
+    }
+
     /// Send stop command.
     ///
     /// This command will stop the currently showing image and turn the device into the standby mode.
     pub async fn stop(&mut self) -> CyberpixieResult<()> {
+        self.ensure_capabilities(Capabilities::IMAGES)?;
         self.connection
             .send_message(RequestHeader::HideImage)
             .await?;
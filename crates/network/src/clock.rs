@@ -0,0 +1,90 @@
+//! NTP-style estimation of the offset between the device clocks.
+//!
+//! The client sends a `SyncClock` request and remembers the local time it was sent and the
+//! response received. The peer is assumed to read its clock in the middle of the round trip,
+//! so the error of the estimated offset doesn't exceed the half of the round trip.
+
+use core::time::Duration;
+
+use cyberpixie_core::proto::types::DeviceTime;
+
+/// Offset between the local and the peer device clocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockOffset {
+    /// Difference between the peer and the local clocks in microseconds.
+    pub offset: i64,
+    /// Round trip time of the exchange, which bounds the estimation error.
+    pub round_trip: Duration,
+}
+
+impl ClockOffset {
+    /// Estimates the clock offset from the single time exchange.
+    ///
+    /// `sent` and `received` are the local times of sending the request and receiving the
+    /// response, `peer_time` is the time reported by the peer.
+    #[must_use]
+    pub fn from_exchange(sent: Duration, peer_time: DeviceTime, received: Duration) -> Self {
+        let round_trip = received.saturating_sub(sent);
+        let local_time = DeviceTime::from(sent + round_trip / 2);
+        Self {
+            offset: micros_diff(peer_time.0, local_time.0),
+            round_trip,
+        }
+    }
+
+    /// Converts the local time into the peer device time.
+    #[must_use]
+    pub fn to_peer_time(self, local_time: Duration) -> DeviceTime {
+        let local_time = DeviceTime::from(local_time);
+        DeviceTime(local_time.0.saturating_add_signed(self.offset))
+    }
+}
+
+fn micros_diff(lhs: u64, rhs: u64) -> i64 {
+    if lhs >= rhs {
+        i64::try_from(lhs - rhs).unwrap_or(i64::MAX)
+    } else {
+        i64::try_from(rhs - lhs).map_or(i64::MIN, |diff| -diff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use cyberpixie_core::proto::types::DeviceTime;
+
+    use super::ClockOffset;
+
+    #[test]
+    fn test_clock_offset_ahead() {
+        let offset = ClockOffset::from_exchange(
+            Duration::from_millis(100),
+            DeviceTime(5_110_000),
+            Duration::from_millis(120),
+        );
+        assert_eq!(offset.round_trip, Duration::from_millis(20));
+        assert_eq!(offset.offset, 5_000_000);
+        assert_eq!(
+            offset.to_peer_time(Duration::from_secs(1)),
+            DeviceTime(6_000_000)
+        );
+    }
+
+    #[test]
+    fn test_clock_offset_behind() {
+        let offset = ClockOffset::from_exchange(
+            Duration::from_secs(10),
+            DeviceTime(1_000),
+            Duration::from_secs(10),
+        );
+        assert_eq!(offset.round_trip, Duration::ZERO);
+        assert_eq!(offset.offset, -9_999_000);
+        assert_eq!(
+            offset.to_peer_time(Duration::from_secs(11)),
+            DeviceTime(1_001_000)
+        );
+        // The peer time saturates instead of underflowing.
+        assert_eq!(offset.to_peer_time(Duration::ZERO), DeviceTime(0));
+    }
+}
@@ -0,0 +1,110 @@
+//! Discovery of the devices in the local network.
+//!
+//! The client broadcasts a discovery request and each device answers it with a datagram
+//! containing its name, the port of the client connections and the handshake information.
+
+use cyberpixie_core::proto::types::{DeviceName, DiscoveryReply, PeerInfo};
+
+use crate::{CyberpixieResult, SocketAddr, UdpSocket};
+
+/// Default UDP port on which the device answers the discovery requests.
+pub const DEFAULT_DISCOVERY_PORT: u16 = 1802;
+
+/// Discovery request datagram.
+const DISCOVERY_REQUEST: &[u8] = b"CPXD";
+/// Prefix of the discovery reply datagram.
+const DISCOVERY_REPLY: &[u8] = b"CPXR";
+/// The maximum length of the discovery reply datagram.
+const MAX_REPLY_LEN: usize = DISCOVERY_REPLY.len() + DiscoveryReply::MAX_LEN;
+
+/// Device which has answered the discovery request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredDevice {
+    /// Address on which the device accepts client connections.
+    pub address: SocketAddr,
+    /// Name of the device in the network.
+    pub name: Option<DeviceName>,
+    /// The same information which the device reports in the handshake.
+    pub peer_info: PeerInfo,
+}
+
+/// Answers the discovery requests received by the given socket until an error occurs.
+///
+/// Other datagrams are ignored.
+pub async fn answer_requests<U: UdpSocket>(
+    socket: &mut U,
+    reply: &DiscoveryReply,
+) -> CyberpixieResult<()> {
+    let mut reply_buf = [0_u8; MAX_REPLY_LEN];
+    reply_buf[..DISCOVERY_REPLY.len()].copy_from_slice(DISCOVERY_REPLY);
+    let reply_len =
+        DISCOVERY_REPLY.len() + reply.encode(&mut reply_buf[DISCOVERY_REPLY.len()..]).len();
+
+    let mut buf = [0_u8; 16];
+    loop {
+        let (len, address) = socket.receive_from(&mut buf).await?;
+        if &buf[..len] != DISCOVERY_REQUEST {
+            continue;
+        }
+
+        log::debug!("Answering the discovery request from the {address}");
+        socket.send_to(&reply_buf[..reply_len], address).await?;
+    }
+}
+
+/// Sends the discovery request to the given address, which is usually a broadcast one.
+pub async fn send_request<U: UdpSocket>(
+    socket: &mut U,
+    address: SocketAddr,
+) -> CyberpixieResult<()> {
+    socket.send_to(DISCOVERY_REQUEST, address).await
+}
+
+/// Waits for the next device reply to the discovery request.
+///
+/// Datagrams that are not valid replies are skipped.
+pub async fn receive_reply<U: UdpSocket>(socket: &mut U) -> CyberpixieResult<DiscoveredDevice> {
+    let mut buf = [0_u8; MAX_REPLY_LEN];
+    loop {
+        let (len, address) = socket.receive_from(&mut buf).await?;
+        let Some(reply) = parse_reply(&buf[..len]) else {
+            log::debug!("Skipping an unexpected datagram from the {address}");
+            continue;
+        };
+
+        return Ok(DiscoveredDevice {
+            address: SocketAddr::new(address.ip(), reply.port),
+            name: reply.name,
+            peer_info: reply.peer_info,
+        });
+    }
+}
+
+fn parse_reply(datagram: &[u8]) -> Option<DiscoveryReply> {
+    let body = datagram.strip_prefix(DISCOVERY_REPLY)?;
+    DiscoveryReply::from_bytes(body).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use cyberpixie_core::proto::types::{DiscoveryReply, PeerInfo};
+
+    use super::{parse_reply, DISCOVERY_REPLY, MAX_REPLY_LEN};
+
+    #[test]
+    fn test_parse_reply() {
+        let reply = DiscoveryReply {
+            name: Some("stage-left".into()),
+            port: 1800,
+            peer_info: PeerInfo::client(),
+        };
+        let mut buf = [0_u8; MAX_REPLY_LEN];
+        let len = reply.encode(&mut buf).len();
+        let datagram = [DISCOVERY_REPLY, &buf[..len]].concat();
+
+        assert_eq!(parse_reply(&datagram), Some(reply));
+        // Reply without the prefix or truncated one is not valid.
+        assert_eq!(parse_reply(&buf[..len]), None);
+        assert_eq!(parse_reply(&datagram[..datagram.len() - 1]), None);
+    }
+}
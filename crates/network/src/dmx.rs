@@ -0,0 +1,278 @@
+//! Art-Net and E1.31 (sACN) DMX input.
+//!
+//! Lighting software sends the DMX channels of each universe in the UDP packets. The receiver
+//! maps channels of the single universe onto the strip pixels, so each received packet
+//! becomes a strip line.
+
+use cyberpixie_core::io::{AsyncRead, ErrorType};
+pub use cyberpixie_core::proto::types::DmxProtocol;
+
+use crate::{CyberpixieError, CyberpixieResult, UdpSocket};
+
+/// The maximum number of the DMX channels in the single universe.
+pub const UNIVERSE_LEN: usize = 512;
+/// The maximum length of the supported packets.
+const MAX_PACKET_LEN: usize = E131_DATA_OFFSET + UNIVERSE_LEN;
+
+/// Art-Net packet identifier.
+const ARTNET_ID: &[u8] = b"Art-Net\0";
+/// Art-Net `OpDmx` operation code.
+const ARTNET_OP_DMX: u16 = 0x5000;
+/// Offset of the DMX channels in the `ArtDmx` packet.
+const ARTNET_DATA_OFFSET: usize = 18;
+
+/// E1.31 root layer packet identifier.
+const E131_ID: &[u8] = b"ASC-E1.17\0\0\0";
+/// E1.31 root layer data vector.
+const E131_VECTOR_ROOT_DATA: u32 = 0x0000_0004;
+/// E1.31 framing layer data vector.
+const E131_VECTOR_FRAME_DATA: u32 = 0x0000_0002;
+/// E1.31 DMP layer set property vector.
+const E131_VECTOR_DMP_SET_PROPERTY: u8 = 0x02;
+/// Offset of the DMX start code in the E1.31 data packet.
+const E131_DATA_OFFSET: usize = 125;
+
+/// Parses a packet of the given protocol and returns its DMX channels.
+///
+/// Returns `None` if the packet does not carry the DMX channels.
+#[must_use]
+pub fn parse_packet(protocol: DmxProtocol, packet: &[u8]) -> Option<DmxPacket<'_>> {
+    match protocol {
+        DmxProtocol::ArtNet => parse_artnet(packet),
+        DmxProtocol::E131 => parse_e131(packet),
+    }
+}
+
+/// DMX channels of the single universe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmxPacket<'a> {
+    /// Universe number.
+    pub universe: u16,
+    /// Channel values.
+    pub data: &'a [u8],
+}
+
+fn be_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn be_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// Parses an `ArtDmx` packet.
+fn parse_artnet(packet: &[u8]) -> Option<DmxPacket<'_>> {
+    if packet.get(0..8)? != ARTNET_ID {
+        return None;
+    }
+    // The operation code is the only little-endian field.
+    if u16::from_le_bytes(packet.get(8..10)?.try_into().ok()?) != ARTNET_OP_DMX {
+        return None;
+    }
+
+    // The 15-bit port address consists of the "Net" and the "SubUni" fields.
+    let universe = u16::from(*packet.get(15)? & 0x7F) << 8 | u16::from(*packet.get(14)?);
+    let len = usize::from(be_u16(packet, 16)?);
+    let data = packet.get(ARTNET_DATA_OFFSET..ARTNET_DATA_OFFSET + len)?;
+    Some(DmxPacket { universe, data })
+}
+
+/// Parses an E1.31 data packet.
+fn parse_e131(packet: &[u8]) -> Option<DmxPacket<'_>> {
+    if packet.get(4..16)? != E131_ID
+        || be_u32(packet, 18)? != E131_VECTOR_ROOT_DATA
+        || be_u32(packet, 40)? != E131_VECTOR_FRAME_DATA
+        || *packet.get(117)? != E131_VECTOR_DMP_SET_PROPERTY
+    {
+        return None;
+    }
+
+    let universe = be_u16(packet, 113)?;
+    // The property values begin with the start code, which is zero for the DMX channels.
+    let values_count = usize::from(be_u16(packet, 123)?);
+    if values_count == 0 || *packet.get(E131_DATA_OFFSET)? != 0 {
+        return None;
+    }
+    let data = packet.get(E131_DATA_OFFSET + 1..E131_DATA_OFFSET + values_count)?;
+    Some(DmxPacket { universe, data })
+}
+
+/// DMX input settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmxInput {
+    /// DMX over UDP protocol.
+    pub protocol: DmxProtocol,
+    /// Universe which is mapped onto the strip.
+    pub universe: u16,
+    /// Local UDP port to receive packets on.
+    pub port: u16,
+}
+
+impl DmxInput {
+    /// Creates a new DMX input settings with the standard protocol port.
+    #[must_use]
+    pub const fn new(protocol: DmxProtocol, universe: u16) -> Self {
+        Self {
+            protocol,
+            universe,
+            port: protocol.default_port(),
+        }
+    }
+}
+
+/// An endless reader of the strip lines received from the DMX input.
+///
+/// Each packet of the selected universe becomes a single line, channels are mapped onto
+/// the line bytes one by one and the missing channels are read as zeros.
+pub struct DmxLines<U> {
+    socket: U,
+    input: DmxInput,
+    line_len: usize,
+    // Channels of the last received packet.
+    channels: [u8; UNIVERSE_LEN],
+    channels_len: usize,
+    // Read position in the current line.
+    line_pos: usize,
+}
+
+impl<U: UdpSocket> DmxLines<U> {
+    /// Creates a new reader of lines with the given length from the bound UDP socket.
+    ///
+    /// # Panics
+    ///
+    /// - if the line length is zero.
+    pub fn new(socket: U, input: DmxInput, line_len: usize) -> Self {
+        assert!(line_len != 0, "Line length should not be zero");
+
+        Self {
+            socket,
+            input,
+            line_len,
+            channels: [0; UNIVERSE_LEN],
+            channels_len: 0,
+            line_pos: line_len,
+        }
+    }
+
+    /// Waits for a next packet of the selected universe.
+    async fn receive_channels(&mut self) -> CyberpixieResult<()> {
+        let mut buf = [0_u8; MAX_PACKET_LEN];
+        loop {
+            let (len, _sender) = self.socket.receive_from(&mut buf).await?;
+            let Some(packet) = parse_packet(self.input.protocol, &buf[0..len]) else {
+                continue;
+            };
+            if packet.universe != self.input.universe {
+                continue;
+            }
+
+            self.channels_len = packet.data.len().min(UNIVERSE_LEN);
+            self.channels[0..self.channels_len].copy_from_slice(&packet.data[0..self.channels_len]);
+            return Ok(());
+        }
+    }
+}
+
+impl<U> ErrorType for DmxLines<U> {
+    type Error = CyberpixieError;
+}
+
+impl<U: UdpSocket> AsyncRead for DmxLines<U> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.line_pos == self.line_len {
+            self.receive_channels().await?;
+            self.line_pos = 0;
+        }
+
+        let amount = buf.len().min(self.line_len - self.line_pos);
+        for (pos, byte) in (self.line_pos..).zip(&mut buf[0..amount]) {
+            *byte = if pos < self.channels_len {
+                self.channels[pos]
+            } else {
+                0
+            };
+        }
+        self.line_pos += amount;
+        Ok(amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_packet, DmxPacket, DmxProtocol};
+
+    fn artnet_packet(universe: u16, data: &[u8]) -> Vec<u8> {
+        let mut packet = b"Art-Net\0".to_vec();
+        packet.extend(0x5000_u16.to_le_bytes());
+        packet.extend(14_u16.to_be_bytes());
+        packet.extend([0, 0]);
+        packet.extend(universe.to_le_bytes());
+        packet.extend(u16::try_from(data.len()).unwrap().to_be_bytes());
+        packet.extend(data);
+        packet
+    }
+
+    fn e131_packet(universe: u16, data: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0_u8; 126];
+        packet[0..2].copy_from_slice(&0x0010_u16.to_be_bytes());
+        packet[4..16].copy_from_slice(b"ASC-E1.17\0\0\0");
+        packet[18..22].copy_from_slice(&4_u32.to_be_bytes());
+        packet[40..44].copy_from_slice(&2_u32.to_be_bytes());
+        packet[113..115].copy_from_slice(&universe.to_be_bytes());
+        packet[117] = 0x02;
+        packet[118] = 0xA1;
+        let values_count = u16::try_from(data.len() + 1).unwrap();
+        packet[123..125].copy_from_slice(&values_count.to_be_bytes());
+        packet.extend(data);
+        packet
+    }
+
+    #[test]
+    fn test_parse_artnet() {
+        let data = [1, 2, 3, 4, 5, 6];
+        let packet = artnet_packet(0x0102, &data);
+        assert_eq!(
+            parse_packet(DmxProtocol::ArtNet, &packet),
+            Some(DmxPacket {
+                universe: 0x0102,
+                data: &data
+            })
+        );
+
+        // Truncated and other protocol packets are ignored.
+        assert_eq!(parse_packet(DmxProtocol::ArtNet, &packet[0..20]), None);
+        assert_eq!(
+            parse_packet(DmxProtocol::ArtNet, &e131_packet(1, &data)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_e131() {
+        let data = [7; 12];
+        let packet = e131_packet(3, &data);
+        assert_eq!(
+            parse_packet(DmxProtocol::E131, &packet),
+            Some(DmxPacket {
+                universe: 3,
+                data: &data
+            })
+        );
+
+        // Packets with the non-zero start code do not carry the DMX channels.
+        let mut packet = packet;
+        packet[125] = 0xDD;
+        assert_eq!(parse_packet(DmxProtocol::E131, &packet), None);
+        assert_eq!(
+            parse_packet(DmxProtocol::E131, &artnet_packet(3, &data)),
+            None
+        );
+    }
+}
@@ -24,16 +24,23 @@ pub use no_std_net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
 
 pub use crate::{
     client::Client,
+    clock::ClockOffset,
     connection::Connection,
     message::{Message, PayloadReader},
 };
 
+pub mod auth;
 mod client;
+pub mod clock;
 mod connection;
+pub mod discovery;
+pub mod dmx;
 mod message;
 
-/// Default IP address of the device.
+/// Default IP address of the device, which is the address of its access point.
 pub const DEFAULT_DEVICE_IP_ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1));
+/// Default port on which the device accepts client connections.
+pub const DEFAULT_DEVICE_PORT: u16 = 1800;
 
 #[cfg(feature = "tokio")]
 pub mod tokio;
@@ -63,6 +70,10 @@ pub trait NetworkSocket {
     /// Type holding of a TCP connection state. Should close the connection when dropped.
     type Connection<'a>: AsyncRead<Error = Self::ConnectionError>
         + AsyncWrite<Error = Self::ConnectionError>;
+    /// Type holding a bound UDP socket state.
+    type Udp<'a>: UdpSocket
+    where
+        Self: 'a;
     /// Accepts an active incoming connection on the specified local port
     ///
     /// Returns `Ok(connection)` when a new pending connection was created.
@@ -71,6 +82,19 @@ pub trait NetworkSocket {
     ///
     /// Returns `Ok(connection)` when a connection was established.
     async fn connect(&mut self, addr: SocketAddr) -> CyberpixieResult<Self::Connection<'_>>;
+    /// Binds a UDP socket to the specified local port.
+    async fn bind(&mut self, port: u16) -> CyberpixieResult<Self::Udp<'_>>;
+}
+
+/// Trait provides operations with the bound UDP socket.
+pub trait UdpSocket {
+    /// Receives a single datagram into the given buffer.
+    ///
+    /// Returns the number of received bytes and the sender address, the datagram bytes
+    /// that do not fit into the buffer are discarded.
+    async fn receive_from(&mut self, buf: &mut [u8]) -> CyberpixieResult<(usize, SocketAddr)>;
+    /// Sends a single datagram to the given address, which may be a broadcast one.
+    async fn send_to(&mut self, buf: &[u8], address: SocketAddr) -> CyberpixieResult<()>;
 }
 
 /// The trait used to socket address conversion into the network stack specific type.
@@ -79,6 +103,12 @@ pub trait FromSocketAddress {
     fn from_socket_address(value: SocketAddr) -> Self;
 }
 
+/// The trait used to network stack specific address conversion into the socket address.
+pub trait IntoSocketAddress {
+    /// Converts this type into a socket address.
+    fn into_socket_address(self) -> SocketAddr;
+}
+
 #[cfg(feature = "std")]
 impl FromSocketAddress for std::net::SocketAddr {
     fn from_socket_address(value: SocketAddr) -> Self {
@@ -111,3 +141,14 @@ impl FromSocketAddress for (smoltcp::wire::IpAddress, u16) {
         }
     }
 }
+
+#[cfg(feature = "embassy-net")]
+impl IntoSocketAddress for smoltcp::wire::IpEndpoint {
+    fn into_socket_address(self) -> SocketAddr {
+        let ip = match self.addr {
+            smoltcp::wire::IpAddress::Ipv4(ip) => IpAddr::V4(Ipv4Addr::from(ip.0)),
+            smoltcp::wire::IpAddress::Ipv6(ip) => IpAddr::V6(Ipv6Addr::from(ip.0)),
+        };
+        SocketAddr::new(ip, self.port)
+    }
+}
@@ -19,8 +19,8 @@ pub struct PayloadReader<T> {
 }
 
 impl<T> PayloadReader<T> {
-    /// Creates a new message payload reader.
-    pub(crate) const fn new(inner: T, payload_len: usize) -> Self {
+    /// Creates a new message payload reader with the given length.
+    pub const fn new(inner: T, payload_len: usize) -> Self {
         Self {
             payload_len,
             bytes_remaining: payload_len,
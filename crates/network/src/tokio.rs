@@ -1,19 +1,19 @@
 //! Network stack implementation for the Tokio types.
 
-use std::net::{Ipv6Addr, SocketAddr};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
 
 // use embedded_io_adapters::tokio_1::FromTokio;
 use embedded_io::adapters::FromTokio;
 use tokio::net::{TcpListener, TcpStream};
 
-use super::{NetworkSocket, NetworkStack};
+use super::{NetworkSocket, NetworkStack, UdpSocket};
 use crate::{
     core::io::{AsyncRead, AsyncWrite, ErrorType},
     CyberpixieError, CyberpixieResult,
 };
 
 /// The [`tokio`] based Cyberpixie network stack.
-#[derive(Default)]
+#[derive(Default, Clone, Copy)]
 pub struct TokioStack;
 
 /// Ephemeral socket type.
@@ -23,8 +23,6 @@ pub struct TokioSocket;
 pub struct TokioConnection {
     /// TCP stream itself.
     stream: FromTokio<TcpStream>,
-    /// Hold the listener instance if this connection is incoming.
-    _listener: Option<TcpListener>,
 }
 
 impl ErrorType for TokioConnection {
@@ -43,9 +41,45 @@ impl AsyncWrite for TokioConnection {
     }
 }
 
+/// Type holding a bound UDP socket.
+pub struct TokioUdpSocket(tokio::net::UdpSocket);
+
+impl UdpSocket for TokioUdpSocket {
+    async fn receive_from(&mut self, buf: &mut [u8]) -> CyberpixieResult<(usize, SocketAddr)> {
+        let (len, address) = self
+            .0
+            .recv_from(buf)
+            .await
+            .map_err(CyberpixieError::network)?;
+        // Report the IPv4 peers by their own addresses instead of the mapped ones.
+        let ip = match address.ip() {
+            IpAddr::V6(ip) => ip.to_ipv4_mapped().map_or(IpAddr::V6(ip), IpAddr::V4),
+            ip @ IpAddr::V4(_) => ip,
+        };
+        Ok((len, SocketAddr::new(ip, address.port())))
+    }
+
+    async fn send_to(&mut self, buf: &[u8], address: SocketAddr) -> CyberpixieResult<()> {
+        // The socket is bound to the IPv6 address, so the IPv4 addresses have to be mapped.
+        let ip = match address.ip() {
+            IpAddr::V4(ip) => IpAddr::V6(ip.to_ipv6_mapped()),
+            ip @ IpAddr::V6(_) => ip,
+        };
+        self.0
+            .send_to(buf, SocketAddr::new(ip, address.port()))
+            .await
+            .map_err(CyberpixieError::network)?;
+        Ok(())
+    }
+}
+
 impl NetworkSocket for TokioSocket {
     type ConnectionError = std::io::Error;
     type Connection<'a> = TokioConnection;
+    type Udp<'a>
+        = TokioUdpSocket
+    where
+        Self: 'a;
 
     async fn accept(&mut self, port: u16) -> CyberpixieResult<Self::Connection<'_>> {
         // Create listener
@@ -57,9 +91,10 @@ impl NetworkSocket for TokioSocket {
         // Accept the first incoming connection.
         let (stream, address) = listener.accept().await.map_err(CyberpixieError::network)?;
         log::info!("Accepted an incoming connection from the {address}");
+        // The listener is closed, so the port can be listened again while this connection
+        // is still alive.
         Ok(TokioConnection {
             stream: FromTokio::new(stream),
-            _listener: Some(listener),
         })
     }
 
@@ -72,9 +107,21 @@ impl NetworkSocket for TokioSocket {
 
         Ok(TokioConnection {
             stream: FromTokio::new(stream),
-            _listener: None,
         })
     }
+
+    async fn bind(&mut self, port: u16) -> CyberpixieResult<Self::Udp<'_>> {
+        let local_address = SocketAddr::from((Ipv6Addr::UNSPECIFIED, port));
+        let socket = tokio::net::UdpSocket::bind(local_address)
+            .await
+            .map_err(CyberpixieError::network)?;
+        // Allow sending the discovery requests.
+        socket
+            .set_broadcast(true)
+            .map_err(CyberpixieError::network)?;
+        log::info!("Bound UDP socket on the {local_address}");
+        Ok(TokioUdpSocket(socket))
+    }
 }
 
 impl NetworkStack for TokioStack {
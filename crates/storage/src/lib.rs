@@ -19,14 +19,19 @@
 use cyberpixie_app::{
     core::{
         io::{
-            image_reader::Image, AsyncRead, BlockingRead, BlockingSeek, ErrorType, ExactSizeRead,
+            image_reader::Image, AsyncRead, AsyncSeek, BlockingRead, BlockingSeek, ErrorType,
+            ExactSizeRead,
+        },
+        proto::types::{
+            AuthKey, ColorOrder, DeviceName, DmxProtocol, Gamma, Hertz, ImageEncoding, ImageId,
+            ImageInfo, ImageName, MainDevice, PixelFormat, Playlists, IMAGE_NAME_LEN,
         },
-        proto::types::{Hertz, ImageId},
     },
-    Configuration, CyberpixieError, CyberpixieResult, ImageReader,
+    AsyncImageReader, Configuration, CyberpixieError, CyberpixieResult, ImageReader,
 };
 use embedded_io::SeekFrom;
 use endian_codec::{DecodeLE, EncodeLE, PackedSize};
+use postcard::experimental::max_size::MaxSize;
 use serde::{Deserialize, Serialize};
 
 #[cfg(any(feature = "std", test))]
@@ -35,6 +40,13 @@ pub mod test_utils;
 /// Storage offset length in bytes.
 const OFFSET_LEN: usize = core::mem::size_of::<u32>();
 
+/// Calculates the FNV-1a checksum of the given bytes.
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
 #[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
 #[cfg_attr(any(feature = "std", test), derive(Debug))]
 struct Metadata {
@@ -42,13 +54,40 @@ struct Metadata {
 }
 
 /// The storage header block.
-#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+///
+/// The encoded header is prefixed by the magic number and its checksum, so the storage
+/// is able to detect whether the memory contains a valid layout.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 #[cfg_attr(any(feature = "std", test), derive(Debug))]
 struct Header {
     /// Storage layout version.
     version: u16,
     /// LED strip length.
     strip_len: u16,
+    /// Global strip brightness.
+    brightness: u8,
+    /// Gamma correction curve.
+    gamma: Gamma,
+    /// Order of the strip color channels.
+    color_order: ColorOrder,
+    /// Pixel format of the strip LEDs.
+    pixel_format: PixelFormat,
+    /// Start showing the current image after the boot.
+    autoplay: bool,
+    /// Device name in the network.
+    name: Option<DeviceName>,
+    /// Show the DMX input lines while the device is idle.
+    dmx_input: bool,
+    /// DMX input protocol.
+    dmx_protocol: DmxProtocol,
+    /// DMX input universe.
+    dmx_universe: u16,
+    /// Accept connections of the secondary devices.
+    accept_secondaries: bool,
+    /// Main device of the secondary device.
+    main_device: Option<MainDevice>,
+    /// Pre-shared key of the client authentication.
+    auth_key: Option<AuthKey>,
     /// Saved images count.
     images_count: ImageId,
     /// Additional metadata, may differ depending on the storage version.
@@ -58,8 +97,20 @@ struct Header {
 impl Default for Header {
     fn default() -> Self {
         Self {
-            version: 1,
+            version: Self::VERSION,
             strip_len: 24,
+            brightness: u8::MAX,
+            gamma: Gamma::LINEAR,
+            color_order: ColorOrder::Grb,
+            pixel_format: PixelFormat::Rgb,
+            autoplay: false,
+            name: None,
+            dmx_input: false,
+            dmx_protocol: DmxProtocol::ArtNet,
+            dmx_universe: 0,
+            accept_secondaries: false,
+            main_device: None,
+            auth_key: None,
             images_count: ImageId(0),
             metadata: Metadata::default(),
         }
@@ -71,6 +122,17 @@ impl From<Header> for Configuration {
         Self {
             strip_len: header.strip_len,
             current_image: header.metadata.current_image,
+            brightness: header.brightness,
+            gamma: header.gamma,
+            color_order: header.color_order,
+            pixel_format: header.pixel_format,
+            autoplay: header.autoplay,
+            name: header.name,
+            dmx_input: header.dmx_input,
+            dmx_protocol: header.dmx_protocol,
+            dmx_universe: header.dmx_universe,
+            accept_secondaries: header.accept_secondaries,
+            main_device: header.main_device,
         }
     }
 }
@@ -80,6 +142,12 @@ impl Header {
     const BLOCK_SIZE: usize = 512;
     /// Header block location.
     const LOCATION: u32 = 0;
+    /// Current storage layout version.
+    const VERSION: u16 = 15;
+    /// Magic number at the beginning of the header block.
+    const MAGIC: [u8; 4] = *b"CPXS";
+    /// Length of the magic number and the checksum prefix.
+    const PREFIX_LEN: usize = 8;
 
     /// Updates header with the specified configuration and returns `true` if config has breaking changes.
     ///
@@ -92,21 +160,59 @@ impl Header {
         let has_breaking_changes = self.strip_len != config.strip_len;
 
         self.strip_len = config.strip_len;
+        self.brightness = config.brightness;
+        self.gamma = config.gamma;
+        self.color_order = config.color_order;
+        self.pixel_format = config.pixel_format;
+        self.autoplay = config.autoplay;
+        self.name = config.name;
+        self.dmx_input = config.dmx_input;
+        self.dmx_protocol = config.dmx_protocol;
+        self.dmx_universe = config.dmx_universe;
+        self.accept_secondaries = config.accept_secondaries;
+        self.main_device = config.main_device;
         self.metadata.current_image = config.current_image;
         has_breaking_changes
     }
 
     /// Reads and decodes header block.
+    ///
+    /// Returns the [`CyberpixieError::Decode`] if the block has no valid header.
     fn read<T: embedded_storage::Storage>(
         backend: &mut T,
         layout: MemoryLayout,
         buf: &mut [u8],
     ) -> CyberpixieResult<Self> {
+        let buf = &mut buf[0..Self::BLOCK_SIZE];
         backend
             .read(Self::location_offset(layout), buf)
             .map_err(|_| CyberpixieError::StorageRead)?;
 
-        postcard::from_bytes(buf).map_err(CyberpixieError::decode)
+        let (prefix, body) = buf.split_at(Self::PREFIX_LEN);
+        if prefix[0..4] != Self::MAGIC {
+            return Err(CyberpixieError::Decode);
+        }
+        let (header, rest) = postcard::take_from_bytes(body).map_err(CyberpixieError::decode)?;
+        let header_len = body.len() - rest.len();
+        if prefix[4..8] != checksum(&body[0..header_len]).to_le_bytes() {
+            log::warn!("Storage header checksum mismatch");
+            return Err(CyberpixieError::Decode);
+        }
+        Ok(header)
+    }
+
+    /// Reads a header block and checks that its layout version is supported.
+    fn read_compatible<T: embedded_storage::Storage>(
+        backend: &mut T,
+        layout: MemoryLayout,
+        buf: &mut [u8],
+    ) -> CyberpixieResult<Self> {
+        let header = Self::read(backend, layout, buf)?;
+        if header.version != Self::VERSION {
+            log::warn!("Unsupported storage layout version {}", header.version);
+            return Err(CyberpixieError::Unsupported);
+        }
+        Ok(header)
     }
 
     /// Writes a header block back to the embedded storage memory.
@@ -116,7 +222,12 @@ impl Header {
         layout: MemoryLayout,
         buf: &mut [u8],
     ) -> CyberpixieResult<()> {
-        postcard::to_slice(self, buf).map_err(CyberpixieError::storage_write)?;
+        let (prefix, body) = buf[0..Self::BLOCK_SIZE].split_at_mut(Self::PREFIX_LEN);
+        let header_len = postcard::to_slice(self, body)
+            .map_err(CyberpixieError::storage_write)?
+            .len();
+        prefix[0..4].copy_from_slice(&Self::MAGIC);
+        prefix[4..8].copy_from_slice(&checksum(&body[0..header_len]).to_le_bytes());
 
         backend
             .write(Self::location_offset(layout), &buf[0..Self::BLOCK_SIZE])
@@ -130,6 +241,56 @@ impl Header {
     }
 }
 
+// Make sure that the application is able to keep metadata of the all stored images.
+const _: () = assert!(
+    PictureLocation::BLOCK_SIZE / core::mem::size_of::<u32>() - 1
+        <= cyberpixie_app::core::MAX_IMAGES_COUNT
+);
+
+/// The stored playlists block.
+struct PlaylistsBlock;
+
+// Make sure that the all playlists can be fitted into the block.
+const _: () = assert!(Playlists::POSTCARD_MAX_SIZE <= PlaylistsBlock::BLOCK_SIZE);
+
+impl PlaylistsBlock {
+    /// Playlists block size.
+    const BLOCK_SIZE: usize = 512;
+    /// Playlists block location.
+    const LOCATION: u32 = (Header::BLOCK_SIZE + PictureLocation::BLOCK_SIZE) as u32;
+
+    /// Reads and decodes playlists block.
+    fn read<T: embedded_storage::Storage>(
+        backend: &mut T,
+        layout: MemoryLayout,
+        buf: &mut [u8],
+    ) -> CyberpixieResult<Playlists> {
+        let bytes = &mut buf[0..Self::BLOCK_SIZE];
+        backend
+            .read(layout.base + Self::LOCATION, bytes)
+            .map_err(|_| CyberpixieError::StorageRead)?;
+
+        postcard::from_bytes(bytes).map_err(CyberpixieError::decode)
+    }
+
+    /// Writes playlists block back to the embedded storage memory.
+    fn write<T: embedded_storage::Storage>(
+        playlists: &Playlists,
+        backend: &mut T,
+        layout: MemoryLayout,
+        buf: &mut [u8],
+    ) -> CyberpixieResult<()> {
+        let bytes = &mut buf[0..Self::BLOCK_SIZE];
+        let len = postcard::to_slice(playlists, bytes)
+            .map_err(CyberpixieError::storage_write)?
+            .len();
+
+        backend
+            .write(layout.base + Self::LOCATION, &bytes[0..len])
+            .map_err(|_| CyberpixieError::StorageWrite)
+    }
+}
+
 /// Picture location pair.
 ///
 /// This structure uses to read information about the current picture location and the next one,
@@ -153,7 +314,7 @@ impl PictureLocation {
     fn first() -> Self {
         Self {
             current: 0,
-            next: (Self::LOCATION + Self::BLOCK_SIZE) as u32,
+            next: PlaylistsBlock::LOCATION + PlaylistsBlock::BLOCK_SIZE as u32,
         }
     }
 
@@ -201,6 +362,76 @@ impl PictureLocation {
     }
 }
 
+/// Image record which precedes the image bytes.
+#[derive(Clone, Copy, PartialEq, PackedSize, EncodeLE, DecodeLE)]
+#[cfg_attr(any(feature = "std", test), derive(Debug))]
+struct ImageRecord {
+    /// Refresh rate of the single image line.
+    refresh_rate: Hertz,
+    /// Image name length in bytes, zero if the image has no name.
+    name_len: u8,
+    /// Image name bytes.
+    name: [u8; IMAGE_NAME_LEN],
+    /// Encoding of the image bytes.
+    encoding: ImageEncoding,
+    /// Format of the image pixels.
+    pixel_format: PixelFormat,
+}
+
+impl ImageRecord {
+    /// Creates a new image record from the given image information.
+    fn new(info: &ImageInfo) -> Self {
+        let mut record = Self {
+            refresh_rate: info.refresh_rate,
+            name_len: 0,
+            name: [0_u8; IMAGE_NAME_LEN],
+            encoding: info.encoding,
+            pixel_format: info.pixel_format,
+        };
+        if let Some(name) = &info.name {
+            record.name_len = name.len() as u8;
+            record.name[0..name.len()].copy_from_slice(name.as_bytes());
+        }
+        record
+    }
+
+    /// Returns the image name if it exists.
+    fn name(&self) -> Option<ImageName> {
+        let bytes = self.name.get(0..usize::from(self.name_len))?;
+        core::str::from_utf8(bytes)
+            .ok()
+            .filter(|name| !name.is_empty())
+            .map(Into::into)
+    }
+
+    /// Reads and decodes image record at the given offset.
+    fn read<T: embedded_storage::Storage>(
+        offset: u32,
+        backend: &mut T,
+        buf: &mut [u8],
+    ) -> CyberpixieResult<Self> {
+        let bytes = &mut buf[0..Self::PACKED_LEN];
+        backend
+            .read(offset, bytes)
+            .map_err(|_| CyberpixieError::StorageRead)?;
+        Ok(Self::decode_from_le_bytes(bytes))
+    }
+
+    /// Writes image record to the given offset.
+    fn write<T: embedded_storage::Storage>(
+        self,
+        offset: u32,
+        backend: &mut T,
+        buf: &mut [u8],
+    ) -> CyberpixieResult<()> {
+        let bytes = &mut buf[0..Self::PACKED_LEN];
+        self.encode_as_le_bytes(bytes);
+        backend
+            .write(offset, bytes)
+            .map_err(|_| CyberpixieError::StorageWrite)
+    }
+}
+
 /// Storage memory layout
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct MemoryLayout {
@@ -229,18 +460,23 @@ impl<T: embedded_storage::Storage> StorageImpl<T> {
     const MAX_PICTURES_NUM: u16 =
         (PictureLocation::BLOCK_SIZE / core::mem::size_of::<u32>() - 1) as u16;
 
-    /// Opens a new Cyberpixie storage.
+    /// Opens an existing Cyberpixie storage.
+    ///
+    /// Returns an error if the storage memory has no valid layout or its version
+    /// is not supported.
     ///
     /// # Panics
     ///
     /// - if the given buffer length less that the 512 bytes.
     pub fn open(
-        backend: T,
+        mut backend: T,
         layout: MemoryLayout,
         buf: &'static mut [u8],
     ) -> CyberpixieResult<Self> {
         assert!(buf.len() >= Header::BLOCK_SIZE);
 
+        Header::read_compatible(&mut backend, layout, buf)?;
+
         Ok(Self {
             backend,
             layout,
@@ -264,13 +500,49 @@ impl<T: embedded_storage::Storage> StorageImpl<T> {
         // Initialize storage memory with a new header block.
         let new_header = Header {
             strip_len: config.strip_len,
+            brightness: config.brightness,
+            gamma: config.gamma,
+            color_order: config.color_order,
+            pixel_format: config.pixel_format,
+            autoplay: config.autoplay,
+            name: config.name,
+            dmx_input: config.dmx_input,
+            dmx_protocol: config.dmx_protocol,
+            dmx_universe: config.dmx_universe,
+            accept_secondaries: config.accept_secondaries,
+            main_device: config.main_device,
             ..Header::default()
         };
         new_header.write(&mut backend, layout, buf)?;
+        PlaylistsBlock::write(&Playlists::new(), &mut backend, layout, buf)?;
 
         Self::open(backend, layout, buf)
     }
 
+    /// Opens an existing Cyberpixie storage or initializes a new one with the specified
+    /// configuration if the storage memory has no valid layout or its version is not supported.
+    ///
+    /// # Panics
+    ///
+    /// - if the given buffer length less that the 512 bytes.
+    pub fn open_or_init(
+        config: Configuration,
+        mut backend: T,
+        layout: MemoryLayout,
+        buf: &'static mut [u8],
+    ) -> CyberpixieResult<Self> {
+        assert!(buf.len() >= Header::BLOCK_SIZE);
+
+        match Header::read_compatible(&mut backend, layout, buf) {
+            Ok(_) => Self::open(backend, layout, buf),
+            Err(CyberpixieError::StorageRead) => Err(CyberpixieError::StorageRead),
+            Err(err) => {
+                log::info!("Formatting storage without a compatible layout: {err}");
+                Self::init(config, backend, layout, buf)
+            }
+        }
+    }
+
     /// Returns a vacant location for a new picture.
     fn vacant_location(&mut self, images_count: ImageId) -> CyberpixieResult<PictureLocation> {
         if images_count.0 == 0 {
@@ -328,6 +600,22 @@ impl<'a, T: embedded_storage::ReadStorage> BlockingSeek for PictureFile<'a, T> {
     }
 }
 
+impl<T: embedded_storage::ReadStorage> AsyncRead for PictureFile<'_, T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let amount = BlockingRead::read(self, buf)?;
+        // The storage backend is blocking, so give the other tasks a chance to run
+        // between the reads.
+        YieldNow::default().await;
+        Ok(amount)
+    }
+}
+
+impl<T: embedded_storage::ReadStorage> AsyncSeek for PictureFile<'_, T> {
+    async fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        BlockingSeek::seek(self, pos)
+    }
+}
+
 impl<'a, T: embedded_storage::ReadStorage> ExactSizeRead for PictureFile<'a, T> {
     #[inline]
     fn bytes_remaining(&self) -> usize {
@@ -337,6 +625,7 @@ impl<'a, T: embedded_storage::ReadStorage> ExactSizeRead for PictureFile<'a, T>
 
 impl<T: embedded_storage::Storage + Send + 'static> cyberpixie_app::Storage for StorageImpl<T> {
     type ImageRead<'a> = PictureFile<'a, T>;
+    type AsyncImageRead<'a> = PictureFile<'a, T>;
 
     fn config(&mut self) -> CyberpixieResult<Configuration> {
         let header = Header::read(&mut self.backend, self.layout, self.buf)?;
@@ -354,9 +643,20 @@ impl<T: embedded_storage::Storage + Send + 'static> cyberpixie_app::Storage for
         Ok(())
     }
 
+    fn auth_key(&mut self) -> CyberpixieResult<Option<AuthKey>> {
+        let header = Header::read(&mut self.backend, self.layout, self.buf)?;
+        Ok(header.auth_key)
+    }
+
+    fn set_auth_key(&mut self, key: Option<AuthKey>) -> CyberpixieResult<()> {
+        let mut header = Header::read(&mut self.backend, self.layout, self.buf)?;
+        header.auth_key = key;
+        header.write(&mut self.backend, self.layout, self.buf)
+    }
+
     async fn add_image<R: AsyncRead + ExactSizeRead>(
         &mut self,
-        refresh_rate: Hertz,
+        info: ImageInfo,
         mut image: R,
     ) -> CyberpixieResult<ImageId> {
         let mut header = Header::read(&mut self.backend, self.layout, self.buf)?;
@@ -372,15 +672,9 @@ impl<T: embedded_storage::Storage + Send + 'static> cyberpixie_app::Storage for
         let last_picture = self.vacant_location(image_id)?;
         let mut offset = last_picture.next;
 
-        // Write the refresh rate.
-        {
-            let buf = &mut self.buf[0..Hertz::PACKED_LEN];
-            refresh_rate.encode_as_le_bytes(buf);
-            self.backend
-                .write(offset, buf)
-                .map_err(|_| CyberpixieError::StorageWrite)?;
-            offset += buf.len() as u32;
-        }
+        // Write the image record.
+        ImageRecord::new(&info).write(offset, &mut self.backend, self.buf)?;
+        offset += ImageRecord::PACKED_LEN as u32;
 
         // Write image bytes
         while !image.is_empty() {
@@ -418,22 +712,16 @@ impl<T: embedded_storage::Storage + Send + 'static> cyberpixie_app::Storage for
         let location = PictureLocation::read(image_id, &mut self.backend, self.layout, self.buf)?;
 
         // Calculate picture file offsets.
-        let mut begin_offset = location.current;
+        let begin_offset = location.current + ImageRecord::PACKED_LEN as u32;
         let end_offset = location.next;
-        // Read refresh rate
-        let refresh_rate = {
-            let buf = &mut self.buf[0..Hertz::PACKED_LEN];
-            self.backend
-                .read(location.current, buf)
-                .map_err(|_| CyberpixieError::StorageRead)?;
-            let rate = Hertz::decode_from_le_bytes(buf);
-            begin_offset += buf.len() as u32;
-            rate
-        };
+        // Read image record
+        let record = ImageRecord::read(location.current, &mut self.backend, self.buf)?;
 
         // Return an image reader.
         Ok(Image {
-            refresh_rate,
+            refresh_rate: record.refresh_rate,
+            encoding: record.encoding,
+            pixel_format: record.pixel_format,
             bytes: PictureFile {
                 backend: &mut self.backend,
                 begin_offset,
@@ -443,28 +731,145 @@ impl<T: embedded_storage::Storage + Send + 'static> cyberpixie_app::Storage for
         })
     }
 
+    async fn read_image_async(
+        &mut self,
+        image_id: ImageId,
+    ) -> CyberpixieResult<AsyncImageReader<'_, Self>> {
+        // The picture file implements both blocking and asynchronous reads.
+        self.read_image(image_id)
+    }
+
+    fn image_name(&mut self, image_id: ImageId) -> CyberpixieResult<Option<ImageName>> {
+        // Check preconditions.
+        if image_id >= self.images_count()? {
+            return Err(CyberpixieError::ImageNotFound);
+        }
+
+        let location = PictureLocation::read(image_id, &mut self.backend, self.layout, self.buf)?;
+        let record = ImageRecord::read(location.current, &mut self.backend, self.buf)?;
+        Ok(record.name())
+    }
+
     fn images_count(&mut self) -> CyberpixieResult<ImageId> {
         let header = Header::read(&mut self.backend, self.layout, self.buf)?;
         Ok(header.images_count)
     }
 
+    fn delete_image(&mut self, image_id: ImageId) -> CyberpixieResult<()> {
+        let mut header = Header::read(&mut self.backend, self.layout, self.buf)?;
+        // Check preconditions.
+        if image_id >= header.images_count {
+            return Err(CyberpixieError::ImageNotFound);
+        }
+
+        let removed = PictureLocation::read(image_id, &mut self.backend, self.layout, self.buf)?;
+        let removed_len = removed.next - removed.current;
+        let last_image = ImageId(header.images_count.0 - 1);
+        let end_offset =
+            PictureLocation::read(last_image, &mut self.backend, self.layout, self.buf)?.next;
+
+        // Move the bytes of the following images to the place of the removed one.
+        let mut from = removed.next;
+        let mut to = removed.current;
+        while from < end_offset {
+            let amount = core::cmp::min(self.buf.len(), (end_offset - from) as usize);
+            let buf = &mut self.buf[0..amount];
+            self.backend
+                .read(from, buf)
+                .map_err(|_| CyberpixieError::StorageRead)?;
+            self.backend
+                .write(to, buf)
+                .map_err(|_| CyberpixieError::StorageWrite)?;
+            from += amount as u32;
+            to += amount as u32;
+        }
+
+        // Shift the locations of the following images.
+        for id in image_id.0..last_image.0 {
+            let next =
+                PictureLocation::read(ImageId(id + 1), &mut self.backend, self.layout, self.buf)?;
+            PictureLocation {
+                current: next.current - removed_len,
+                next: next.next - removed_len,
+            }
+            .write(ImageId(id), &mut self.backend, self.layout, self.buf)?;
+        }
+
+        // Update storage header.
+        header.images_count = last_image;
+        header.metadata.current_image = match header.metadata.current_image {
+            Some(current) if current == image_id => None,
+            Some(current) if current > image_id => Some(ImageId(current.0 - 1)),
+            other => other,
+        };
+        header.write(&mut self.backend, self.layout, self.buf)?;
+
+        // Remove the image from the playlists.
+        let mut playlists = self.playlists()?;
+        for playlist in &mut playlists {
+            playlist.remove_image(image_id);
+        }
+        self.set_playlists(&playlists)
+    }
+
     fn clear_images(&mut self) -> CyberpixieResult<()> {
         let mut header = Header::read(&mut self.backend, self.layout, self.buf)?;
         header.images_count = ImageId(0);
         header.metadata.current_image = None;
-        header.write(&mut self.backend, self.layout, self.buf)
+        header.write(&mut self.backend, self.layout, self.buf)?;
+        // Playlists without images make no sense.
+        self.set_playlists(&Playlists::new())
+    }
+
+    fn playlists(&mut self) -> CyberpixieResult<Playlists> {
+        PlaylistsBlock::read(&mut self.backend, self.layout, self.buf)
+    }
+
+    fn set_playlists(&mut self, playlists: &Playlists) -> CyberpixieResult<()> {
+        PlaylistsBlock::write(playlists, &mut self.backend, self.layout, self.buf)
+    }
+}
+
+/// A future that yields to the executor once before completion.
+#[derive(Debug, Default)]
+struct YieldNow {
+    yielded: bool,
+}
+
+impl core::future::Future for YieldNow {
+    type Output = ();
+
+    fn poll(
+        mut self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Self::Output> {
+        if self.yielded {
+            core::task::Poll::Ready(())
+        } else {
+            self.yielded = true;
+            cx.waker().wake_by_ref();
+            core::task::Poll::Pending
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use cyberpixie_app::{core::proto::types::ImageId, Configuration};
+    use cyberpixie_app::{
+        core::proto::types::{Hertz, ImageId, ImageInfo},
+        Configuration, CyberpixieError, Storage,
+    };
 
     use crate::{
         test_utils::{leaked_buf, MemoryBackend},
         Header, MemoryLayout, Metadata, PictureLocation, StorageImpl,
     };
 
+    const LAYOUT: MemoryLayout = MemoryLayout {
+        base: 0x9000,
+        size: 0xFFFFF,
+    };
+
     impl PictureLocation {
         /// Returns a location pair for the next image.
         fn next_image(self, image_len: u32) -> Self {
@@ -479,10 +884,7 @@ mod tests {
         StorageImpl::init(
             Configuration::default(),
             MemoryBackend::default(),
-            MemoryLayout {
-                base: 0x9000,
-                size: 0xFFFFF,
-            },
+            LAYOUT,
             leaked_buf(512),
         )
         .unwrap()
@@ -551,4 +953,91 @@ mod tests {
             PictureLocation::read(ImageId(1), &mut backend, layout, buf).unwrap()
         );
     }
+
+    #[test]
+    fn test_open_uninitialized_storage() {
+        assert!(matches!(
+            StorageImpl::open(MemoryBackend::default(), LAYOUT, leaked_buf(512)),
+            Err(CyberpixieError::Decode)
+        ));
+
+        let config = Configuration {
+            strip_len: 32,
+            ..Configuration::default()
+        };
+        let mut storage = StorageImpl::open_or_init(
+            config.clone(),
+            MemoryBackend::default(),
+            LAYOUT,
+            leaked_buf(512),
+        )
+        .unwrap();
+        assert_eq!(storage.config().unwrap(), config);
+        assert_eq!(storage.images_count().unwrap(), ImageId(0));
+    }
+
+    #[tokio::test]
+    async fn test_open_or_init_after_reboot() {
+        let mut storage = init_storage();
+        let image_id = storage
+            .add_image(ImageInfo::new(Hertz(50), 24), &[1_u8; 72][..])
+            .await
+            .unwrap();
+        let config = Configuration {
+            current_image: Some(image_id),
+            brightness: 42,
+            ..Configuration::default()
+        };
+        storage.set_config(config.clone()).unwrap();
+
+        // Simulate a reboot, the existing storage layout should be preserved.
+        let mut storage = StorageImpl::open_or_init(
+            Configuration::default(),
+            storage.backend,
+            LAYOUT,
+            leaked_buf(512),
+        )
+        .unwrap();
+        assert_eq!(storage.config().unwrap(), config);
+        assert_eq!(storage.images_count().unwrap(), ImageId(1));
+        assert_eq!(
+            storage.read_image(image_id).unwrap().refresh_rate,
+            Hertz(50)
+        );
+
+        // Corrupt the header, the storage should be formatted after the next reboot.
+        let header_offset = (LAYOUT.base + Header::LOCATION) as usize;
+        storage.backend.0[header_offset + Header::PREFIX_LEN] ^= 0xFF;
+        let mut storage = StorageImpl::open_or_init(
+            Configuration::default(),
+            storage.backend,
+            LAYOUT,
+            leaked_buf(512),
+        )
+        .unwrap();
+        assert_eq!(storage.config().unwrap(), Configuration::default());
+        assert_eq!(storage.images_count().unwrap(), ImageId(0));
+    }
+
+    #[test]
+    fn test_open_or_init_incompatible_version() {
+        let mut storage = init_storage();
+        let header = Header {
+            version: Header::VERSION - 1,
+            strip_len: 18,
+            ..Header::default()
+        };
+        header
+            .write(&mut storage.backend, LAYOUT, storage.buf)
+            .unwrap();
+
+        let mut storage = StorageImpl::open_or_init(
+            Configuration::default(),
+            storage.backend,
+            LAYOUT,
+            leaked_buf(512),
+        )
+        .unwrap();
+        assert_eq!(storage.config().unwrap().strip_len, 24);
+    }
 }
@@ -1,9 +1,19 @@
 use cyberpixie_app::{
     core::{
-        io::{image_reader::ImageLines, BlockingRead, ExactSizeRead},
-        proto::types::{Hertz, ImageId},
+        color::RGBW8,
+        io::{
+            image_reader::{AsyncImageLines, ImageLines},
+            palette,
+            rle::RleEncoder,
+            BlockingRead, ExactSizeRead,
+        },
+        proto::types::{
+            AuthKey, ColorOrder, DmxProtocol, EntryLength, Gamma, Hertz, ImageEncoding, ImageId,
+            ImageInfo, MainDevice, PixelFormat, Playlist, PlaylistEntry, Playlists,
+        },
+        rgb::RGB8,
     },
-    Configuration, Storage,
+    Configuration, CyberpixieError, Storage,
 };
 use cyberpixie_embedded_storage::{
     test_utils::{leaked_buf, MemoryBackend},
@@ -30,13 +40,49 @@ fn test_config_read_write() {
     let expected_config = Configuration {
         strip_len: 32,
         current_image: None,
+        brightness: 128,
+        gamma: Gamma(22),
+        color_order: ColorOrder::Grb,
+        pixel_format: PixelFormat::Rgbw,
+        autoplay: true,
+        name: Some("stage-left".into()),
+        dmx_input: true,
+        dmx_protocol: DmxProtocol::E131,
+        dmx_universe: 3,
+        accept_secondaries: true,
+        main_device: Some(MainDevice {
+            ip: [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+            port: 1801,
+            group_id: Some(7),
+        }),
     };
-    storage.set_config(expected_config).unwrap();
+    storage.set_config(expected_config.clone()).unwrap();
 
     let actual_config = storage.config().unwrap();
     assert_eq!(actual_config, expected_config);
 }
 
+#[test]
+fn test_auth_key_read_write() {
+    let mut storage = init_storage();
+    assert_eq!(storage.auth_key().unwrap(), None);
+
+    let key = AuthKey::from_slice(b"secret").unwrap();
+    storage.set_auth_key(Some(key.clone())).unwrap();
+    assert_eq!(storage.auth_key().unwrap(), Some(key.clone()));
+    // Configuration changes keep the key.
+    storage
+        .set_config(Configuration {
+            strip_len: 32,
+            ..Configuration::default()
+        })
+        .unwrap();
+    assert_eq!(storage.auth_key().unwrap(), Some(key));
+
+    storage.set_auth_key(None).unwrap();
+    assert_eq!(storage.auth_key().unwrap(), None);
+}
+
 fn read_image(storage: &mut StorageImpl<MemoryBackend>, id: ImageId) -> (Hertz, Vec<u8>) {
     let mut image = storage.read_image(id).unwrap();
     let mut buf = vec![0_u8; image.bytes.bytes_remaining()];
@@ -53,7 +99,7 @@ async fn image_read_write_simple() {
 
     // Add a first image
     storage
-        .add_image(Hertz(500), &image_data_1[..])
+        .add_image(ImageInfo::new(Hertz(500), 24), &image_data_1[..])
         .await
         .unwrap();
     assert_eq!(storage.images_count().unwrap(), ImageId(1));
@@ -65,7 +111,7 @@ async fn image_read_write_simple() {
 
     // Add a second image
     storage
-        .add_image(Hertz(42), &image_data_2[..])
+        .add_image(ImageInfo::new(Hertz(42), 24), &image_data_2[..])
         .await
         .unwrap();
     assert_eq!(storage.images_count().unwrap(), ImageId(2));
@@ -89,11 +135,11 @@ async fn image_read_write_clear() {
 
     // Add an images
     storage
-        .add_image(Hertz(500), &image_data_1[..])
+        .add_image(ImageInfo::new(Hertz(500), 24), &image_data_1[..])
         .await
         .unwrap();
     storage
-        .add_image(Hertz(42), &image_data_1[..])
+        .add_image(ImageInfo::new(Hertz(42), 24), &image_data_1[..])
         .await
         .unwrap();
     assert_eq!(storage.images_count().unwrap(), ImageId(2));
@@ -104,7 +150,7 @@ async fn image_read_write_clear() {
 
     let image_data_2 = [2_u8; 24 * 3 * 20];
     storage
-        .add_image(Hertz(48), &image_data_2[..])
+        .add_image(ImageInfo::new(Hertz(48), 24), &image_data_2[..])
         .await
         .unwrap();
     assert_eq!(storage.images_count().unwrap(), ImageId(1));
@@ -115,6 +161,90 @@ async fn image_read_write_clear() {
     assert_eq!(data, image_data_2);
 }
 
+#[tokio::test]
+async fn image_read_write_delete() {
+    let mut storage = init_storage();
+
+    let image_data_1 = [1_u8; 72];
+    let image_data_2 = [2_u8; 24 * 3 * 20];
+    let image_data_3 = [3_u8; 24 * 3 * 2];
+
+    // Add an images
+    storage
+        .add_image(ImageInfo::new(Hertz(500), 24), &image_data_1[..])
+        .await
+        .unwrap();
+    storage
+        .add_image(ImageInfo::new(Hertz(42), 24), &image_data_2[..])
+        .await
+        .unwrap();
+    storage
+        .add_image(ImageInfo::new(Hertz(48), 24), &image_data_3[..])
+        .await
+        .unwrap();
+    storage.set_current_image_id(ImageId(2)).unwrap();
+
+    // Delete an image in the middle.
+    storage.delete_image(ImageId(1)).unwrap();
+    assert_eq!(storage.images_count().unwrap(), ImageId(2));
+    assert_eq!(storage.current_image_id().unwrap(), Some(ImageId(1)));
+    // Check that the rest images are not corrupted.
+    let (rate, data) = read_image(&mut storage, ImageId(0));
+    assert_eq!(rate, Hertz(500));
+    assert_eq!(data, image_data_1);
+    let (rate, data) = read_image(&mut storage, ImageId(1));
+    assert_eq!(rate, Hertz(48));
+    assert_eq!(data, image_data_3);
+
+    // Delete a current image.
+    storage.delete_image(ImageId(1)).unwrap();
+    assert_eq!(storage.images_count().unwrap(), ImageId(1));
+    assert_eq!(storage.current_image_id().unwrap(), None);
+    assert!(storage.delete_image(ImageId(1)).is_err());
+
+    // Add an image again
+    storage
+        .add_image(ImageInfo::new(Hertz(42), 24), &image_data_2[..])
+        .await
+        .unwrap();
+    let (rate, data) = read_image(&mut storage, ImageId(0));
+    assert_eq!(rate, Hertz(500));
+    assert_eq!(data, image_data_1);
+    let (rate, data) = read_image(&mut storage, ImageId(1));
+    assert_eq!(rate, Hertz(42));
+    assert_eq!(data, image_data_2);
+}
+
+#[tokio::test]
+async fn image_read_write_named() {
+    let mut storage = init_storage();
+
+    let image_data = [1_u8; 72];
+    storage
+        .add_image(ImageInfo::new(Hertz(500), 24), &image_data[..])
+        .await
+        .unwrap();
+    storage
+        .add_image(
+            ImageInfo {
+                name: Some("nyan".into()),
+                ..ImageInfo::new(Hertz(42), 24)
+            },
+            &image_data[..],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(storage.image_name(ImageId(0)).unwrap(), None);
+    assert_eq!(storage.image_name(ImageId(1)).unwrap(), Some("nyan".into()));
+    assert_eq!(storage.find_image("nyan").unwrap(), Some(ImageId(1)));
+    assert_eq!(storage.find_image("cat").unwrap(), None);
+    // Make sure that the name doesn't affect the image bytes.
+    let (rate, data) = read_image(&mut storage, ImageId(1));
+    assert_eq!(rate, Hertz(42));
+    assert_eq!(data, image_data);
+}
+
 #[tokio::test]
 async fn test_image_lines_cycle_nyan_cat() {
     let mut storage = init_storage();
@@ -129,10 +259,13 @@ async fn test_image_lines_cycle_nyan_cat() {
         raw.extend(rgb.0);
     }
     // Add image.
-    storage.add_image(Hertz(500), &raw[..]).await.unwrap();
+    storage
+        .add_image(ImageInfo::new(Hertz(500), 48), &raw[..])
+        .await
+        .unwrap();
     // Read image line by line.
     let image = storage.read_image(ImageId(0)).unwrap();
-    let mut lines = ImageLines::new(image, 48, vec![0_u8; 512]);
+    let mut lines = ImageLines::new(image, 48, vec![0_u8; 512]).unwrap();
 
     let first_line: Vec<_> = lines.next_line().unwrap().collect();
     // Render a lot of lines
@@ -146,3 +279,314 @@ async fn test_image_lines_cycle_nyan_cat() {
     let line: Vec<_> = lines.next_line().unwrap().collect();
     assert_ne!(first_line, line);
 }
+
+#[tokio::test]
+async fn test_image_lines_rle_nyan_cat() {
+    let mut storage = init_storage();
+    storage
+        .set_config(Configuration {
+            strip_len: 48,
+            ..Configuration::default()
+        })
+        .unwrap();
+
+    // Read an image and convert it to the RLE encoded bytes.
+    let image = image::load_from_memory(include_bytes!("../../../assets/nyan_cat_48.png"))
+        .unwrap()
+        .to_rgb8();
+    let raw = image.into_raw();
+    let encoded: Vec<_> = RleEncoder::new(&raw).flatten().collect();
+    // Add image.
+    storage
+        .add_image(
+            ImageInfo {
+                encoding: ImageEncoding::Rle,
+                ..ImageInfo::new(Hertz(500), 48)
+            },
+            &encoded[..],
+        )
+        .await
+        .unwrap();
+
+    let metadata = storage.image_metadata(ImageId(0)).unwrap();
+    assert_eq!(metadata.encoding, ImageEncoding::Rle);
+    assert_eq!(metadata.len as usize, encoded.len());
+    assert_eq!(metadata.lines as usize, raw.len() / (48 * 3));
+
+    // Decoded lines should be equal to the raw image lines, even after several cycles.
+    let image = storage.read_image(ImageId(0)).unwrap();
+    let mut lines = ImageLines::new(image, 48, vec![0_u8; 512]).unwrap();
+    for expected in raw.chunks_exact(48 * 3).cycle().take(1000) {
+        let line: Vec<_> = lines
+            .next_line()
+            .unwrap()
+            .flat_map(|pixel| [pixel.r, pixel.g, pixel.b])
+            .collect();
+        assert_eq!(line, expected);
+    }
+}
+
+#[tokio::test]
+async fn test_rle_image_empty_runs() {
+    let mut storage = init_storage();
+
+    // Two lines of the 24 pixels strip, the second image ends with an empty run.
+    let runs = [[40_u8, 1, 2, 3], [8, 4, 5, 6]].concat();
+    let trailing_empty = [runs.as_slice(), &[0, 7, 8, 9]].concat();
+    let info = ImageInfo {
+        encoding: ImageEncoding::Rle,
+        ..ImageInfo::new(Hertz(50), 24)
+    };
+    for image in [&runs, &trailing_empty] {
+        storage.add_image(info.clone(), &image[..]).await.unwrap();
+    }
+
+    // Empty runs don't change the pixels count, so they have to be checked separately.
+    let mut image = storage.read_image(ImageId(0)).unwrap();
+    assert_eq!(image.pixels_count().unwrap(), 48);
+    assert!(!image.has_empty_runs().unwrap());
+    let mut image = storage.read_image(ImageId(1)).unwrap();
+    assert_eq!(image.pixels_count().unwrap(), 48);
+    assert!(image.has_empty_runs().unwrap());
+    // The image is rewound after the check.
+    assert_eq!(image.bytes.bytes_remaining(), trailing_empty.len());
+}
+
+#[tokio::test]
+async fn test_image_lines_palette() {
+    let mut storage = init_storage();
+
+    let colors = [
+        RGB8::new(255, 0, 0),
+        RGB8::new(0, 255, 0),
+        RGB8::new(0, 0, 255),
+    ];
+    // Five lines of the 24 pixels strip.
+    let indices: Vec<u8> = (0..24 * 5).map(|i| (i % 7 % 3) as u8).collect();
+    let image_data: Vec<u8> = palette::encode_block(&colors)
+        .chain(indices.iter().copied())
+        .collect();
+    storage
+        .add_image(
+            ImageInfo {
+                encoding: ImageEncoding::Palette,
+                ..ImageInfo::new(Hertz(500), 24)
+            },
+            &image_data[..],
+        )
+        .await
+        .unwrap();
+
+    let metadata = storage.image_metadata(ImageId(0)).unwrap();
+    assert_eq!(metadata.encoding, ImageEncoding::Palette);
+    assert_eq!(metadata.lines, 5);
+
+    // Expanded lines should match the palette colors, even after several cycles.
+    let image = storage.read_image(ImageId(0)).unwrap();
+    let mut lines = ImageLines::new(image, 24, vec![0_u8; 512]).unwrap();
+    for expected in indices.chunks_exact(24).cycle().take(12) {
+        let line: Vec<_> = lines.next_line().unwrap().collect();
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|&index| colors[usize::from(index)])
+            .collect();
+        assert_eq!(line, expected);
+    }
+}
+
+#[tokio::test]
+async fn test_image_lines_rgbw() {
+    let mut storage = init_storage();
+
+    // Two lines of the 24 pixels strip.
+    let image_data: Vec<u8> = (0..24 * 2).flat_map(|i| [i, 0, 0, 10]).collect();
+    storage
+        .add_image(
+            ImageInfo {
+                pixel_format: PixelFormat::Rgbw,
+                ..ImageInfo::new(Hertz(500), 24)
+            },
+            &image_data[..],
+        )
+        .await
+        .unwrap();
+
+    let metadata = storage.image_metadata(ImageId(0)).unwrap();
+    assert_eq!(metadata.pixel_format, PixelFormat::Rgbw);
+    assert_eq!(metadata.lines, 2);
+
+    let image = storage.read_image(ImageId(0)).unwrap();
+    let mut lines = ImageLines::new(image, 24, vec![0_u8; 512]).unwrap();
+    // RGBW pixels are read as is.
+    let line: Vec<_> = lines.next_rgbw_line().unwrap().collect();
+    assert_eq!(line.len(), 24);
+    assert_eq!(line[1], RGBW8::new(1, 0, 0, 10));
+    // The white channel is mixed into the RGB pixels.
+    let line: Vec<_> = lines.next_line().unwrap().collect();
+    assert_eq!(line.len(), 24);
+    assert_eq!(line[1], RGB8::new(35, 10, 10));
+    // Rewind to the first line.
+    let line: Vec<_> = lines.next_rgbw_line().unwrap().collect();
+    assert_eq!(line[0], RGBW8::new(0, 0, 0, 10));
+}
+
+#[tokio::test]
+async fn test_async_image_lines() {
+    let mut storage = init_storage();
+
+    // The same three lines image in the every supported encoding.
+    let pixels: Vec<u8> = (0..24 * 3).flat_map(|i| [i / 10, 0, 1]).collect();
+    let runs: Vec<u8> = RleEncoder::new(&pixels).flatten().collect();
+    let colors: Vec<_> = (0..8).map(|i| RGB8::new(i, 0, 1)).collect();
+    let indexed: Vec<u8> = palette::encode_block(&colors)
+        .chain((0..24 * 3).map(|i| i / 10))
+        .collect();
+    for (encoding, image_data) in [
+        (ImageEncoding::Raw, &pixels),
+        (ImageEncoding::Rle, &runs),
+        (ImageEncoding::Palette, &indexed),
+    ] {
+        storage
+            .add_image(
+                ImageInfo {
+                    encoding,
+                    ..ImageInfo::new(Hertz(500), 24)
+                },
+                &image_data[..],
+            )
+            .await
+            .unwrap();
+    }
+
+    let expected_lines: Vec<Vec<_>> = pixels
+        .chunks_exact(24 * 3)
+        .map(|line| {
+            line.chunks_exact(3)
+                .map(|bytes| RGB8::new(bytes[0], bytes[1], bytes[2]))
+                .collect()
+        })
+        .collect();
+    for id in 0..3 {
+        // Both readers should rewind to the first line after the last one.
+        let image = storage.read_image(ImageId(id)).unwrap();
+        let mut lines = ImageLines::new(image, 24, vec![0_u8; 512]).unwrap();
+        for expected in expected_lines.iter().cycle().take(7) {
+            let line: Vec<_> = lines.next_line().unwrap().collect();
+            assert_eq!(&line, expected);
+        }
+
+        let image = storage.read_image_async(ImageId(id)).await.unwrap();
+        let mut lines = AsyncImageLines::new(image, 24, vec![0_u8; 512]).unwrap();
+        for expected in expected_lines.iter().cycle().take(7) {
+            let line: Vec<_> = lines.next_line().await.unwrap().collect();
+            assert_eq!(&line, expected);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_image_lines_errors() {
+    let mut storage = init_storage();
+
+    // Raw image with the length that is not a multiple of the strip line.
+    storage
+        .add_image(ImageInfo::new(Hertz(50), 24), &[1_u8; 24 * 3 + 1][..])
+        .await
+        .unwrap();
+    // Run-length encoded image with the truncated run.
+    storage
+        .add_image(
+            ImageInfo {
+                encoding: ImageEncoding::Rle,
+                ..ImageInfo::new(Hertz(50), 24)
+            },
+            &[24_u8, 1, 2][..],
+        )
+        .await
+        .unwrap();
+    // Palette-indexed RGBW image.
+    storage
+        .add_image(
+            ImageInfo {
+                encoding: ImageEncoding::Palette,
+                pixel_format: PixelFormat::Rgbw,
+                ..ImageInfo::new(Hertz(50), 24)
+            },
+            &[0_u8, 1, 2, 3][..],
+        )
+        .await
+        .unwrap();
+
+    let image = storage.read_image(ImageId(0)).unwrap();
+    assert_eq!(
+        ImageLines::new(image, 24, vec![0_u8; 512]).err(),
+        Some(CyberpixieError::ImageLengthMismatch)
+    );
+    let image = storage.read_image(ImageId(1)).unwrap();
+    assert_eq!(
+        ImageLines::new(image, 24, vec![0_u8; 512]).err(),
+        Some(CyberpixieError::ImageLengthMismatch)
+    );
+    let image = storage.read_image_async(ImageId(2)).await.unwrap();
+    assert_eq!(
+        AsyncImageLines::new(image, 24, vec![0_u8; 512]).err(),
+        Some(CyberpixieError::Unsupported)
+    );
+    // The strip line doesn't fit into the buffer.
+    let image = storage.read_image(ImageId(0)).unwrap();
+    assert_eq!(
+        ImageLines::new(image, 24, vec![0_u8; 24]).err(),
+        Some(CyberpixieError::StripLengthMismatch)
+    );
+}
+
+#[tokio::test]
+async fn test_playlists_read_write() {
+    let mut storage = init_storage();
+    assert!(storage.playlists().unwrap().is_empty());
+
+    for i in 0..3 {
+        storage
+            .add_image(ImageInfo::new(Hertz(50), 24), &[i; 72][..])
+            .await
+            .unwrap();
+    }
+
+    let entry = |id, length| PlaylistEntry {
+        image_id: ImageId(id),
+        length,
+    };
+    let mut playlists = Playlists::new();
+    for entries in [
+        [
+            entry(0, EntryLength::Millis(1000)),
+            entry(2, EntryLength::Loops(5)),
+        ],
+        [
+            entry(1, EntryLength::Loops(1)),
+            entry(1, EntryLength::Millis(42)),
+        ],
+    ] {
+        let playlist = Playlist {
+            entries: entries.into_iter().collect(),
+        };
+        playlists.push(playlist).unwrap();
+    }
+    storage.set_playlists(&playlists).unwrap();
+    assert_eq!(storage.playlists().unwrap(), playlists);
+
+    // Images should be removed from the playlists and the following images should be shifted.
+    storage.delete_image(ImageId(1)).unwrap();
+    let playlists = storage.playlists().unwrap();
+    assert_eq!(
+        playlists[0].entries,
+        [
+            entry(0, EntryLength::Millis(1000)),
+            entry(1, EntryLength::Loops(5)),
+        ]
+    );
+    assert!(playlists[1].entries.is_empty());
+
+    storage.clear_images().unwrap();
+    assert!(storage.playlists().unwrap().is_empty());
+}
@@ -1,6 +1,16 @@
-use std::path::Path;
+use std::{collections::HashMap, path::Path};
 
-use image::io::Reader;
+use color_quant::NeuQuant;
+use cyberpixie_network::core::{
+    color::RGBW8,
+    io::{
+        palette::{self, Palette, MAX_COLORS},
+        rle::{RleDecoder, RleEncoder, RUN_LEN},
+    },
+    proto::types::{EntryLength, ImageEncoding, ImageId, PlaylistEntry},
+    rgb::RGB8,
+};
+use image::{io::Reader, RgbImage};
 
 pub fn convert_image_to_raw(path: impl AsRef<Path>) -> anyhow::Result<(usize, Vec<u8>)> {
     let image = Reader::open(path)?.decode()?.to_rgb8();
@@ -15,3 +25,285 @@ pub fn convert_image_to_raw(path: impl AsRef<Path>) -> anyhow::Result<(usize, Ve
 
     Ok((width, raw))
 }
+
+pub fn save_raw_image(
+    path: impl AsRef<Path>,
+    strip_len: usize,
+    raw: Vec<u8>,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(strip_len != 0, "Strip length should not be zero");
+
+    let width = u32::try_from(strip_len)?;
+    let height = u32::try_from(raw.len() / (strip_len * 3))?;
+    let image = RgbImage::from_raw(width, height, raw)
+        .ok_or_else(|| anyhow::anyhow!("Image length is not a multiple of the strip length"))?;
+    image.save(path)?;
+    Ok(())
+}
+
+/// Encodes raw image bytes by using the run-length encoding.
+pub fn encode_rle(raw: &[u8]) -> Vec<u8> {
+    RleEncoder::new(raw).flatten().collect()
+}
+
+/// Decodes run-length encoded image bytes back to the raw image bytes.
+pub fn decode_rle(encoded: &[u8]) -> anyhow::Result<Vec<u8>> {
+    anyhow::ensure!(
+        encoded.len() % RUN_LEN == 0,
+        "Encoded image length is not a multiple of the run length"
+    );
+
+    let mut reader = encoded;
+    let mut decoder = RleDecoder::new();
+    let mut raw = Vec::new();
+    while !(reader.is_empty() && decoder.is_run_finished()) {
+        let pixel = decoder
+            .next_pixel(&mut reader)
+            .map_err(|err| anyhow::anyhow!("Unable to decode image: {err:?}"))?;
+        raw.extend(pixel);
+    }
+    Ok(raw)
+}
+
+/// Converts raw image bytes to the palette-indexed image bytes.
+///
+/// Images with more than [`MAX_COLORS`] colors are quantized.
+pub fn encode_palette(raw: &[u8]) -> Vec<u8> {
+    let pixels = raw
+        .chunks_exact(3)
+        .map(|pixel| RGB8::new(pixel[0], pixel[1], pixel[2]));
+
+    // Try to use the exact image colors first.
+    let mut colors = Vec::new();
+    let mut indices = HashMap::new();
+    for pixel in pixels.clone() {
+        indices.entry(pixel).or_insert_with(|| {
+            colors.push(pixel);
+            colors.len() - 1
+        });
+    }
+
+    let (colors, indices): (Vec<_>, Vec<_>) = if colors.len() <= MAX_COLORS {
+        let indices = pixels.map(|pixel| indices[&pixel] as u8).collect();
+        (colors, indices)
+    } else {
+        let rgba: Vec<_> = pixels.clone().flat_map(|p| [p.r, p.g, p.b, 0xFF]).collect();
+        let quantizer = NeuQuant::new(10, MAX_COLORS, &rgba);
+        let colors = quantizer
+            .color_map_rgb()
+            .chunks_exact(3)
+            .map(|color| RGB8::new(color[0], color[1], color[2]))
+            .collect();
+        let indices = rgba
+            .chunks_exact(4)
+            .map(|pixel| quantizer.index_of(pixel) as u8)
+            .collect();
+        (colors, indices)
+    };
+
+    palette::encode_block(&colors).chain(indices).collect()
+}
+
+/// Decodes palette-indexed image bytes back to the raw image bytes.
+pub fn decode_palette(encoded: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut reader = encoded;
+    let palette = Palette::read(&mut reader)
+        .map_err(|err| anyhow::anyhow!("Unable to read image palette: {err:?}"))?;
+
+    let raw = reader
+        .iter()
+        .flat_map(|&index| {
+            let color = palette.color(index);
+            [color.r, color.g, color.b]
+        })
+        .collect();
+    Ok(raw)
+}
+
+/// Chooses an encoding for the given raw image bytes and returns the encoded bytes.
+///
+/// The palette-indexed encoding is used if it has been requested, otherwise the run-length
+/// encoding is used if it is supported by the device and makes the image smaller.
+pub fn encode_image(raw: Vec<u8>, palette: bool, rle_supported: bool) -> (ImageEncoding, Vec<u8>) {
+    if palette {
+        let encoded = encode_palette(&raw);
+        log::info!(
+            "Using palette encoding, image size {} -> {} bytes",
+            raw.len(),
+            encoded.len()
+        );
+        return (ImageEncoding::Palette, encoded);
+    }
+
+    if rle_supported {
+        let encoded = encode_rle(&raw);
+        if encoded.len() < raw.len() {
+            log::info!(
+                "Using RLE encoding, image size {} -> {} bytes",
+                raw.len(),
+                encoded.len()
+            );
+            return (ImageEncoding::Rle, encoded);
+        }
+    }
+
+    (ImageEncoding::Raw, raw)
+}
+
+/// Converts raw RGB image bytes to the RGBW image bytes by extracting the white channel.
+pub fn extract_white(raw: &[u8]) -> Vec<u8> {
+    raw.chunks_exact(3)
+        .flat_map(|pixel| {
+            let pixel = RGBW8::extract_white(RGB8::new(pixel[0], pixel[1], pixel[2]));
+            [pixel.r, pixel.g, pixel.b, pixel.w]
+        })
+        .collect()
+}
+
+/// Converts raw RGBW image bytes back to the RGB image bytes.
+pub fn mix_white(raw: &[u8]) -> Vec<u8> {
+    raw.chunks_exact(4)
+        .flat_map(|pixel| {
+            let pixel = RGBW8::new(pixel[0], pixel[1], pixel[2], pixel[3]).to_rgb();
+            [pixel.r, pixel.g, pixel.b]
+        })
+        .collect()
+}
+
+/// Parses a playlist entry in the `IMAGE_ID:LENGTH` format.
+///
+/// The entry length is either a duration like `500ms` and `5s` or a number of the image
+/// repetitions like `3x`.
+pub fn parse_playlist_entry(s: &str) -> anyhow::Result<PlaylistEntry> {
+    let (image_id, length) = s.split_once(':').ok_or_else(|| {
+        anyhow::anyhow!("Playlist entry should be in the `IMAGE_ID:LENGTH` format")
+    })?;
+
+    let length = if let Some(millis) = length.strip_suffix("ms") {
+        EntryLength::Millis(millis.parse()?)
+    } else if let Some(secs) = length.strip_suffix('s') {
+        let millis = secs.parse::<u32>()?.checked_mul(1000);
+        EntryLength::Millis(millis.ok_or_else(|| anyhow::anyhow!("Duration is too long"))?)
+    } else if let Some(loops) = length.strip_suffix('x') {
+        EntryLength::Loops(loops.parse()?)
+    } else {
+        anyhow::bail!("Unknown playlist entry length `{length}`, use `ms`, `s` or `x` suffix");
+    };
+    anyhow::ensure!(
+        !length.is_empty(),
+        "Playlist entry length should not be zero"
+    );
+
+    Ok(PlaylistEntry {
+        image_id: ImageId(image_id.parse()?),
+        length,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use cyberpixie_network::core::proto::types::ImageEncoding;
+
+    use super::{
+        decode_palette, decode_rle, encode_image, encode_palette, encode_rle, extract_white,
+        mix_white,
+    };
+
+    /// Raw image with runs of the same pixels, which RLE compresses well.
+    fn striped_image() -> Vec<u8> {
+        (0..24 * 10)
+            .flat_map(|i| {
+                let color = (i / 7 % 3) as u8 * 100;
+                [color, 255 - color, 42]
+            })
+            .collect()
+    }
+
+    /// Raw image without repeated neighbouring pixels.
+    fn noisy_image() -> Vec<u8> {
+        (0..24 * 10_u32)
+            .flat_map(|i| i.to_le_bytes()[..3].to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_rle_round_trip() {
+        for raw in [striped_image(), noisy_image(), Vec::new()] {
+            let encoded = encode_rle(&raw);
+            assert_eq!(decode_rle(&encoded).unwrap(), raw);
+        }
+        // Truncated runs are not valid.
+        let encoded = encode_rle(&striped_image());
+        assert!(decode_rle(&encoded[..encoded.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_encode_image_chooses_rle() {
+        let raw = striped_image();
+        let (encoding, encoded) = encode_image(raw.clone(), false, true);
+        assert_eq!(encoding, ImageEncoding::Rle);
+        assert!(encoded.len() < raw.len());
+        assert_eq!(decode_rle(&encoded).unwrap(), raw);
+
+        // RLE is not used if it doesn't make the image smaller or is not supported.
+        let raw = noisy_image();
+        assert_eq!(
+            encode_image(raw.clone(), false, true),
+            (ImageEncoding::Raw, raw.clone())
+        );
+        let raw = striped_image();
+        assert_eq!(
+            encode_image(raw.clone(), false, false),
+            (ImageEncoding::Raw, raw)
+        );
+    }
+
+    #[test]
+    fn test_palette_round_trip() {
+        // Images with up to 256 colors keep their exact colors.
+        for raw in [striped_image(), noisy_image()] {
+            let encoded = encode_palette(&raw);
+            assert_eq!(decode_palette(&encoded).unwrap(), raw);
+        }
+
+        let (encoding, encoded) = encode_image(striped_image(), true, true);
+        assert_eq!(encoding, ImageEncoding::Palette);
+        assert_eq!(decode_palette(&encoded).unwrap(), striped_image());
+    }
+
+    #[test]
+    fn test_palette_quantization() {
+        // A gradient with 1024 different colors.
+        let raw: Vec<u8> = (0..1024_u32)
+            .flat_map(|i| [(i % 256) as u8, (i / 4) as u8, 128])
+            .collect();
+        let encoded = encode_palette(&raw);
+        // Each pixel is stored as a single byte index after the palette block.
+        assert!(encoded.len() > 1024 && encoded.len() < raw.len());
+
+        let decoded = decode_palette(&encoded).unwrap();
+        assert_eq!(decoded.len(), raw.len());
+        let total_error: u32 = raw
+            .iter()
+            .zip(&decoded)
+            .map(|(&expected, &actual)| u32::from(expected.abs_diff(actual)))
+            .sum();
+        let mean_error = total_error / raw.len() as u32;
+        assert!(mean_error < 16, "Mean color error {mean_error} is too big");
+    }
+
+    #[test]
+    fn test_white_round_trip() {
+        let raw = [10, 20, 30, 255, 255, 255, 0, 0, 0, 200, 100, 100];
+        let rgbw = extract_white(&raw);
+        assert_eq!(
+            rgbw,
+            [0, 10, 20, 10, 0, 0, 0, 255, 0, 0, 0, 0, 100, 0, 0, 100]
+        );
+        assert_eq!(mix_white(&rgbw), raw);
+
+        for raw in [striped_image(), noisy_image()] {
+            assert_eq!(mix_white(&extract_white(&raw)), raw);
+        }
+    }
+}
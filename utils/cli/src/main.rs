@@ -1,13 +1,27 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
-use clap::{CommandFactory, Parser, Subcommand};
-use cyberpixie_cli::convert_image_to_raw;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use cyberpixie_cli::{
+    convert_image_to_raw, decode_palette, decode_rle, encode_image, extract_white, mix_white,
+    parse_playlist_entry, save_raw_image,
+};
 use cyberpixie_network::{
-    core::proto::types::{Hertz, ImageId},
-    tokio::TokioStack,
-    Client, NetworkStack, SocketAddr,
+    core::proto::types::{
+        AuthKey, Capabilities, ColorOrder, DeviceName, DmxProtocol, EntryLength, Gamma, Hertz,
+        ImageEncoding, ImageId, ImageInfo, ImageName, MainDevice, PixelFormat, Playback,
+        PlaybackDirection, Playlist, PlaylistEntry, PlaylistId, AUTH_KEY_LEN, MAX_PLAYLIST_LEN,
+    },
+    discovery::{self, DiscoveredDevice, DEFAULT_DISCOVERY_PORT},
+    tokio::{TokioConnection, TokioStack},
+    Client, IpAddr, Ipv4Addr, NetworkSocket, NetworkStack, SocketAddr, DEFAULT_DEVICE_IP_ADDRESS,
+    DEFAULT_DEVICE_PORT,
 };
 
+/// Default address of the discovery request.
+const DEFAULT_BROADCAST_ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::BROADCAST);
+/// Default time to wait for the discovery replies in milliseconds.
+const DEFAULT_DISCOVERY_TIMEOUT: u64 = 1000;
+
 /// Cyberpixie device manipulation utility
 ///
 /// A command line application for interacting with the Cyberpixie device via WiFi connection
@@ -15,9 +29,16 @@ use cyberpixie_network::{
 #[command(author, version, about, long_about = None)]
 #[command(propagate_version = false)]
 struct Cli {
-    /// Device socket address
-    #[arg(short, long, default_value = "192.168.71.1:1800")]
-    address: String,
+    /// Device socket address, the default device address is used unless the device
+    /// is specified by its name
+    #[arg(short, long, conflicts_with = "device")]
+    address: Option<String>,
+    /// Name of the device, which is found by the discovery request
+    #[arg(long)]
+    device: Option<String>,
+    /// Pre-shared key to authenticate with, if the device requires it
+    #[arg(short, long, env = "CYBERPIXIE_KEY", hide_env_values = true)]
+    key: Option<String>,
     /// Actual command
     #[command(subcommand)]
     command: Command,
@@ -25,6 +46,15 @@ struct Cli {
 
 #[derive(Debug, Subcommand)]
 enum Command {
+    /// Find the devices in the local network
+    Discover {
+        /// Address to which the discovery request is sent
+        #[arg(short, long, default_value_t = DEFAULT_BROADCAST_ADDRESS)]
+        broadcast: IpAddr,
+        /// Time to wait for the device replies
+        #[arg(short, long, default_value_t = DEFAULT_DISCOVERY_TIMEOUT, value_name = "ms")]
+        timeout: u64,
+    },
     /// Get information about device firmware
     DeviceInfo,
     /// Add a new image to device memory
@@ -35,16 +65,145 @@ enum Command {
         /// Refresh rate of the single image line
         #[arg(short, long = "refresh-rate", default_value = "300", value_name = "Hz")]
         refresh_rate: Hertz,
+        /// Short image name
+        #[arg(short, long)]
+        name: Option<ImageName>,
+        /// Store image in the palette-indexed format, the image colors are quantized if needed
+        #[arg(short, long)]
+        palette: bool,
+        /// Extract the white channel from the image colors and store it as an RGBW image
+        #[arg(short, long, conflicts_with = "palette")]
+        white: bool,
+    },
+    /// Export an image from the device memory to the PNG file
+    ExportImage {
+        /// Image index
+        image_id: u16,
+        /// Output image path
+        #[arg(value_name = "FILE")]
+        path: PathBuf,
     },
     /// Show image
     Start {
         /// Image index
-        image_id: u16,
+        #[arg(required_unless_present = "name")]
+        image_id: Option<u16>,
+        /// Image name
+        #[arg(short, long, conflicts_with = "image_id")]
+        name: Option<String>,
+        /// Order in which the image lines are shown
+        #[arg(short, long, value_enum, default_value = "forward")]
+        direction: PlaybackDirectionArg,
+        /// The number of the image repetitions, the image is repeated endlessly by default
+        #[arg(short, long)]
+        repeat: Option<u16>,
     },
     /// Hide currently showing image
     Stop,
+    /// List images stored in the device memory
+    ListImages,
+    /// Delete a single image from the device memory
+    DeleteImage {
+        /// Image index
+        image_id: u16,
+    },
     /// Clear all images stored in the device memory
     ClearImages,
+    /// Get the device configuration
+    GetConfig,
+    /// Update the device configuration
+    ///
+    /// Changing the strip length removes all images stored in the device memory.
+    SetConfig {
+        /// The number of LEDs in the strip
+        #[arg(short, long)]
+        strip_len: Option<u16>,
+        /// Index of the image which will be shown by default
+        #[arg(short, long)]
+        current_image: Option<u16>,
+        /// Global strip brightness in range 0..=255
+        #[arg(short, long)]
+        brightness: Option<u8>,
+        /// Gamma correction exponent, e.g. 2.2
+        #[arg(short, long)]
+        gamma: Option<Gamma>,
+        /// Order of the strip color channels
+        #[arg(short = 'o', long, value_enum)]
+        color_order: Option<ColorOrderArg>,
+        /// Pixel format of the strip LEDs
+        #[arg(short, long, value_enum)]
+        pixel_format: Option<PixelFormatArg>,
+        /// Start showing the current image right after the device boot
+        #[arg(long)]
+        autoplay: Option<bool>,
+        /// Device name in the network, which can be used instead of its address
+        #[arg(short, long)]
+        name: Option<DeviceName>,
+        /// Show the DMX input while the device is idle, the input is ignored while
+        /// the device has a key
+        #[arg(long)]
+        dmx_input: Option<bool>,
+        /// DMX over UDP protocol of the input
+        #[arg(long, value_enum)]
+        dmx_protocol: Option<DmxProtocolArg>,
+        /// DMX universe which is shown on the strip
+        #[arg(long)]
+        dmx_universe: Option<u16>,
+        /// Accept connections of the secondary devices
+        #[arg(long)]
+        accept_secondaries: Option<bool>,
+        /// Socket address of the main device, which the device connects to as a secondary one
+        ///
+        /// The secondary device accepts the client connections only while it is unable
+        /// to connect to the main device.
+        #[arg(long, conflicts_with = "main")]
+        main_address: Option<SocketAddr>,
+        /// Group identifier of the secondary device
+        #[arg(long, requires = "main_address")]
+        group_id: Option<u32>,
+        /// Run the device as a main one instead of the secondary one
+        #[arg(long)]
+        main: bool,
+    },
+    /// Set the pre-shared key which the clients have to authenticate with, the key is sent
+    /// unencrypted
+    ///
+    /// Anyone who observes the network traffic learns the new key, so it should be set only
+    /// in a trusted network, e.g. while connected to the device access point alone. The key
+    /// protects the device from the unauthenticated requests, but the traffic of the
+    /// authenticated clients is not encrypted either.
+    SetKey {
+        /// New pre-shared key, the key is removed if it is not specified
+        new_key: Option<String>,
+    },
+    /// Add a new playlist to device memory
+    AddPlaylist {
+        /// Playlist entries in the `IMAGE_ID:LENGTH` format, the length is either a duration
+        /// like `500ms` and `5s` or a number of the image repetitions like `3x`
+        #[arg(required = true, value_parser = parse_playlist_entry)]
+        entries: Vec<PlaylistEntry>,
+    },
+    /// List playlists stored in the device memory
+    ListPlaylists,
+    /// Delete a single playlist from the device memory
+    DeletePlaylist {
+        /// Playlist index
+        playlist_id: u16,
+    },
+    /// Show images of the playlist one after another
+    StartPlaylist {
+        /// Playlist index
+        playlist_id: u16,
+    },
+    /// Stream image lines to the device, which shows them without saving
+    Stream {
+        /// Image path
+        #[arg(value_name = "FILE")]
+        path: PathBuf,
+        /// Refresh rate of the single image line
+        #[arg(short, long = "refresh-rate", default_value = "300", value_name = "Hz")]
+        refresh_rate: Hertz,
+    },
     /// Generate shell completions
     Completions {
         /// The shell to generate the completions for
@@ -53,15 +212,171 @@ enum Command {
     },
 }
 
+/// Order of the strip color channels
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ColorOrderArg {
+    Rgb,
+    Grb,
+    Bgr,
+}
+
+impl From<ColorOrderArg> for ColorOrder {
+    fn from(value: ColorOrderArg) -> Self {
+        match value {
+            ColorOrderArg::Rgb => Self::Rgb,
+            ColorOrderArg::Grb => Self::Grb,
+            ColorOrderArg::Bgr => Self::Bgr,
+        }
+    }
+}
+
+/// Pixel format of the strip LEDs
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum PixelFormatArg {
+    Rgb,
+    Rgbw,
+}
+
+impl From<PixelFormatArg> for PixelFormat {
+    fn from(value: PixelFormatArg) -> Self {
+        match value {
+            PixelFormatArg::Rgb => Self::Rgb,
+            PixelFormatArg::Rgbw => Self::Rgbw,
+        }
+    }
+}
+
+/// DMX over UDP protocol
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum DmxProtocolArg {
+    Artnet,
+    E131,
+}
+
+impl From<DmxProtocolArg> for DmxProtocol {
+    fn from(value: DmxProtocolArg) -> Self {
+        match value {
+            DmxProtocolArg::Artnet => Self::ArtNet,
+            DmxProtocolArg::E131 => Self::E131,
+        }
+    }
+}
+
+/// Order in which the image lines are shown
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum PlaybackDirectionArg {
+    Forward,
+    Reverse,
+    PingPong,
+}
+
+impl From<PlaybackDirectionArg> for PlaybackDirection {
+    fn from(value: PlaybackDirectionArg) -> Self {
+        match value {
+            PlaybackDirectionArg::Forward => Self::Forward,
+            PlaybackDirectionArg::Reverse => Self::Reverse,
+            PlaybackDirectionArg::PingPong => Self::PingPong,
+        }
+    }
+}
+
+/// Prints results of the last request forwarded by the main device to its secondary devices.
+async fn print_secondary_results(client: &mut Client<TokioConnection>) -> anyhow::Result<()> {
+    if !client.capabilities().contains(Capabilities::SECONDARIES) {
+        return Ok(());
+    }
+
+    for (index, result) in client.secondary_results().await?.iter().enumerate() {
+        let group = result
+            .group_id
+            .map_or_else(|| "none".to_owned(), |group_id| group_id.to_string());
+        match result.error {
+            None => println!("Secondary device {index} (group {group}): ok"),
+            Some(err) => println!("Secondary device {index} (group {group}): {err}"),
+        }
+    }
+    Ok(())
+}
+
+/// Broadcasts the discovery request and collects the device replies until the timeout expires.
+async fn discover_devices(
+    broadcast: IpAddr,
+    timeout: Duration,
+) -> anyhow::Result<Vec<DiscoveredDevice>> {
+    let mut stack = TokioStack;
+    let mut socket = stack.socket();
+    let mut udp = socket.bind(0).await?;
+    discovery::send_request(&mut udp, SocketAddr::new(broadcast, DEFAULT_DISCOVERY_PORT)).await?;
+
+    let mut devices = Vec::new();
+    let deadline = tokio::time::Instant::now() + timeout;
+    while let Ok(device) =
+        tokio::time::timeout_at(deadline, discovery::receive_reply(&mut udp)).await
+    {
+        let device = device?;
+        // The same device may answer several times.
+        if !devices.contains(&device) {
+            devices.push(device);
+        }
+    }
+    Ok(devices)
+}
+
+/// Returns the address of the device specified either by its address or by its name.
+async fn device_address(
+    address: Option<String>,
+    name: Option<String>,
+) -> anyhow::Result<SocketAddr> {
+    if let Some(address) = address {
+        return address.parse().map_err(|err| anyhow::anyhow!("{err}"));
+    }
+    let Some(name) = name else {
+        return Ok(SocketAddr::new(
+            DEFAULT_DEVICE_IP_ADDRESS,
+            DEFAULT_DEVICE_PORT,
+        ));
+    };
+
+    log::info!("Looking for the device {name}");
+    let devices = discover_devices(
+        DEFAULT_BROADCAST_ADDRESS,
+        Duration::from_millis(DEFAULT_DISCOVERY_TIMEOUT),
+    )
+    .await?;
+    devices
+        .into_iter()
+        .find(|device| device.name.as_deref() == Some(name.as_str()))
+        .map(|device| device.address)
+        .ok_or_else(|| anyhow::anyhow!("Device {name} has not been found"))
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     env_logger::init();
 
     let cli = Cli::parse();
-    let address: SocketAddr = cli
-        .address
-        .parse()
-        .map_err(|err| anyhow::anyhow!("{err}"))?;
+    // These commands don't need a device connection.
+    match cli.command {
+        Command::Discover { broadcast, timeout } => {
+            let devices = discover_devices(broadcast, Duration::from_millis(timeout)).await?;
+            for device in devices {
+                let name = device.name.as_deref().unwrap_or("unnamed");
+                let info = device.peer_info;
+                println!(
+                    "{name}: {}, {:?}, protocol version {}",
+                    device.address, info.role, info.version
+                );
+            }
+            return Ok(());
+        }
+        Command::Completions { shell } => {
+            shell.generate(&mut Cli::command(), &mut std::io::stdout());
+            return Ok(());
+        }
+        _ => {}
+    }
+    let address = device_address(cli.address, cli.device).await?;
+    let key = cli.key.as_deref().map(str::as_bytes);
 
     let mut stack = TokioStack;
     // Allocate socket.
@@ -70,56 +385,344 @@ async fn main() -> anyhow::Result<()> {
         Command::DeviceInfo => {
             log::info!("Sending firmware info request to {}", address);
 
-            let peer_info = Client::connect(&mut socket, address)
-                .await?
-                .peer_info()
-                .await?;
-            // TODO replace by the full firmware info.
+            let mut client = Client::connect_with_key(&mut socket, address, key).await?;
+            let peer_info = client.peer_info().await?;
             log::info!("Got {:#?} from the {}", peer_info, address);
+            let firmware_info = client.firmware_info().await?;
+
+            println!("Firmware version: {}", firmware_info.version);
+            println!("Board: {}", firmware_info.board_name);
+            println!("Build: {}", firmware_info.build_id);
+            println!("Max strip length: {}", firmware_info.max_strip_len);
+            println!("Max refresh rate: {}Hz", firmware_info.max_refresh_rate);
+            println!("Storage capacity: {} bytes", firmware_info.storage_capacity);
+            if let Some(device_info) = peer_info.device_info {
+                println!("Strip length: {}", device_info.strip_len);
+                println!("Images count: {}", device_info.images_count);
+                if let Some(position) = device_info.playlist {
+                    println!(
+                        "Playlist: {}, entry {}",
+                        position.playlist_id, position.entry
+                    );
+                }
+            }
         }
 
-        Command::AddImage { path, refresh_rate } => {
+        Command::AddImage {
+            path,
+            refresh_rate,
+            name,
+            palette,
+            white,
+        } => {
             let (strip_len, raw) = convert_image_to_raw(&path)?;
 
             log::info!("Sending image {:?}[{}] to {}", path, strip_len, address);
-            let index = Client::connect(&mut socket, address)
-                .await?
-                .add_image(refresh_rate, strip_len as u16, &raw)
-                .await?;
+            let mut client = Client::connect_with_key(&mut socket, address, key).await?;
+            let (pixel_format, encoding, bytes) = if white {
+                // Encoded images support only RGB pixels.
+                (PixelFormat::Rgbw, ImageEncoding::Raw, extract_white(&raw))
+            } else {
+                let rle_supported = client.capabilities().contains(Capabilities::RLE_IMAGES);
+                let (encoding, bytes) = encode_image(raw, palette, rle_supported);
+                (PixelFormat::Rgb, encoding, bytes)
+            };
+
+            let info = ImageInfo {
+                name,
+                encoding,
+                pixel_format,
+                ..ImageInfo::new(refresh_rate, strip_len as u16)
+            };
+            let index = client.add_image_with_info(info, &bytes).await?;
             log::info!(
                 "Image loaded into the device {} with index {}",
                 address,
                 index
             );
+            print_secondary_results(&mut client).await?;
         }
 
-        Command::Start { image_id } => {
-            log::info!("Sending show image command to {address}");
-            Client::connect(&mut socket, address)
+        Command::ExportImage { image_id, path } => {
+            log::info!("Sending read image command to {address}");
+            let mut raw = Vec::new();
+            let info = Client::connect_with_key(&mut socket, address, key)
                 .await?
-                .start(ImageId(image_id))
+                .read_image(ImageId(image_id), &mut raw)
                 .await?;
+            raw = match info.encoding {
+                ImageEncoding::Raw => raw,
+                ImageEncoding::Rle => decode_rle(&raw)?,
+                ImageEncoding::Palette => decode_palette(&raw)?,
+            };
+            if info.pixel_format == PixelFormat::Rgbw {
+                raw = mix_white(&raw);
+            }
+            save_raw_image(&path, info.strip_len.into(), raw)?;
+            log::info!(
+                "Image {image_id} with refresh rate {}Hz exported to {path:?}",
+                info.refresh_rate
+            );
+        }
+
+        Command::Start {
+            image_id,
+            name,
+            direction,
+            repeat,
+        } => {
+            log::info!("Sending show image command to {address}");
+            let mut client = Client::connect_with_key(&mut socket, address, key).await?;
+            let image_id = match (image_id, name) {
+                (Some(image_id), _) => ImageId(image_id),
+                (None, Some(name)) => client.find_image(&name).await?,
+                (None, None) => unreachable!("Image ID or name should be specified"),
+            };
+            let playback = Playback {
+                direction: direction.into(),
+                repeat,
+            };
+            client.start_with_playback(image_id, playback).await?;
             log::info!("Showing image with id {image_id}");
+            print_secondary_results(&mut client).await?;
         }
 
         Command::Stop => {
             log::info!("Sending hide image command to {address}");
-            Client::connect(&mut socket, address).await?.stop().await?;
+            let mut client = Client::connect_with_key(&mut socket, address, key).await?;
+            client.stop().await?;
             log::info!("Hide a currently showing image");
+            print_secondary_results(&mut client).await?;
+        }
+
+        Command::ListImages => {
+            log::info!("Sending list images command to {address}");
+            let mut images = Vec::new();
+            Client::connect_with_key(&mut socket, address, key)
+                .await?
+                .list_images(&mut images)
+                .await?;
+
+            for (id, image) in images.iter().enumerate() {
+                println!(
+                    "{id}: {} bytes, {} lines, {}Hz, {:?}, {:?}",
+                    image.len, image.lines, image.refresh_rate, image.encoding, image.pixel_format
+                );
+            }
+        }
+
+        Command::DeleteImage { image_id } => {
+            log::info!("Sending delete image command to {address}");
+            Client::connect_with_key(&mut socket, address, key)
+                .await?
+                .delete_image(ImageId(image_id))
+                .await?;
+            log::info!("Deleted image with id {image_id}");
         }
 
         Command::ClearImages => {
             log::info!("Sending clear images command to {address}");
 
-            Client::connect(&mut socket, address)
+            let mut client = Client::connect_with_key(&mut socket, address, key).await?;
+            client.clear_images().await?;
+            log::trace!("Sent images clear command to {address}");
+            print_secondary_results(&mut client).await?;
+        }
+
+        Command::GetConfig => {
+            log::info!("Sending get config command to {address}");
+            let config = Client::connect_with_key(&mut socket, address, key)
                 .await?
-                .clear_images()
+                .config()
                 .await?;
-            log::trace!("Sent images clear command to {address}");
+
+            println!("Strip length: {}", config.strip_len);
+            if let Some(current_image) = config.current_image {
+                println!("Current image: {current_image}");
+            }
+            println!("Brightness: {}", config.brightness);
+            println!("Gamma: {}", config.gamma);
+            println!("Color order: {:?}", config.color_order);
+            println!("Pixel format: {:?}", config.pixel_format);
+            println!("Autoplay: {}", config.autoplay);
+            if let Some(name) = config.name {
+                println!("Name: {name}");
+            }
+            println!("DMX input: {}", config.dmx_input);
+            println!("DMX protocol: {:?}", config.dmx_protocol);
+            println!("DMX universe: {}", config.dmx_universe);
+            println!("Accept secondaries: {}", config.accept_secondaries);
+            if let Some(main_device) = config.main_device {
+                println!("Main device: {}", main_device.address());
+                if let Some(group_id) = main_device.group_id {
+                    println!("Group: {group_id}");
+                }
+            }
         }
 
-        Command::Completions { shell } => {
-            shell.generate(&mut Cli::command(), &mut std::io::stdout());
+        Command::SetConfig {
+            strip_len,
+            current_image,
+            brightness,
+            gamma,
+            color_order,
+            pixel_format,
+            autoplay,
+            name,
+            dmx_input,
+            dmx_protocol,
+            dmx_universe,
+            accept_secondaries,
+            main_address,
+            group_id,
+            main,
+        } => {
+            log::info!("Sending set config command to {address}");
+            let mut client = Client::connect_with_key(&mut socket, address, key).await?;
+            let mut config = client.config().await?;
+            if let Some(strip_len) = strip_len {
+                config.strip_len = strip_len;
+            }
+            if let Some(current_image) = current_image {
+                config.current_image = Some(ImageId(current_image));
+            }
+            if let Some(brightness) = brightness {
+                config.brightness = brightness;
+            }
+            if let Some(gamma) = gamma {
+                config.gamma = gamma;
+            }
+            if let Some(color_order) = color_order {
+                config.color_order = color_order.into();
+            }
+            if let Some(pixel_format) = pixel_format {
+                config.pixel_format = pixel_format.into();
+            }
+            if let Some(autoplay) = autoplay {
+                config.autoplay = autoplay;
+            }
+            if name.is_some() {
+                config.name = name;
+            }
+            if let Some(dmx_input) = dmx_input {
+                config.dmx_input = dmx_input;
+            }
+            if let Some(dmx_protocol) = dmx_protocol {
+                config.dmx_protocol = dmx_protocol.into();
+            }
+            if let Some(dmx_universe) = dmx_universe {
+                config.dmx_universe = dmx_universe;
+            }
+            if let Some(accept_secondaries) = accept_secondaries {
+                config.accept_secondaries = accept_secondaries;
+            }
+            if let Some(main_address) = main_address {
+                config.main_device = Some(MainDevice::new(main_address, group_id));
+            }
+            if main {
+                config.main_device = None;
+            }
+            client.set_config(config.clone()).await?;
+            log::info!("Device configuration updated to {config:?}");
+        }
+
+        Command::SetKey { new_key } => {
+            log::info!("Sending set key command to {address}");
+            let new_key = new_key
+                .map(|new_key| {
+                    AuthKey::from_slice(new_key.as_bytes()).map_err(|()| {
+                        anyhow::anyhow!("The key is longer than {AUTH_KEY_LEN} bytes")
+                    })
+                })
+                .transpose()?;
+            let removed = new_key.is_none();
+            Client::connect_with_key(&mut socket, address, key)
+                .await?
+                .set_auth_key(new_key)
+                .await?;
+            if removed {
+                log::info!("Device key has been removed");
+            } else {
+                log::info!("Device key has been updated");
+            }
+        }
+
+        Command::AddPlaylist { entries } => {
+            log::info!("Sending add playlist command to {address}");
+            anyhow::ensure!(
+                entries.len() <= MAX_PLAYLIST_LEN,
+                "Playlist should have at most {MAX_PLAYLIST_LEN} entries"
+            );
+            let playlist = Playlist {
+                entries: entries.into_iter().collect(),
+            };
+            let playlist_id = Client::connect_with_key(&mut socket, address, key)
+                .await?
+                .add_playlist(&playlist)
+                .await?;
+            log::info!("Playlist added with index {playlist_id}");
+        }
+
+        Command::ListPlaylists => {
+            log::info!("Sending list playlists command to {address}");
+            let mut playlists = Vec::new();
+            Client::connect_with_key(&mut socket, address, key)
+                .await?
+                .list_playlists(&mut playlists)
+                .await?;
+
+            for (id, playlist) in playlists.iter().enumerate() {
+                let entries = playlist
+                    .entries
+                    .iter()
+                    .map(|entry| match entry.length {
+                        EntryLength::Millis(millis) => format!("{}:{millis}ms", entry.image_id),
+                        EntryLength::Loops(loops) => format!("{}:{loops}x", entry.image_id),
+                    })
+                    .collect::<Vec<_>>();
+                println!("{id}: {}", entries.join(" "));
+            }
+        }
+
+        Command::DeletePlaylist { playlist_id } => {
+            log::info!("Sending delete playlist command to {address}");
+            Client::connect_with_key(&mut socket, address, key)
+                .await?
+                .delete_playlist(PlaylistId(playlist_id))
+                .await?;
+            log::info!("Deleted playlist with id {playlist_id}");
+        }
+
+        Command::StartPlaylist { playlist_id } => {
+            log::info!("Sending start playlist command to {address}");
+            Client::connect_with_key(&mut socket, address, key)
+                .await?
+                .start_playlist(PlaylistId(playlist_id))
+                .await?;
+            log::info!("Showing playlist with id {playlist_id}");
+        }
+
+        Command::Stream { path, refresh_rate } => {
+            let (strip_len, raw) = convert_image_to_raw(&path)?;
+
+            log::info!("Streaming image {path:?}[{strip_len}] to {address}");
+            let mut client = Client::connect_with_key(&mut socket, address, key).await?;
+            let config = client.config().await?;
+            anyhow::ensure!(
+                usize::from(config.strip_len) == strip_len,
+                "Image width {strip_len} differs from the device strip length {}",
+                config.strip_len
+            );
+            let lines = match config.pixel_format {
+                PixelFormat::Rgb => raw,
+                PixelFormat::Rgbw => extract_white(&raw),
+            };
+            client.stream(refresh_rate, &lines).await?;
+            log::info!("Image {path:?} has been streamed to {address}");
+        }
+
+        Command::Discover { .. } | Command::Completions { .. } => {
+            unreachable!("The command has been already handled")
         }
     }
 